@@ -0,0 +1,158 @@
+use hmac::{Hmac, Mac};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::Sha256;
+use std::{
+    collections::VecDeque,
+    fmt::Write,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+pub const DEFAULT_CAPACITY: usize = 1000;
+
+type HmacSha256 = Hmac<Sha256>;
+
+// One executed tool call, as exposed at `/audit`.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AuditEntry {
+    pub seq: u64,
+    // Milliseconds since the Unix epoch.
+    pub timestamp: u64,
+    pub session_id: Option<String>,
+    pub tool: String,
+    pub arguments: Value,
+    pub is_error: bool,
+    // Hex HMAC-SHA256 of the entry's canonical JSON, when a key is configured.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+impl AuditEntry {
+    // The entry without its signature, serialized with sorted keys
+    // (serde_json maps are ordered), so a verifier can rebuild it exactly.
+    pub fn canonical_json(&self) -> String {
+        let mut value = serde_json::to_value(self).unwrap_or_default();
+        if let Value::Object(fields) = &mut value {
+            fields.remove("signature");
+        }
+        value.to_string()
+    }
+
+    // What an external verifier does: recompute the HMAC and compare.
+    #[cfg(test)]
+    pub fn verify(&self, key: &[u8]) -> bool {
+        self.signature.as_deref() == Some(sign(key, &self.canonical_json()).as_str())
+    }
+}
+
+fn sign(key: &[u8], message: &str) -> String {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(message.as_bytes());
+    mac.finalize().into_bytes().iter().fold(String::new(), |mut hex, byte| {
+        let _ = write!(hex, "{:02x}", byte);
+        hex
+    })
+}
+
+// The most recent tool calls, oldest dropped first once `capacity` is
+// reached. With an HMAC key every entry is signed as it is recorded.
+#[derive(Debug)]
+pub struct AuditLog {
+    capacity: usize,
+    hmac_key: Option<Vec<u8>>,
+    inner: Mutex<Entries>,
+}
+
+#[derive(Debug, Default)]
+struct Entries {
+    entries: VecDeque<AuditEntry>,
+    next_seq: u64,
+}
+
+impl AuditLog {
+    pub fn new(capacity: usize, hmac_key: Option<Vec<u8>>) -> Self {
+        Self {
+            capacity,
+            hmac_key,
+            inner: Mutex::new(Entries::default()),
+        }
+    }
+
+    pub fn record(&self, session_id: Option<String>, tool: &str, arguments: Value, is_error: bool) {
+        if self.capacity == 0 {
+            return;
+        }
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+
+        let mut inner = self.inner.lock().unwrap();
+        inner.next_seq += 1;
+        let mut entry = AuditEntry {
+            seq: inner.next_seq,
+            timestamp,
+            session_id,
+            tool: tool.to_string(),
+            arguments,
+            is_error,
+            signature: None,
+        };
+        if let Some(key) = &self.hmac_key {
+            entry.signature = Some(sign(key, &entry.canonical_json()));
+        }
+        if inner.entries.len() >= self.capacity {
+            inner.entries.pop_front();
+        }
+        inner.entries.push_back(entry);
+    }
+
+    // Drops every entry and returns how many there were. Sequence numbers
+    // carry on from where they were, so a reset shows up as a gap.
+    pub fn clear(&self) -> usize {
+        let mut inner = self.inner.lock().unwrap();
+        let cleared = inner.entries.len();
+        inner.entries.clear();
+        cleared
+    }
+
+    pub fn entries(&self) -> Vec<AuditEntry> {
+        self.inner.lock().unwrap().entries.iter().cloned().collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_signature_verifies_only_with_the_signing_key() {
+        let log = AuditLog::new(10, Some(b"audit-secret".to_vec()));
+        log.record(Some("session-1".to_string()), "add", json!({ "a": 1, "b": 2 }), false);
+
+        let entry = log.entries().remove(0);
+        assert_eq!(entry.signature.as_ref().map(String::len), Some(64));
+        assert!(entry.verify(b"audit-secret"));
+        assert!(!entry.verify(b"wrong-secret"));
+
+        let mut tampered = entry.clone();
+        tampered.arguments = json!({ "a": 1, "b": 3 });
+        assert!(!tampered.verify(b"audit-secret"));
+    }
+
+    #[test]
+    fn test_keeps_most_recent_entries() {
+        let log = AuditLog::new(2, None);
+        for tool in ["add", "square", "sqrt"] {
+            log.record(None, tool, json!({}), false);
+        }
+
+        let entries = log.entries();
+        assert_eq!(entries.iter().map(|e| e.tool.as_str()).collect::<Vec<_>>(), ["square", "sqrt"]);
+        assert_eq!(entries[1].seq, 3);
+        assert_eq!(entries[1].signature, None);
+    }
+}
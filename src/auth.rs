@@ -0,0 +1,173 @@
+use async_trait::async_trait;
+use axum::{
+    extract::{Request, State},
+    http::{header::AUTHORIZATION, HeaderMap, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use crate::error::ErrorCode;
+use serde_json::json;
+use std::{fmt, sync::Arc};
+
+// Identity established by an `Authenticator`, attached to the request's
+// extensions for downstream handlers.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Principal {
+    pub id: String,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthError {
+    MissingCredentials,
+    InvalidCredentials,
+}
+
+impl fmt::Display for AuthError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            AuthError::MissingCredentials => write!(f, "missing credentials"),
+            AuthError::InvalidCredentials => write!(f, "invalid credentials"),
+        }
+    }
+}
+
+// Pluggable authentication scheme. Deployments needing JWT, mTLS or token
+// introspection implement this and hand it to `app_with_authenticator`.
+#[async_trait]
+pub trait Authenticator: Send + Sync {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<Principal, AuthError>;
+}
+
+// Default scheme: a shared key in the `X-API-Key` header.
+pub struct ApiKeyAuthenticator {
+    key: String,
+}
+
+impl ApiKeyAuthenticator {
+    pub fn new(key: impl Into<String>) -> Self {
+        Self { key: key.into() }
+    }
+}
+
+#[async_trait]
+impl Authenticator for ApiKeyAuthenticator {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<Principal, AuthError> {
+        let presented = headers
+            .get("x-api-key")
+            .ok_or(AuthError::MissingCredentials)?
+            .as_bytes();
+        if constant_time_eq(presented, self.key.as_bytes()) {
+            Ok(Principal {
+                id: "api-key".to_string(),
+            })
+        } else {
+            Err(AuthError::InvalidCredentials)
+        }
+    }
+}
+
+// Requires `Authorization: Bearer <token>` matching a shared token.
+pub struct BearerTokenAuthenticator {
+    token: String,
+}
+
+impl BearerTokenAuthenticator {
+    pub fn new(token: impl Into<String>) -> Self {
+        Self { token: token.into() }
+    }
+}
+
+#[async_trait]
+impl Authenticator for BearerTokenAuthenticator {
+    async fn authenticate(&self, headers: &HeaderMap) -> Result<Principal, AuthError> {
+        let value = headers
+            .get(AUTHORIZATION)
+            .ok_or(AuthError::MissingCredentials)?
+            .as_bytes();
+        let presented = value
+            .strip_prefix(b"Bearer ")
+            .ok_or(AuthError::InvalidCredentials)?;
+        if constant_time_eq(presented, self.token.as_bytes()) {
+            Ok(Principal {
+                id: "bearer-token".to_string(),
+            })
+        } else {
+            Err(AuthError::InvalidCredentials)
+        }
+    }
+}
+
+// Compares without short-circuiting so response timing doesn't leak how many
+// leading bytes of a guess were right.
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+pub async fn require_auth(
+    State(authenticator): State<Arc<dyn Authenticator>>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    match authenticator.authenticate(request.headers()).await {
+        Ok(principal) => {
+            request.extensions_mut().insert(principal);
+            next.run(request).await
+        }
+        Err(e) => {
+            tracing::warn!("Rejected request to {}: {}", request.uri().path(), e);
+            let body = json!({
+                "jsonrpc": "2.0",
+                "id": null,
+                "error": {
+                    "code": ErrorCode::Unauthorized as i32,
+                    "message": format!("Unauthorized: {}", e)
+                }
+            });
+            (StatusCode::UNAUTHORIZED, Json(body)).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_api_key_authenticator() {
+        let auth = ApiKeyAuthenticator::new("secret");
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(auth.authenticate(&headers).await, Err(AuthError::MissingCredentials));
+
+        headers.insert("x-api-key", "guess".parse().unwrap());
+        assert_eq!(auth.authenticate(&headers).await, Err(AuthError::InvalidCredentials));
+
+        headers.insert("x-api-key", "secret".parse().unwrap());
+        assert!(auth.authenticate(&headers).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_authenticator() {
+        let auth = BearerTokenAuthenticator::new("s3cret");
+
+        let mut headers = HeaderMap::new();
+        assert_eq!(auth.authenticate(&headers).await, Err(AuthError::MissingCredentials));
+
+        headers.insert(AUTHORIZATION, "Basic s3cret".parse().unwrap());
+        assert_eq!(auth.authenticate(&headers).await, Err(AuthError::InvalidCredentials));
+
+        headers.insert(AUTHORIZATION, "Bearer s3cret".parse().unwrap());
+        assert!(auth.authenticate(&headers).await.is_ok());
+    }
+
+    #[test]
+    fn test_constant_time_eq() {
+        assert!(constant_time_eq(b"token", b"token"));
+        assert!(!constant_time_eq(b"token", b"tokem"));
+        assert!(!constant_time_eq(b"token", b"token2"));
+    }
+}
@@ -0,0 +1,122 @@
+use serde_json::Value;
+use std::{
+    collections::{hash_map::Entry, HashMap},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+use tokio::sync::Notify;
+
+// Set when the client cancels a request. Synchronous tool bodies poll
+// `is_cancelled`; async waiters use `cancelled`.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<Inner>);
+
+#[derive(Debug, Default)]
+struct Inner {
+    cancelled: AtomicBool,
+    notify: Notify,
+}
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.cancelled.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.cancelled.load(Ordering::SeqCst)
+    }
+
+    pub async fn cancelled(&self) {
+        loop {
+            // Registering before checking the flag means a `cancel` racing
+            // with this call can't be missed.
+            let notified = self.0.notify.notified();
+            if self.is_cancelled() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+// Tokens of in-flight requests, keyed by session and request id since ids
+// are only unique within one client's session. Callers without a session
+// share no namespace anyone could safely cancel in, so they aren't tracked.
+#[derive(Debug, Default)]
+pub struct CancellationRegistry {
+    tokens: Mutex<HashMap<String, CancellationToken>>,
+}
+
+fn key(session_id: &str, request_id: &Value) -> String {
+    format!("{}:{}", session_id, request_id)
+}
+
+impl CancellationRegistry {
+    // A token for the request, or `None` when the session already has a
+    // request with this id in flight; the first one keeps its token.
+    pub fn register(&self, session_id: &str, request_id: &Value) -> Option<CancellationToken> {
+        let mut tokens = self.tokens.lock().unwrap();
+        match tokens.entry(key(session_id, request_id)) {
+            Entry::Occupied(_) => None,
+            Entry::Vacant(entry) => Some(entry.insert(CancellationToken::default()).clone()),
+        }
+    }
+
+    pub fn get(&self, session_id: &str, request_id: &Value) -> Option<CancellationToken> {
+        self.tokens.lock().unwrap().get(&key(session_id, request_id)).cloned()
+    }
+
+    // Cancels the request if it is still running; returns whether it was.
+    pub fn cancel(&self, session_id: &str, request_id: &Value) -> bool {
+        match self.get(session_id, request_id) {
+            Some(token) => {
+                token.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    pub fn remove(&self, session_id: &str, request_id: &Value) {
+        self.tokens.lock().unwrap().remove(&key(session_id, request_id));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_cancel_wakes_waiters() {
+        let registry = CancellationRegistry::default();
+        let token = registry.register("session-1", &json!(7)).unwrap();
+        let waiter = tokio::spawn({
+            let token = token.clone();
+            async move { token.cancelled().await }
+        });
+
+        assert!(!registry.cancel("session-2", &json!(7)));
+        assert!(registry.cancel("session-1", &json!(7)));
+        assert!(token.is_cancelled());
+        tokio::time::timeout(Duration::from_secs(1), waiter).await.unwrap().unwrap();
+
+        registry.remove("session-1", &json!(7));
+        assert!(registry.get("session-1", &json!(7)).is_none());
+    }
+
+    #[test]
+    fn test_duplicate_registration_is_refused() {
+        let registry = CancellationRegistry::default();
+        let first = registry.register("session-1", &json!(7)).unwrap();
+        assert!(registry.register("session-1", &json!(7)).is_none());
+        assert!(registry.register("session-2", &json!(7)).is_some());
+
+        registry.cancel("session-1", &json!(7));
+        assert!(first.is_cancelled());
+    }
+}
@@ -0,0 +1,65 @@
+use crate::config;
+use clap::Parser;
+use std::net::IpAddr;
+
+// Command-line flags. Each one overrides the MCP_* variable of the same
+// setting, so local runs don't need to export anything.
+#[derive(Debug, Parser)]
+#[command(version, about = "Calculator MCP server over HTTP, SSE and WebSocket")]
+pub struct Cli {
+    /// Address to listen on (MCP_HOST); IPv6 literals may be bracketed.
+    #[arg(long, value_parser = parse_host)]
+    pub host: Option<IpAddr>,
+    /// Port to listen on (MCP_PORT).
+    #[arg(long)]
+    pub port: Option<u16>,
+    /// Initial log level (MCP_LOG_LEVEL).
+    #[arg(long, value_parser = ["off", "error", "warn", "info", "debug", "trace"])]
+    pub log_level: Option<String>,
+    /// Comma-separated tools to expose, e.g. add,multiply (MCP_ENABLED_TOOLS).
+    #[arg(long)]
+    pub enabled_tools: Option<String>,
+    /// Check a running server against a recording made with MCP_RECORD_PATH
+    /// instead of starting one; URL defaults to http://127.0.0.1:3000/mcp.
+    #[arg(long, num_args = 1..=2, value_names = ["FILE", "URL"])]
+    pub replay: Option<Vec<String>>,
+}
+
+impl Cli {
+    // The flag standing in for environment variable `key`, if it was given.
+    pub fn var(&self, key: &str) -> Option<String> {
+        match key {
+            "MCP_HOST" => self.host.map(|ip| ip.to_string()),
+            "MCP_PORT" => self.port.map(|port| port.to_string()),
+            "MCP_LOG_LEVEL" => self.log_level.clone(),
+            "MCP_ENABLED_TOOLS" => self.enabled_tools.clone(),
+            _ => None,
+        }
+    }
+}
+
+// Unlike MCP_HOST, which falls back to the default with a warning, a bad
+// `--host` stops the server before it starts.
+fn parse_host(raw: &str) -> Result<IpAddr, String> {
+    config::parse_ip(raw).ok_or_else(|| format!("{:?} is not an IPv4 or IPv6 address", raw))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_flags_map_to_variables() {
+        let cli = Cli::parse_from(["mcp-sse-rust", "--port", "4000", "--enabled-tools", "add,sqrt"]);
+        assert_eq!(cli.var("MCP_PORT").as_deref(), Some("4000"));
+        assert_eq!(cli.var("MCP_ENABLED_TOOLS").as_deref(), Some("add,sqrt"));
+        assert_eq!(cli.var("MCP_HOST"), None);
+
+        assert!(Cli::try_parse_from(["mcp-sse-rust", "--log-level", "loud"]).is_err());
+        assert!(Cli::try_parse_from(["mcp-sse-rust", "--host", "not-an-ip"]).is_err());
+        let cli = Cli::parse_from(["mcp-sse-rust", "--host", "[::1]"]);
+        assert_eq!(cli.var("MCP_HOST").as_deref(), Some("::1"));
+        let cli = Cli::parse_from(["mcp-sse-rust", "--replay", "session.jsonl"]);
+        assert_eq!(cli.replay, Some(vec!["session.jsonl".to_string()]));
+    }
+}
@@ -0,0 +1,522 @@
+use crate::{cli::Cli, logging::LogFormat, numeric::DivZeroPolicy};
+use axum::http::HeaderValue;
+use std::{
+    env,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
+    path::PathBuf,
+    str::FromStr,
+    time::Duration,
+};
+use tracing::level_filters::LevelFilter;
+
+// Groups of defaults chosen with MCP_PROFILE; individual MCP_* variables
+// still override them.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Profile {
+    Dev,
+    Prod,
+}
+
+impl FromStr for Profile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "dev" | "development" => Ok(Profile::Dev),
+            "prod" | "production" => Ok(Profile::Prod),
+            other => Err(format!("unknown profile {:?}", other)),
+        }
+    }
+}
+
+// Runtime configuration, resolved from MCP_* environment variables.
+#[derive(Debug, Clone)]
+pub struct Config {
+    /// Address the server listens on, from MCP_HOST and MCP_PORT.
+    pub bind_address: SocketAddr,
+    /// `serverInfo.name` reported by `initialize`.
+    pub server_name: String,
+    /// Guidance for the model returned as `instructions` by `initialize`; omitted when unset.
+    pub instructions: Option<String>,
+    /// Maximum number of tool calls running on the blocking pool at once.
+    pub blocking_pool_size: usize,
+    /// Shared key required in `X-API-Key` on `/mcp` and `/sse`; auth is off when unset.
+    pub api_key: Option<String>,
+    /// Token required as `Authorization: Bearer <token>`; takes precedence over `api_key`.
+    pub auth_token: Option<String>,
+    /// Requests still running after this long are answered with 408.
+    pub request_timeout: Duration,
+    /// Longest numeric string accepted where a tool expects a number.
+    pub max_number_string_len: usize,
+    /// Origins allowed by CORS; `None` allows any origin, an empty list none.
+    pub cors_origins: Option<Vec<HeaderValue>>,
+    /// How long results stored via `_meta.asResource` stay readable.
+    pub resource_ttl: Duration,
+    /// Number of tools per `tools/list` page.
+    pub tools_page_size: usize,
+    /// List tools alphabetically in `tools/list` instead of in registration order.
+    pub sort_tools: bool,
+    /// JSON file declaring extra expression-backed tools, loaded at startup.
+    pub tools_file: Option<PathBuf>,
+    /// Result of `divide` and `modulo` by zero: an error, `null`, or "Infinity".
+    pub divzero_policy: DivZeroPolicy,
+    /// Initial log level; SIGHUP cycles it at runtime on Unix.
+    pub log_level: LevelFilter,
+    /// Number of parsed expressions kept by the `evaluate` tool's AST cache.
+    pub expr_cache_size: usize,
+    /// Most expression nodes one `evaluate` call may visit.
+    pub expr_max_steps: usize,
+    /// Deepest nesting of parentheses, calls and operators an expression may have.
+    pub expr_max_depth: usize,
+    /// Interval between SSE keep-alive comments.
+    pub sse_keepalive: Duration,
+    /// Text of the SSE keep-alive comment; axum's default when unset.
+    pub sse_keepalive_text: Option<String>,
+    /// Largest SSE event payload sent inline; bigger ones are stored as a resource.
+    pub sse_max_event_bytes: usize,
+    /// Number of built-in tool results kept for repeated calls; 0 disables the cache.
+    pub result_cache_size: usize,
+    /// Reject session requests until the client sends `notifications/initialized`.
+    pub strict_initialization: bool,
+    /// Tool calls still running after this long get a JSON-RPC error; a
+    /// warning notification goes out at 80% of it.
+    pub tool_timeout: Duration,
+    /// Largest request body accepted on `/mcp`; bigger ones get 413.
+    pub max_body_bytes: usize,
+    /// Most requests handled at once; later ones wait for a slot.
+    pub max_concurrency: usize,
+    /// When set, only these tools are listed and callable.
+    pub enabled_tools: Option<Vec<String>>,
+    /// Extra names accepted by `tools/call`, as (alias, canonical tool) pairs.
+    pub tool_aliases: Vec<(String, String)>,
+    /// Pretty-print JSON responses.
+    pub pretty_json: bool,
+    /// Send JSON responses as `application/json; charset=utf-8`.
+    pub json_charset: bool,
+    /// Include internal failure details (panic messages, tool internals) in errors.
+    pub detailed_errors: bool,
+    /// Human-readable text or one JSON object per log line.
+    pub log_format: LogFormat,
+    /// How long a `tools/call` result is replayed for repeats of its idempotency key.
+    pub idempotency_ttl: Duration,
+    /// Most idempotency keys remembered at once; the least recently used go first.
+    pub idempotency_max_keys: usize,
+    /// Sessions without an open SSE or WebSocket connection are dropped after this long idle.
+    pub session_idle_ttl: Duration,
+    /// `/readyz` reports 503 when a probe task takes longer than this to be scheduled.
+    pub ready_max_latency: Duration,
+    /// Number of recent tool calls kept for `/audit`; 0 disables the audit log.
+    pub audit_log_size: usize,
+    /// Secret for signing audit entries with HMAC-SHA256; entries are unsigned when unset.
+    pub audit_hmac_key: Option<String>,
+    /// File receiving one JSON line per JSON-RPC request; off when unset.
+    pub log_file: Option<PathBuf>,
+    /// Size at which the request log file is rotated to `<file>.1`.
+    pub log_file_max_bytes: u64,
+    /// Replace tool argument values with "[redacted]" in the request log file.
+    pub log_redact_arguments: bool,
+    /// File receiving every `/mcp` request and its response as JSON lines, for `--replay`; off when unset.
+    pub record_path: Option<PathBuf>,
+    /// OTLP/HTTP collector receiving a span per `/mcp` request (`OTEL_EXPORTER_OTLP_ENDPOINT`); off when unset.
+    pub otlp_endpoint: Option<String>,
+    /// Significant digits shown for numbers in result text; structured fields keep full precision.
+    pub result_precision: Option<usize>,
+    /// Add `_meta.durationMicros` to tool results.
+    pub include_timing: bool,
+    /// Most numbers one `range` call may produce.
+    pub range_max_count: usize,
+    /// Most elements one broadcast tool call may run.
+    pub broadcast_max_elements: usize,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            bind_address: SocketAddr::new(IpAddr::V4(Ipv4Addr::LOCALHOST), 3000),
+            server_name: "Calculator MCP Server".to_string(),
+            instructions: None,
+            blocking_pool_size: default_pool_size(),
+            api_key: None,
+            auth_token: None,
+            request_timeout: Duration::from_millis(30_000),
+            max_number_string_len: crate::numeric::DEFAULT_MAX_NUMBER_STRING_LEN,
+            cors_origins: None,
+            resource_ttl: Duration::from_secs(300),
+            tools_page_size: 50,
+            sort_tools: false,
+            tools_file: None,
+            divzero_policy: DivZeroPolicy::Error,
+            log_level: LevelFilter::INFO,
+            expr_cache_size: crate::expr::DEFAULT_CACHE_SIZE,
+            expr_max_steps: crate::expr::DEFAULT_MAX_STEPS,
+            expr_max_depth: crate::expr::DEFAULT_MAX_DEPTH,
+            sse_keepalive: Duration::from_secs(30),
+            sse_keepalive_text: None,
+            sse_max_event_bytes: 256 * 1024,
+            result_cache_size: 0,
+            strict_initialization: false,
+            tool_timeout: Duration::from_millis(25_000),
+            max_body_bytes: 1024 * 1024,
+            max_concurrency: 256,
+            enabled_tools: None,
+            tool_aliases: Vec::new(),
+            pretty_json: false,
+            json_charset: false,
+            detailed_errors: true,
+            log_format: LogFormat::Text,
+            idempotency_ttl: Duration::from_secs(600),
+            idempotency_max_keys: 1000,
+            session_idle_ttl: crate::session::DEFAULT_IDLE_TTL,
+            ready_max_latency: Duration::from_millis(100),
+            audit_log_size: crate::audit::DEFAULT_CAPACITY,
+            audit_hmac_key: None,
+            log_file: None,
+            log_file_max_bytes: crate::request_log::DEFAULT_MAX_BYTES,
+            log_redact_arguments: false,
+            record_path: None,
+            otlp_endpoint: None,
+            result_precision: None,
+            include_timing: false,
+            range_max_count: 10_000,
+            broadcast_max_elements: 1000,
+        }
+    }
+}
+
+impl Config {
+    pub fn from_env(cli: &Cli) -> Self {
+        Self::from_layers(cli, |key| env::var(key).ok())
+    }
+
+    // Command-line flags win over the environment, which wins over defaults.
+    fn from_layers(cli: &Cli, env: impl Fn(&str) -> Option<String>) -> Self {
+        Self::from_lookup(|key| cli.var(key).or_else(|| env(key)))
+    }
+
+    pub fn for_profile(profile: Profile) -> Self {
+        match profile {
+            Profile::Dev => Self {
+                pretty_json: true,
+                detailed_errors: true,
+                cors_origins: None,
+                log_level: LevelFilter::DEBUG,
+                log_format: LogFormat::Text,
+                ..Self::default()
+            },
+            Profile::Prod => Self {
+                pretty_json: false,
+                detailed_errors: false,
+                cors_origins: Some(Vec::new()),
+                log_level: LevelFilter::INFO,
+                log_format: LogFormat::Json,
+                ..Self::default()
+            },
+        }
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Self {
+        let defaults = match parse_var::<Profile>(&lookup, "MCP_PROFILE") {
+            Some(profile) => Self::for_profile(profile),
+            None => Self::default(),
+        };
+        Self {
+            bind_address: SocketAddr::new(
+                lookup("MCP_HOST")
+                    .and_then(|host| parse_host(&host))
+                    .unwrap_or(defaults.bind_address.ip()),
+                parse_var(&lookup, "MCP_PORT").unwrap_or(defaults.bind_address.port()),
+            ),
+            server_name: lookup("MCP_SERVER_NAME")
+                .filter(|name| !name.trim().is_empty())
+                .unwrap_or(defaults.server_name),
+            instructions: lookup("MCP_INSTRUCTIONS").filter(|text| !text.trim().is_empty()),
+            blocking_pool_size: parse_var(&lookup, "MCP_BLOCKING_POOL_SIZE")
+                .filter(|size| *size > 0)
+                .unwrap_or(defaults.blocking_pool_size),
+            api_key: lookup("MCP_API_KEY").filter(|key| !key.is_empty()),
+            auth_token: lookup("MCP_AUTH_TOKEN").filter(|token| !token.is_empty()),
+            request_timeout: parse_var(&lookup, "MCP_REQUEST_TIMEOUT_MS")
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.request_timeout),
+            max_number_string_len: parse_var(&lookup, "MCP_MAX_NUMBER_STRING_LEN")
+                .unwrap_or(defaults.max_number_string_len),
+            cors_origins: lookup("MCP_CORS_ORIGINS")
+                .filter(|raw| !raw.trim().is_empty())
+                .map(|raw| (raw.trim() != "*").then(|| parse_origins(&raw)))
+                .unwrap_or(defaults.cors_origins),
+            resource_ttl: parse_var(&lookup, "MCP_RESOURCE_TTL_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.resource_ttl),
+            tools_page_size: parse_var(&lookup, "MCP_TOOLS_PAGE_SIZE")
+                .filter(|size| *size > 0)
+                .unwrap_or(defaults.tools_page_size),
+            sort_tools: parse_var(&lookup, "MCP_SORT_TOOLS").unwrap_or(defaults.sort_tools),
+            tools_file: lookup("MCP_TOOLS_FILE").filter(|path| !path.is_empty()).map(PathBuf::from),
+            divzero_policy: parse_var(&lookup, "MCP_DIVZERO_POLICY").unwrap_or(defaults.divzero_policy),
+            log_level: parse_var(&lookup, "MCP_LOG_LEVEL").unwrap_or(defaults.log_level),
+            expr_cache_size: parse_var(&lookup, "MCP_EXPR_CACHE_SIZE").unwrap_or(defaults.expr_cache_size),
+            expr_max_steps: parse_var(&lookup, "MCP_EXPR_MAX_STEPS")
+                .filter(|steps| *steps > 0)
+                .unwrap_or(defaults.expr_max_steps),
+            expr_max_depth: parse_var(&lookup, "MCP_EXPR_MAX_DEPTH")
+                .filter(|depth| *depth > 0)
+                .unwrap_or(defaults.expr_max_depth),
+            sse_keepalive: parse_var(&lookup, "MCP_SSE_KEEPALIVE_SECS")
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.sse_keepalive),
+            sse_keepalive_text: lookup("MCP_SSE_KEEPALIVE_TEXT").filter(|text| !text.is_empty()),
+            sse_max_event_bytes: parse_var(&lookup, "MCP_SSE_MAX_EVENT_BYTES")
+                .filter(|bytes| *bytes > 0)
+                .unwrap_or(defaults.sse_max_event_bytes),
+            result_cache_size: parse_var(&lookup, "MCP_RESULT_CACHE_SIZE").unwrap_or(defaults.result_cache_size),
+            strict_initialization: parse_var(&lookup, "MCP_STRICT_INIT").unwrap_or(defaults.strict_initialization),
+            tool_timeout: parse_var(&lookup, "MCP_TOOL_TIMEOUT_MS")
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.tool_timeout),
+            max_body_bytes: parse_var(&lookup, "MCP_MAX_BODY_BYTES")
+                .filter(|bytes| *bytes > 0)
+                .unwrap_or(defaults.max_body_bytes),
+            max_concurrency: parse_var(&lookup, "MCP_MAX_CONCURRENCY")
+                .filter(|limit| *limit > 0)
+                .unwrap_or(defaults.max_concurrency),
+            enabled_tools: lookup("MCP_ENABLED_TOOLS")
+                .map(|raw| parse_list(&raw))
+                .filter(|tools| !tools.is_empty()),
+            tool_aliases: lookup("MCP_TOOL_ALIASES").map(|raw| parse_aliases(&raw)).unwrap_or_default(),
+            pretty_json: parse_var(&lookup, "MCP_PRETTY_JSON").unwrap_or(defaults.pretty_json),
+            json_charset: parse_var(&lookup, "MCP_JSON_CHARSET").unwrap_or(defaults.json_charset),
+            detailed_errors: parse_var(&lookup, "MCP_DETAILED_ERRORS").unwrap_or(defaults.detailed_errors),
+            log_format: parse_var(&lookup, "MCP_LOG_FORMAT").unwrap_or(defaults.log_format),
+            idempotency_ttl: parse_var(&lookup, "MCP_IDEMPOTENCY_TTL_SECS")
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.idempotency_ttl),
+            idempotency_max_keys: parse_var(&lookup, "MCP_IDEMPOTENCY_MAX_KEYS")
+                .filter(|count| *count > 0)
+                .unwrap_or(defaults.idempotency_max_keys),
+            session_idle_ttl: parse_var(&lookup, "MCP_SESSION_IDLE_TTL_SECS")
+                .filter(|secs| *secs > 0)
+                .map(Duration::from_secs)
+                .unwrap_or(defaults.session_idle_ttl),
+            ready_max_latency: parse_var(&lookup, "MCP_READY_MAX_LATENCY_MS")
+                .filter(|ms| *ms > 0)
+                .map(Duration::from_millis)
+                .unwrap_or(defaults.ready_max_latency),
+            audit_log_size: parse_var(&lookup, "MCP_AUDIT_LOG_SIZE").unwrap_or(defaults.audit_log_size),
+            audit_hmac_key: lookup("MCP_AUDIT_HMAC_KEY").filter(|key| !key.is_empty()),
+            log_file: lookup("MCP_LOG_FILE").filter(|path| !path.is_empty()).map(PathBuf::from),
+            log_file_max_bytes: parse_var(&lookup, "MCP_LOG_FILE_MAX_BYTES")
+                .filter(|bytes| *bytes > 0)
+                .unwrap_or(defaults.log_file_max_bytes),
+            log_redact_arguments: parse_var(&lookup, "MCP_LOG_REDACT_ARGUMENTS")
+                .unwrap_or(defaults.log_redact_arguments),
+            record_path: lookup("MCP_RECORD_PATH").filter(|path| !path.is_empty()).map(PathBuf::from),
+            otlp_endpoint: lookup("OTEL_EXPORTER_OTLP_ENDPOINT").filter(|endpoint| !endpoint.is_empty()),
+            result_precision: parse_var(&lookup, "MCP_RESULT_PRECISION").filter(|digits| (1..=17).contains(digits)),
+            include_timing: parse_var(&lookup, "MCP_INCLUDE_TIMING").unwrap_or(defaults.include_timing),
+            range_max_count: parse_var(&lookup, "MCP_RANGE_MAX_COUNT")
+                .filter(|count| *count > 0)
+                .unwrap_or(defaults.range_max_count),
+            broadcast_max_elements: parse_var(&lookup, "MCP_BROADCAST_MAX_ELEMENTS")
+                .filter(|count| *count > 0)
+                .unwrap_or(defaults.broadcast_max_elements),
+        }
+    }
+}
+
+fn default_pool_size() -> usize {
+    std::thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+}
+
+// Parses a comma-separated origin list, e.g.
+// "https://app.example.com, http://localhost:5173".
+pub fn parse_origins(raw: &str) -> Vec<HeaderValue> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|origin| !origin.is_empty())
+        .filter_map(|origin| match HeaderValue::from_str(origin) {
+            Ok(value) => Some(value),
+            Err(_) => {
+                eprintln!("Ignoring invalid CORS origin: {:?}", origin);
+                None
+            }
+        })
+        .collect()
+}
+
+// An IPv4 or IPv6 literal; IPv6 may be bracketed as in a URL, e.g. "[::1]".
+pub fn parse_ip(raw: &str) -> Option<IpAddr> {
+    let host = raw.trim();
+    let host = host.strip_prefix('[').and_then(|h| h.strip_suffix(']')).unwrap_or(host);
+    host.parse().ok()
+}
+
+fn parse_host(raw: &str) -> Option<IpAddr> {
+    let ip = parse_ip(raw);
+    if ip.is_none() {
+        eprintln!("Ignoring invalid value for MCP_HOST: {:?}", raw);
+    }
+    ip
+}
+
+// `alias=canonical` pairs separated by commas, e.g. "plus=add,times=multiply".
+fn parse_aliases(raw: &str) -> Vec<(String, String)> {
+    parse_list(raw)
+        .into_iter()
+        .filter_map(|pair| match pair.split_once('=') {
+            Some((alias, canonical)) if !alias.trim().is_empty() && !canonical.trim().is_empty() => {
+                Some((alias.trim().to_string(), canonical.trim().to_string()))
+            }
+            _ => {
+                eprintln!("Ignoring invalid tool alias: {:?}", pair);
+                None
+            }
+        })
+        .collect()
+}
+
+fn parse_list(raw: &str) -> Vec<String> {
+    raw.split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+fn parse_var<T: std::str::FromStr>(lookup: &impl Fn(&str) -> Option<String>, key: &str) -> Option<T> {
+    let raw = lookup(key)?;
+    match raw.trim().parse() {
+        Ok(value) => Some(value),
+        Err(_) => {
+            eprintln!("Ignoring invalid value for {}: {:?}", key, raw);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pool_size_from_env() {
+        let config = Config::from_lookup(|key| (key == "MCP_BLOCKING_POOL_SIZE").then(|| "3".to_string()));
+        assert_eq!(config.blocking_pool_size, 3);
+
+        let config = Config::from_lookup(|_| Some("0".to_string()));
+        assert_eq!(config.blocking_pool_size, default_pool_size());
+    }
+
+    #[test]
+    fn test_request_timeout_from_env() {
+        let config = Config::from_lookup(|key| (key == "MCP_REQUEST_TIMEOUT_MS").then(|| "250".to_string()));
+        assert_eq!(config.request_timeout, Duration::from_millis(250));
+
+        let config = Config::from_lookup(|_| None);
+        assert_eq!(config.request_timeout, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_cli_flags_override_env() {
+        use clap::Parser;
+
+        let env = |key: &str| match key {
+            "MCP_PORT" => Some("8080".to_string()),
+            "MCP_LOG_LEVEL" => Some("debug".to_string()),
+            _ => None,
+        };
+        let cli = Cli::parse_from(["mcp-sse-rust", "--port", "9090"]);
+        let config = Config::from_layers(&cli, env);
+        assert_eq!(config.bind_address.port(), 9090);
+        assert_eq!(config.log_level, LevelFilter::DEBUG);
+        assert_eq!(config.bind_address.ip(), Config::default().bind_address.ip());
+        assert_eq!(config.enabled_tools, None);
+    }
+
+    #[test]
+    fn test_bind_address_from_env() {
+        let config = Config::from_lookup(|key| match key {
+            "MCP_HOST" => Some("::1".to_string()),
+            "MCP_PORT" => Some("8080".to_string()),
+            _ => None,
+        });
+        assert_eq!(config.bind_address, "[::1]:8080".parse::<SocketAddr>().unwrap());
+        assert_eq!(config.bind_address.to_string(), "[::1]:8080");
+
+        let config = Config::from_lookup(|key| (key == "MCP_HOST").then(|| "[::]".to_string()));
+        assert_eq!(config.bind_address, "[::]:3000".parse::<SocketAddr>().unwrap());
+
+        let config = Config::from_lookup(|key| (key == "MCP_HOST").then(|| "not-an-ip".to_string()));
+        assert_eq!(config.bind_address, "127.0.0.1:3000".parse::<SocketAddr>().unwrap());
+    }
+
+    #[test]
+    fn test_parse_origins() {
+        let origins = parse_origins("https://app.example.com, http://localhost:5173,");
+        assert_eq!(
+            origins,
+            vec![
+                HeaderValue::from_static("https://app.example.com"),
+                HeaderValue::from_static("http://localhost:5173"),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_log_level_from_env() {
+        let config = Config::from_lookup(|key| (key == "MCP_LOG_LEVEL").then(|| "debug".to_string()));
+        assert_eq!(config.log_level, LevelFilter::DEBUG);
+
+        let config = Config::from_lookup(|key| (key == "MCP_LOG_LEVEL").then(|| "loud".to_string()));
+        assert_eq!(config.log_level, LevelFilter::INFO);
+    }
+
+    #[test]
+    fn test_sse_keepalive_from_env() {
+        let config = Config::from_lookup(|key| (key == "MCP_SSE_KEEPALIVE_SECS").then(|| "10".to_string()));
+        assert_eq!(config.sse_keepalive, Duration::from_secs(10));
+
+        let config = Config::from_lookup(|key| (key == "MCP_SSE_KEEPALIVE_SECS").then(|| "0".to_string()));
+        assert_eq!(config.sse_keepalive, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_enabled_tools_from_env() {
+        let config = Config::from_lookup(|key| (key == "MCP_ENABLED_TOOLS").then(|| " add, sqrt,".to_string()));
+        assert_eq!(config.enabled_tools, Some(vec!["add".to_string(), "sqrt".to_string()]));
+
+        let config = Config::from_lookup(|key| (key == "MCP_ENABLED_TOOLS").then(|| " ".to_string()));
+        assert_eq!(config.enabled_tools, None);
+    }
+
+    #[test]
+    fn test_tool_aliases_from_env() {
+        let config =
+            Config::from_lookup(|key| (key == "MCP_TOOL_ALIASES").then(|| "plus = add, times=multiply,bad".to_string()));
+        assert_eq!(
+            config.tool_aliases,
+            vec![("plus".to_string(), "add".to_string()), ("times".to_string(), "multiply".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_prod_profile() {
+        let config = Config::from_lookup(|key| (key == "MCP_PROFILE").then(|| "prod".to_string()));
+        assert!(!config.detailed_errors);
+        assert_eq!(config.cors_origins, Some(Vec::new()));
+        assert_eq!(config.log_format, LogFormat::Json);
+        assert_eq!(config.log_level, LevelFilter::INFO);
+
+        let config = Config::from_lookup(|key| match key {
+            "MCP_PROFILE" => Some("prod".to_string()),
+            "MCP_DETAILED_ERRORS" => Some("true".to_string()),
+            "MCP_CORS_ORIGINS" => Some("https://app.example.com".to_string()),
+            _ => None,
+        });
+        assert!(config.detailed_errors);
+        assert_eq!(config.cors_origins, Some(vec![HeaderValue::from_static("https://app.example.com")]));
+    }
+}
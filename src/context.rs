@@ -0,0 +1,174 @@
+use crate::cancel::CancellationToken;
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+// Session-scoped variables shared by every call in the same session.
+pub type SessionVars = Arc<Mutex<HashMap<String, Value>>>;
+
+// Per-call context handed to every tool handler, so request-scoped features
+// (progress, session state, `_meta` passthrough) don't each need their own
+// parameter.
+#[derive(Debug, Clone, Default)]
+pub struct ToolContext {
+    pub session_id: Option<String>,
+    pub meta: Option<Value>,
+    pub progress: ProgressReporter,
+    pub vars: SessionVars,
+    // Triggered by `notifications/cancelled`; long-running tools check it.
+    pub cancellation: CancellationToken,
+    // Decimal places the session's client asked results to be shown with.
+    pub display_precision: Option<usize>,
+    // Where a tool with a large result sends it piece by piece.
+    pub chunks: ChunkSink,
+}
+
+impl ToolContext {
+    pub fn new(session_id: Option<String>, meta: Option<Value>, vars: SessionVars) -> Self {
+        let token = meta
+            .as_ref()
+            .and_then(|m| m.get("progressToken"))
+            .cloned();
+        Self {
+            session_id,
+            meta,
+            progress: ProgressReporter::new(token, None),
+            vars,
+            cancellation: CancellationToken::default(),
+            display_precision: None,
+            chunks: ChunkSink::default(),
+        }
+    }
+}
+
+// Sends `notifications/progress` for the call's `progressToken`. Reporting is
+// a no-op when the client didn't ask for progress or the transport has no
+// channel to push notifications on.
+#[derive(Debug, Clone, Default)]
+pub struct ProgressReporter {
+    token: Option<Value>,
+    sink: Option<UnboundedSender<Value>>,
+}
+
+impl ProgressReporter {
+    pub fn new(token: Option<Value>, sink: Option<UnboundedSender<Value>>) -> Self {
+        Self { token, sink }
+    }
+
+    // For `ToolHandler` implementations; the built-in tools that report
+    // progress always send a partial result with it.
+    #[allow(dead_code)]
+    pub fn report(&self, progress: f64, total: Option<f64>) {
+        self.send(progress, total, None);
+    }
+
+    // Like `report`, also carrying an intermediate result in `partial`.
+    pub fn report_partial(&self, progress: f64, total: Option<f64>, partial: Value) {
+        self.send(progress, total, Some(partial));
+    }
+
+    fn send(&self, progress: f64, total: Option<f64>, partial: Option<Value>) {
+        let (Some(token), Some(sink)) = (&self.token, &self.sink) else {
+            return;
+        };
+        let mut params = json!({
+            "progressToken": token,
+            "progress": progress
+        });
+        if let Some(total) = total {
+            params["total"] = json!(total);
+        }
+        if let Some(partial) = partial {
+            params["partial"] = partial;
+        }
+        // A closed channel means the client went away; nothing to report to.
+        let _ = sink.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/progress",
+            "params": params
+        }));
+    }
+}
+
+// Sends a large result in pieces, each as a `notifications/message` ahead of
+// the response, when the client asked for `_meta.stream` on a transport that
+// can push notifications. Tools check `is_streaming` and, when it's false,
+// return the whole result as usual.
+#[derive(Debug, Clone, Default)]
+pub struct ChunkSink {
+    tool: String,
+    sink: Option<UnboundedSender<Value>>,
+}
+
+impl ChunkSink {
+    pub fn new(tool: &str, sink: Option<UnboundedSender<Value>>) -> Self {
+        Self {
+            tool: tool.to_string(),
+            sink,
+        }
+    }
+
+    pub fn is_streaming(&self) -> bool {
+        self.sink.is_some()
+    }
+
+    pub fn send(&self, index: usize, content: Value) {
+        let Some(sink) = &self.sink else {
+            return;
+        };
+        let _ = sink.send(json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/message",
+            "params": {
+                "level": "info",
+                "logger": self.tool,
+                "data": { "chunk": index, "content": content }
+            }
+        }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::sync::mpsc;
+
+    // Stand-in for a long-running tool that uses its context.
+    fn countdown(ctx: &ToolContext) -> Option<Value> {
+        for step in 0..=2 {
+            ctx.progress.report(f64::from(step) * 50.0, Some(100.0));
+        }
+        ctx.meta.as_ref()?.get("label").cloned()
+    }
+
+    #[test]
+    fn test_tool_reads_meta_and_reports_progress() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut ctx = ToolContext::new(
+            Some("session-1".to_string()),
+            Some(json!({ "progressToken": "tok", "label": "countdown" })),
+            SessionVars::default(),
+        );
+        ctx.progress = ProgressReporter::new(Some(json!("tok")), Some(tx));
+
+        assert_eq!(countdown(&ctx), Some(json!("countdown")));
+
+        let mut seen = Vec::new();
+        while let Ok(notification) = rx.try_recv() {
+            assert_eq!(notification["method"], "notifications/progress");
+            assert_eq!(notification["params"]["progressToken"], "tok");
+            seen.push(notification["params"]["progress"].as_f64().unwrap());
+        }
+        assert_eq!(seen, vec![0.0, 50.0, 100.0]);
+    }
+
+    #[test]
+    fn test_progress_without_sink_is_noop() {
+        let ctx = ToolContext::new(None, Some(json!({ "progressToken": 7 })), SessionVars::default());
+        ctx.progress.report(1.0, None);
+        assert_eq!(ctx.progress.token, Some(json!(7)));
+    }
+}
@@ -0,0 +1,122 @@
+use crate::JsonRpcError;
+use serde_json::Value;
+use std::fmt;
+
+// Error codes this server puts on the wire: the JSON-RPC 2.0 standard ones
+// plus the server-defined and MCP codes in their reserved ranges.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[repr(i32)]
+pub enum ErrorCode {
+    ParseError = -32700,
+    InvalidRequest = -32600,
+    MethodNotFound = -32601,
+    InvalidParams = -32602,
+    InternalError = -32603,
+    Unauthorized = -32001,
+    ResourceNotFound = -32002,
+    RequestCancelled = -32800,
+}
+
+// Failure of a JSON-RPC request, turned into the wire `JsonRpcError` in one
+// place instead of every handler spelling out codes and empty `data`.
+#[derive(Debug)]
+pub enum McpError {
+    ParseError(String),
+    InvalidRequest(String),
+    MethodNotFound(String),
+    InvalidParams(String),
+    InternalError(String),
+    ResourceNotFound(String),
+    Cancelled,
+    // Any of the above with structured detail for `error.data`.
+    WithData(Box<McpError>, Value),
+    // An error a custom tool handler built itself, passed through untouched.
+    Rpc(JsonRpcError),
+}
+
+impl McpError {
+    pub fn with_data(self, data: Value) -> Self {
+        McpError::WithData(Box::new(self), data)
+    }
+
+    pub fn code(&self) -> i32 {
+        match self {
+            McpError::ParseError(_) => ErrorCode::ParseError as i32,
+            McpError::InvalidRequest(_) => ErrorCode::InvalidRequest as i32,
+            McpError::MethodNotFound(_) => ErrorCode::MethodNotFound as i32,
+            McpError::InvalidParams(_) => ErrorCode::InvalidParams as i32,
+            McpError::InternalError(_) => ErrorCode::InternalError as i32,
+            McpError::ResourceNotFound(_) => ErrorCode::ResourceNotFound as i32,
+            McpError::Cancelled => ErrorCode::RequestCancelled as i32,
+            McpError::WithData(inner, _) => inner.code(),
+            McpError::Rpc(error) => error.code,
+        }
+    }
+}
+
+impl fmt::Display for McpError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            McpError::ParseError(message)
+            | McpError::InvalidRequest(message)
+            | McpError::MethodNotFound(message)
+            | McpError::InvalidParams(message)
+            | McpError::InternalError(message)
+            | McpError::ResourceNotFound(message) => f.write_str(message),
+            McpError::Cancelled => f.write_str("Request cancelled"),
+            McpError::WithData(inner, _) => inner.fmt(f),
+            McpError::Rpc(error) => f.write_str(&error.message),
+        }
+    }
+}
+
+impl From<McpError> for JsonRpcError {
+    fn from(error: McpError) -> Self {
+        match error {
+            McpError::Rpc(error) => error,
+            McpError::WithData(inner, data) => JsonRpcError {
+                data: Some(data),
+                ..JsonRpcError::from(*inner)
+            },
+            error => JsonRpcError {
+                code: error.code(),
+                message: error.to_string(),
+                data: None,
+            },
+        }
+    }
+}
+
+impl From<JsonRpcError> for McpError {
+    fn from(error: JsonRpcError) -> Self {
+        McpError::Rpc(error)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_conversion_to_json_rpc_error() {
+        let error = JsonRpcError::from(McpError::InvalidParams("bad a".to_string()));
+        assert_eq!((error.code, error.message.as_str(), error.data), (-32602, "bad a", None));
+
+        let error = McpError::MethodNotFound("Method not found".to_string()).with_data(json!(["ping"]));
+        let error = JsonRpcError::from(error);
+        assert_eq!(error.code, -32601);
+        assert_eq!(error.data, Some(json!(["ping"])));
+
+        assert_eq!(JsonRpcError::from(McpError::Cancelled).code, -32800);
+    }
+
+    #[test]
+    fn test_error_code_values() {
+        assert_eq!(ErrorCode::ParseError as i32, -32700);
+        assert_eq!(ErrorCode::InvalidRequest as i32, -32600);
+        assert_eq!(ErrorCode::MethodNotFound as i32, -32601);
+        assert_eq!(ErrorCode::InvalidParams as i32, -32602);
+        assert_eq!(ErrorCode::InternalError as i32, -32603);
+    }
+}
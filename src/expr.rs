@@ -0,0 +1,543 @@
+use crate::lru::LruCache;
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+};
+
+pub const DEFAULT_CACHE_SIZE: usize = 256;
+pub const DEFAULT_MAX_STEPS: usize = 100_000;
+pub const DEFAULT_MAX_DEPTH: usize = 64;
+// Longest expression accepted, in tokens. Each AST node comes from at least
+// one token, so this also bounds the size of a parsed tree.
+pub const MAX_TOKENS: usize = 10_000;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum EvalError {
+    // The expression itself is at fault (unknown variable, division by zero).
+    Invalid(String),
+    // Evaluation took more steps than allowed.
+    BudgetExceeded,
+}
+
+impl fmt::Display for EvalError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            EvalError::Invalid(message) => f.write_str(message),
+            EvalError::BudgetExceeded => f.write_str("evaluation budget exceeded"),
+        }
+    }
+}
+
+impl From<String> for EvalError {
+    fn from(message: String) -> Self {
+        EvalError::Invalid(message)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOp {
+    Add,
+    Sub,
+    Mul,
+    Div,
+    Rem,
+    Pow,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Function {
+    Sqrt,
+    Abs,
+    Ln,
+    Log,
+    Exp,
+    Floor,
+    Ceil,
+    Round,
+}
+
+impl Function {
+    fn from_name(name: &str) -> Option<Self> {
+        Some(match name {
+            "sqrt" => Self::Sqrt,
+            "abs" => Self::Abs,
+            "ln" => Self::Ln,
+            "log" => Self::Log,
+            "exp" => Self::Exp,
+            "floor" => Self::Floor,
+            "ceil" => Self::Ceil,
+            "round" => Self::Round,
+            _ => return None,
+        })
+    }
+
+    fn apply(self, x: f64) -> f64 {
+        match self {
+            Self::Sqrt => x.sqrt(),
+            Self::Abs => x.abs(),
+            Self::Ln => x.ln(),
+            Self::Log => x.log10(),
+            Self::Exp => x.exp(),
+            Self::Floor => x.floor(),
+            Self::Ceil => x.ceil(),
+            Self::Round => x.round(),
+        }
+    }
+}
+
+// Parsed form of an arithmetic expression. Constants (`pi`, `e`) are folded
+// into numbers at parse time; other bare identifiers are variables.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Number(f64),
+    Var(String),
+    Neg(Box<Expr>),
+    Binary(BinaryOp, Box<Expr>, Box<Expr>),
+    Call(Function, Box<Expr>),
+}
+
+impl Expr {
+    // Evaluates with `vars` resolving variable names, visiting at most
+    // `max_steps` nodes. The budget bounds the work a single call can do,
+    // which matters once evaluation can recurse through references.
+    pub fn eval(&self, vars: &impl Fn(&str) -> Option<f64>, max_steps: usize) -> Result<f64, EvalError> {
+        let mut steps_left = max_steps;
+        self.eval_steps(vars, &mut steps_left)
+    }
+
+    // Post-order walk over an explicit stack rather than the call stack, so
+    // a long chain like `1+1+...+1` can't overflow it. `Visit` counts a node
+    // against the budget and schedules its operands; `Apply` combines their
+    // values once they are all on `values`.
+    fn eval_steps(&self, vars: &impl Fn(&str) -> Option<f64>, steps_left: &mut usize) -> Result<f64, EvalError> {
+        enum Frame<'a> {
+            Visit(&'a Expr),
+            Apply(&'a Expr),
+        }
+
+        let mut frames = vec![Frame::Visit(self)];
+        let mut values: Vec<f64> = Vec::new();
+        while let Some(frame) = frames.pop() {
+            let value = match frame {
+                Frame::Visit(expr) => {
+                    *steps_left = steps_left.checked_sub(1).ok_or(EvalError::BudgetExceeded)?;
+                    match expr {
+                        Expr::Number(n) => *n,
+                        Expr::Var(name) => vars(name).ok_or_else(|| format!("unknown variable {:?}", name))?,
+                        Expr::Neg(operand) | Expr::Call(_, operand) => {
+                            frames.extend([Frame::Apply(expr), Frame::Visit(operand)]);
+                            continue;
+                        }
+                        Expr::Binary(_, lhs, rhs) => {
+                            frames.extend([Frame::Apply(expr), Frame::Visit(rhs), Frame::Visit(lhs)]);
+                            continue;
+                        }
+                    }
+                }
+                Frame::Apply(expr) => {
+                    let b = values.pop().expect("operand evaluated");
+                    match expr {
+                        Expr::Neg(_) => -b,
+                        Expr::Call(function, _) => function.apply(b),
+                        Expr::Binary(op, _, _) => {
+                            let a = values.pop().expect("operand evaluated");
+                            match op {
+                                BinaryOp::Add => a + b,
+                                BinaryOp::Sub => a - b,
+                                BinaryOp::Mul => a * b,
+                                BinaryOp::Div if b == 0.0 => return Err("division by zero".to_string().into()),
+                                BinaryOp::Div => a / b,
+                                BinaryOp::Rem if b == 0.0 => return Err("division by zero".to_string().into()),
+                                BinaryOp::Rem => a % b,
+                                BinaryOp::Pow => a.powf(b),
+                            }
+                        }
+                        Expr::Number(_) | Expr::Var(_) => unreachable!("leaves are never applied"),
+                    }
+                }
+            };
+            if !value.is_finite() {
+                return Err("result is not a finite number".to_string().into());
+            }
+            values.push(value);
+        }
+        Ok(values.pop().expect("expression evaluated"))
+    }
+
+    // Moves this node's operands into `pending`, leaving placeholders behind.
+    fn take_operands(&mut self, pending: &mut Vec<Expr>) {
+        match self {
+            Expr::Neg(operand) | Expr::Call(_, operand) => {
+                pending.push(std::mem::replace(operand.as_mut(), Expr::Number(0.0)));
+            }
+            Expr::Binary(_, lhs, rhs) => {
+                pending.push(std::mem::replace(lhs.as_mut(), Expr::Number(0.0)));
+                pending.push(std::mem::replace(rhs.as_mut(), Expr::Number(0.0)));
+            }
+            Expr::Number(_) | Expr::Var(_) => {}
+        }
+    }
+}
+
+// The derived drop glue would recurse once per level of the tree; detaching
+// operands onto a heap stack keeps dropping a long chain flat.
+impl Drop for Expr {
+    fn drop(&mut self) {
+        let mut pending = Vec::new();
+        self.take_operands(&mut pending);
+        while let Some(mut expr) = pending.pop() {
+            expr.take_operands(&mut pending);
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Number(f64),
+    Ident(String),
+    Op(char),
+    LParen,
+    RParen,
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = source.char_indices().peekable();
+    while let Some(&(start, c)) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() || c == '.' {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                // An exponent sign belongs to the number, e.g. "1e-3".
+                let exponent_sign = (c == '-' || c == '+')
+                    && matches!(source[..i].chars().last(), Some('e' | 'E'));
+                if c.is_ascii_digit() || c == '.' || c == 'e' || c == 'E' || exponent_sign {
+                    end = i + c.len_utf8();
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            let text = &source[start..end];
+            let n = text
+                .parse()
+                .map_err(|_| format!("invalid number {:?} at position {}", text, start))?;
+            tokens.push(Token::Number(n));
+        } else if c.is_ascii_alphabetic() {
+            let mut end = start;
+            while let Some(&(i, c)) = chars.peek() {
+                if c.is_ascii_alphanumeric() || c == '_' {
+                    end = i + 1;
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(source[start..end].to_string()));
+        } else {
+            chars.next();
+            tokens.push(match c {
+                '+' | '-' | '*' | '/' | '%' | '^' => Token::Op(c),
+                '(' => Token::LParen,
+                ')' => Token::RParen,
+                _ => return Err(format!("unexpected character {:?} at position {}", c, start)),
+            });
+        }
+    }
+    Ok(tokens)
+}
+
+// Recursive-descent parser. `^` binds tighter than unary minus and is
+// right-associative, so `-2^2` is -4 and `2^3^2` is 512. Every level of
+// nesting (parentheses, function calls, prefix signs, exponents) passes
+// through `unary`, which counts them against `max_depth`; `+ - * / %` chains
+// are built in a loop. Parsing therefore recurses at most `max_depth` deep,
+// and evaluating or dropping the tree doesn't recurse at all.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    depth: usize,
+    max_depth: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn expression(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.term()?;
+        while let Some(Token::Op(c @ ('+' | '-'))) = self.peek() {
+            let op = if *c == '+' { BinaryOp::Add } else { BinaryOp::Sub };
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.term()?));
+        }
+        Ok(lhs)
+    }
+
+    fn term(&mut self) -> Result<Expr, String> {
+        let mut lhs = self.unary()?;
+        while let Some(Token::Op(c @ ('*' | '/' | '%'))) = self.peek() {
+            let op = match c {
+                '*' => BinaryOp::Mul,
+                '/' => BinaryOp::Div,
+                _ => BinaryOp::Rem,
+            };
+            self.pos += 1;
+            lhs = Expr::Binary(op, Box::new(lhs), Box::new(self.unary()?));
+        }
+        Ok(lhs)
+    }
+
+    fn unary(&mut self) -> Result<Expr, String> {
+        if self.depth == self.max_depth {
+            return Err("expression too deeply nested".to_string());
+        }
+        self.depth += 1;
+        let expr = match self.peek() {
+            Some(Token::Op('-')) => {
+                self.pos += 1;
+                self.unary().map(|operand| Expr::Neg(Box::new(operand)))
+            }
+            Some(Token::Op('+')) => {
+                self.pos += 1;
+                self.unary()
+            }
+            _ => self.power(),
+        };
+        self.depth -= 1;
+        expr
+    }
+
+    fn power(&mut self) -> Result<Expr, String> {
+        let base = self.primary()?;
+        if let Some(Token::Op('^')) = self.peek() {
+            self.pos += 1;
+            let exponent = self.unary()?;
+            return Ok(Expr::Binary(BinaryOp::Pow, Box::new(base), Box::new(exponent)));
+        }
+        Ok(base)
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        match self.next() {
+            Some(Token::Number(n)) => Ok(Expr::Number(n)),
+            Some(Token::LParen) => {
+                let inner = self.expression()?;
+                self.expect_rparen()?;
+                Ok(inner)
+            }
+            Some(Token::Ident(name)) => match name.as_str() {
+                "pi" => Ok(Expr::Number(std::f64::consts::PI)),
+                "e" => Ok(Expr::Number(std::f64::consts::E)),
+                _ if self.peek() != Some(&Token::LParen) => Ok(Expr::Var(name)),
+                _ => {
+                    let function =
+                        Function::from_name(&name).ok_or_else(|| format!("unknown function {:?}", name))?;
+                    self.pos += 1;
+                    let arg = self.expression()?;
+                    self.expect_rparen()?;
+                    Ok(Expr::Call(function, Box::new(arg)))
+                }
+            },
+            Some(token) => Err(format!("unexpected token {:?}", token)),
+            None => Err("unexpected end of expression".to_string()),
+        }
+    }
+
+    fn expect_rparen(&mut self) -> Result<(), String> {
+        match self.next() {
+            Some(Token::RParen) => Ok(()),
+            _ => Err("expected ')'".to_string()),
+        }
+    }
+}
+
+pub fn parse(source: &str, max_depth: usize) -> Result<Expr, String> {
+    let tokens = tokenize(source)?;
+    if tokens.len() > MAX_TOKENS {
+        return Err(format!("expression too long (max {} tokens)", MAX_TOKENS));
+    }
+    let mut parser = Parser {
+        tokens,
+        pos: 0,
+        depth: 0,
+        max_depth,
+    };
+    let expr = parser.expression()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected token {:?}", token)),
+    }
+}
+
+fn is_reserved(name: &str) -> bool {
+    matches!(name, "pi" | "e") || Function::from_name(name).is_some()
+}
+
+// Splits `name = expression` into the assigned variable and the expression.
+// Input without `=` is a plain expression.
+pub fn split_assignment(source: &str) -> Result<(Option<&str>, &str), String> {
+    let Some((target, expression)) = source.split_once('=') else {
+        return Ok((None, source));
+    };
+    let target = target.trim();
+    let mut chars = target.chars();
+    let is_identifier = chars.next().is_some_and(|c| c.is_ascii_alphabetic())
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !is_identifier {
+        return Err(format!("invalid assignment target {:?}", target));
+    }
+    if is_reserved(target) {
+        return Err(format!("cannot assign to {}", target));
+    }
+    Ok((Some(target), expression))
+}
+
+// Least-recently-used cache of parsed expressions, keyed by source text, so
+// clients evaluating the same expression in a loop only pay for parsing once.
+#[derive(Debug)]
+pub struct ExprCache {
+    entries: LruCache<Arc<Expr>>,
+    parses: AtomicUsize,
+    max_depth: usize,
+}
+
+impl ExprCache {
+    pub fn new(capacity: usize, max_depth: usize) -> Self {
+        Self {
+            entries: LruCache::new(capacity),
+            parses: AtomicUsize::new(0),
+            max_depth,
+        }
+    }
+
+    pub fn get_or_parse(&self, source: &str) -> Result<Arc<Expr>, String> {
+        if let Some(expr) = self.entries.get(source) {
+            return Ok(expr);
+        }
+
+        self.parses.fetch_add(1, Ordering::Relaxed);
+        let expr = Arc::new(parse(source, self.max_depth)?);
+        self.entries.insert(source.to_string(), Arc::clone(&expr));
+        Ok(expr)
+    }
+
+    #[cfg(test)]
+    pub fn parse_count(&self) -> usize {
+        self.parses.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval(source: &str) -> Result<f64, String> {
+        parse(source, DEFAULT_MAX_DEPTH)?
+            .eval(&|name| (name == "x").then_some(2.0), DEFAULT_MAX_STEPS)
+            .map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn test_precedence() {
+        assert_eq!(eval("1 + 2 * 3"), Ok(7.0));
+        assert_eq!(eval("(1 + 2) * 3"), Ok(9.0));
+        assert_eq!(eval("-2^2"), Ok(-4.0));
+        assert_eq!(eval("2^3^2"), Ok(512.0));
+        assert_eq!(eval("7 % 4 - 1e1"), Ok(-7.0));
+        assert_eq!(eval("sqrt(16) + abs(-2)"), Ok(6.0));
+        assert_eq!(eval("3 * x"), Ok(6.0));
+    }
+
+    #[test]
+    fn test_step_budget() {
+        let expr = parse("1 + 2 + 3", DEFAULT_MAX_DEPTH).unwrap();
+        // Three numbers and two additions.
+        assert_eq!(expr.eval(&|_| None, 5), Ok(6.0));
+        assert_eq!(expr.eval(&|_| None, 4), Err(EvalError::BudgetExceeded));
+    }
+
+    #[test]
+    fn test_nesting_depth_limit() {
+        let nested = format!("{}1{}", "(".repeat(200), ")".repeat(200));
+        assert_eq!(eval(&nested), Err("expression too deeply nested".to_string()));
+        assert_eq!(eval(&format!("{}1", "-".repeat(200))), Err("expression too deeply nested".to_string()));
+
+        let shallow = format!("{}1{}", "(".repeat(10), ")".repeat(10));
+        assert_eq!(eval(&shallow), Ok(1.0));
+        assert_eq!(parse(&shallow, 10).unwrap_err(), "expression too deeply nested");
+    }
+
+    #[test]
+    fn test_long_chains_do_not_recurse() {
+        let chain = vec!["1"; MAX_TOKENS / 2].join("+");
+        assert_eq!(eval(&chain), Ok((MAX_TOKENS / 2) as f64));
+        let too_long = vec!["1"; 300_000].join("+");
+        assert_eq!(eval(&too_long), Err(format!("expression too long (max {} tokens)", MAX_TOKENS)));
+
+        // Trees built directly aren't bound by the token limit; evaluating
+        // and dropping them must not use the call stack either.
+        let mut expr = Expr::Number(1.0);
+        for _ in 0..300_000 {
+            expr = Expr::Binary(BinaryOp::Add, Box::new(expr), Box::new(Expr::Number(1.0)));
+        }
+        assert_eq!(expr.eval(&|_| None, DEFAULT_MAX_STEPS), Err(EvalError::BudgetExceeded));
+        assert_eq!(expr.eval(&|_| None, usize::MAX), Ok(300_001.0));
+        drop(expr);
+    }
+
+    #[test]
+    fn test_split_assignment() {
+        assert_eq!(split_assignment("x = 1 + 2"), Ok((Some("x"), " 1 + 2")));
+        assert_eq!(split_assignment("1 + 2"), Ok((None, "1 + 2")));
+        assert!(split_assignment("2x = 1").is_err());
+        assert!(split_assignment("pi = 3").is_err());
+    }
+
+    #[test]
+    fn test_errors() {
+        assert!(eval("1 +").is_err());
+        assert!(eval("(1").is_err());
+        assert!(eval("1 2").is_err());
+        assert!(eval("foo(1)").is_err());
+        assert_eq!(eval("y + 1"), Err("unknown variable \"y\"".to_string()));
+        assert_eq!(eval("1 / 0"), Err("division by zero".to_string()));
+        assert!(eval("sqrt(-1)").is_err());
+    }
+
+    #[test]
+    fn test_cache_reuses_parsed_expressions() {
+        let cache = ExprCache::new(2, DEFAULT_MAX_DEPTH);
+        let first = cache.get_or_parse("1 + 2").unwrap();
+        let second = cache.get_or_parse("1 + 2").unwrap();
+        assert!(Arc::ptr_eq(&first, &second));
+        assert_eq!(cache.parse_count(), 1);
+    }
+
+    #[test]
+    fn test_cache_evicts_least_recently_used() {
+        let cache = ExprCache::new(2, DEFAULT_MAX_DEPTH);
+        cache.get_or_parse("1").unwrap();
+        cache.get_or_parse("2").unwrap();
+        cache.get_or_parse("1").unwrap();
+        cache.get_or_parse("3").unwrap();
+        assert_eq!(cache.parse_count(), 3);
+
+        // "2" was evicted, "1" was kept.
+        cache.get_or_parse("1").unwrap();
+        assert_eq!(cache.parse_count(), 3);
+        cache.get_or_parse("2").unwrap();
+        assert_eq!(cache.parse_count(), 4);
+    }
+}
@@ -0,0 +1,93 @@
+use crate::lru::LruCache;
+use serde_json::Value;
+use std::{
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+// Header a client sets on `/mcp` to make a `tools/call` safe to retry; the
+// same key can also be sent as `params._meta.idempotencyKey`.
+pub const IDEMPOTENCY_HEADER: &str = "idempotency-key";
+
+// What a repeated key finds: the first result when the call is the same,
+// or a mismatch when the key was reused for a different call.
+#[derive(Debug, PartialEq)]
+pub enum Replay {
+    Result(Value),
+    Mismatch,
+}
+
+#[derive(Debug)]
+struct Entry {
+    call: Value,
+    result: Value,
+    expires_at: Instant,
+}
+
+// Results of tool calls made with an idempotency key, shared by every session
+// so a retry after reconnecting still finds the first result. Each result is
+// kept with the call that produced it, so only an identical retry replays it.
+// At most `max_keys` are kept, so fresh keys can't grow memory without bound;
+// expired entries are ignored until the cache evicts or overwrites them.
+#[derive(Debug)]
+pub struct IdempotencyStore {
+    ttl: Duration,
+    entries: LruCache<Arc<Entry>>,
+}
+
+impl IdempotencyStore {
+    pub fn new(ttl: Duration, max_keys: usize) -> Self {
+        Self {
+            ttl,
+            entries: LruCache::new(max_keys),
+        }
+    }
+
+    pub fn get(&self, key: &str, call: &Value) -> Option<Replay> {
+        let entry = self.entries.get(key).filter(|entry| entry.expires_at > Instant::now())?;
+        Some(match entry.call == *call {
+            true => Replay::Result(entry.result.clone()),
+            false => Replay::Mismatch,
+        })
+    }
+
+    pub fn insert(&self, key: String, call: Value, result: Value) {
+        let expires_at = Instant::now() + self.ttl;
+        self.entries.insert(key, Arc::new(Entry { call, result, expires_at }));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_insert_and_get() {
+        let store = IdempotencyStore::new(Duration::from_secs(60), 16);
+        let call = json!({ "name": "add", "arguments": { "a": 1, "b": 2 } });
+        store.insert("retry-1".to_string(), call.clone(), json!({ "ok": true }));
+        assert_eq!(store.get("retry-1", &call), Some(Replay::Result(json!({ "ok": true }))));
+        assert_eq!(store.get("retry-2", &call), None);
+
+        let other = json!({ "name": "add", "arguments": { "a": 1, "b": 3 } });
+        assert_eq!(store.get("retry-1", &other), Some(Replay::Mismatch));
+    }
+
+    #[test]
+    fn test_expired_keys_are_forgotten() {
+        let store = IdempotencyStore::new(Duration::ZERO, 16);
+        store.insert("retry-1".to_string(), json!({}), json!({ "ok": true }));
+        assert_eq!(store.get("retry-1", &json!({})), None);
+    }
+
+    #[test]
+    fn test_key_count_is_bounded() {
+        let store = IdempotencyStore::new(Duration::from_secs(60), 2);
+        for key in ["retry-1", "retry-2", "retry-3"] {
+            store.insert(key.to_string(), json!({}), json!(key));
+        }
+        assert_eq!(store.get("retry-1", &json!({})), None);
+        assert_eq!(store.get("retry-3", &json!({})), Some(Replay::Result(json!("retry-3"))));
+    }
+}
@@ -0,0 +1,217 @@
+use axum::{
+    extract::Request,
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use crate::telemetry::SERVICE_NAME;
+use opentelemetry::trace::TracerProvider as _;
+use opentelemetry_sdk::trace::SdkTracerProvider;
+use std::{str::FromStr, sync::Mutex, time::Instant};
+use tracing::{info, level_filters::LevelFilter, Instrument};
+use tracing_subscriber::{fmt, layer::SubscriberExt, reload, util::SubscriberInitExt, Layer, Registry};
+
+// Response header carrying the id logged for the request.
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+// Gives every inbound request a UUID, logs it with the method and path on
+// entry and with the status and latency on exit, and returns it in
+// `X-Request-Id`. Everything logged while handling the request runs inside
+// a span carrying the id, so its lines can be correlated.
+pub async fn log_requests(request: Request, next: Next) -> Response {
+    let request_id = uuid::Uuid::new_v4().to_string();
+    let span = tracing::info_span!("request", id = %request_id);
+    let method = request.method().clone();
+    let path = request.uri().path().to_string();
+
+    async move {
+        info!("--> {} {}", method, path);
+        let start = Instant::now();
+        let mut response = next.run(request).await;
+        info!("<-- {} {} {} in {:?}", method, path, response.status().as_u16(), start.elapsed());
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            response.headers_mut().insert(REQUEST_ID_HEADER, value);
+        }
+        response
+    }
+    .instrument(span)
+    .await
+}
+
+// Handle for changing the global log level while the server runs.
+pub struct LogLevel {
+    handle: reload::Handle<LevelFilter, Registry>,
+    current: Mutex<LevelFilter>,
+}
+
+impl LogLevel {
+    fn new(handle: reload::Handle<LevelFilter, Registry>, level: LevelFilter) -> Self {
+        Self {
+            handle,
+            current: Mutex::new(level),
+        }
+    }
+
+    pub fn current(&self) -> LevelFilter {
+        *self.current.lock().unwrap()
+    }
+
+    pub fn set(&self, level: LevelFilter) {
+        let mut current = self.current.lock().unwrap();
+        if self.handle.reload(level).is_ok() {
+            *current = level;
+        }
+    }
+
+    // Advances info → debug → trace → info; any other level restarts at info.
+    pub fn cycle(&self) -> LevelFilter {
+        let next = match self.current() {
+            LevelFilter::INFO => LevelFilter::DEBUG,
+            LevelFilter::DEBUG => LevelFilter::TRACE,
+            _ => LevelFilter::INFO,
+        };
+        self.set(next);
+        next
+    }
+}
+
+// The filter for an MCP `logging/setLevel` level. MCP uses the syslog
+// severities, which tracing folds into its coarser levels.
+pub fn mcp_level_filter(level: &str) -> Option<LevelFilter> {
+    match level {
+        "debug" => Some(LevelFilter::DEBUG),
+        "info" | "notice" => Some(LevelFilter::INFO),
+        "warning" => Some(LevelFilter::WARN),
+        "error" | "critical" | "alert" | "emergency" => Some(LevelFilter::ERROR),
+        _ => None,
+    }
+}
+
+// A reloadable filter that isn't installed globally, so tests don't fight
+// over the process-wide subscriber.
+#[cfg(test)]
+pub fn local_level(level: LevelFilter) -> (LogLevel, impl tracing::Subscriber) {
+    let (filter, handle) = reload::Layer::new(level);
+    let subscriber = tracing_subscriber::registry().with(filter);
+    (LogLevel::new(handle, level), subscriber)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LogFormat {
+    Text,
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            other => Err(format!("unknown log format {:?}", other)),
+        }
+    }
+}
+
+// Installs the global subscriber with a reloadable level filter, and when
+// `traces` is given, a layer exporting spans through it.
+pub fn init(level: LevelFilter, format: LogFormat, traces: Option<&SdkTracerProvider>) -> LogLevel {
+    let (level, subscriber) = subscriber(level, format, traces);
+    subscriber.init();
+    level
+}
+
+// The reloadable filter only applies to the log output. Span export keeps
+// its own fixed level, so `MCP_LOG_LEVEL=warn` or a `logging/setLevel`
+// call doesn't silently stop the info-level `mcp.request` spans.
+fn subscriber(
+    level: LevelFilter,
+    format: LogFormat,
+    traces: Option<&SdkTracerProvider>,
+) -> (LogLevel, impl tracing::Subscriber + Send + Sync) {
+    let (filter, handle) = reload::Layer::new(level);
+    let output: Box<dyn Layer<Registry> + Send + Sync> = match format {
+        LogFormat::Text => Box::new(fmt::layer()),
+        LogFormat::Json => Box::new(fmt::layer().json()),
+    };
+    let otel = traces.map(|provider| {
+        tracing_opentelemetry::layer()
+            .with_tracer(provider.tracer(SERVICE_NAME))
+            .with_filter(LevelFilter::INFO)
+    });
+    let subscriber = tracing_subscriber::registry().with(output.with_filter(filter)).with(otel);
+    (LogLevel::new(handle, level), subscriber)
+}
+
+// Cycles the log level on every SIGHUP, for operators without another way
+// to change it at runtime. The handler is registered before this returns.
+#[cfg(unix)]
+pub fn cycle_on_sighup(
+    level: std::sync::Arc<LogLevel>,
+) -> std::io::Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut hangups = signal(SignalKind::hangup())?;
+    Ok(tokio::spawn(async move {
+        while hangups.recv().await.is_some() {
+            let next = level.cycle();
+            tracing::info!("SIGHUP received, log level is now {}", next);
+        }
+    }))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cycle() {
+        let (level, _subscriber) = local_level(LevelFilter::INFO);
+        assert_eq!(level.cycle(), LevelFilter::DEBUG);
+        assert_eq!(level.cycle(), LevelFilter::TRACE);
+        assert_eq!(level.cycle(), LevelFilter::INFO);
+
+        level.set(LevelFilter::WARN);
+        assert_eq!(level.cycle(), LevelFilter::INFO);
+    }
+
+    #[test]
+    fn test_log_level_does_not_gate_span_export() {
+        use opentelemetry_sdk::trace::InMemorySpanExporter;
+
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let (level, subscriber) = subscriber(LevelFilter::WARN, LogFormat::Text, Some(&provider));
+        tracing::subscriber::with_default(subscriber, || {
+            drop(crate::telemetry::request_span("ping", None));
+            level.set(LevelFilter::ERROR);
+            drop(crate::telemetry::request_span("tools/list", None));
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        assert_eq!(spans.len(), 2);
+        assert!(spans.iter().all(|span| span.name == "mcp.request"));
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn test_sighup_advances_level() {
+        let (level, _subscriber) = local_level(LevelFilter::INFO);
+        let level = std::sync::Arc::new(level);
+        let task = cycle_on_sighup(std::sync::Arc::clone(&level)).unwrap();
+
+        unsafe {
+            libc::kill(libc::getpid(), libc::SIGHUP);
+        }
+
+        for _ in 0..100 {
+            if level.current() == LevelFilter::DEBUG {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        assert_eq!(level.current(), LevelFilter::DEBUG);
+        task.abort();
+    }
+}
@@ -0,0 +1,89 @@
+use std::{collections::HashMap, sync::Mutex};
+
+// Small thread-safe least-recently-used map. Eviction scans for the oldest
+// entry, which is cheap at the few hundred entries these caches hold.
+#[derive(Debug)]
+pub struct LruCache<V> {
+    capacity: usize,
+    inner: Mutex<Entries<V>>,
+}
+
+#[derive(Debug)]
+struct Entries<V> {
+    // Each entry carries the tick of its last use; the lowest tick is evicted.
+    map: HashMap<String, (V, u64)>,
+    tick: u64,
+}
+
+impl<V: Clone> LruCache<V> {
+    // A capacity of 0 disables the cache: nothing is ever stored.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity,
+            inner: Mutex::new(Entries {
+                map: HashMap::new(),
+                tick: 0,
+            }),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.capacity > 0
+    }
+
+    pub fn get(&self, key: &str) -> Option<V> {
+        let mut inner = self.inner.lock().unwrap();
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.map.get_mut(key).map(|(value, last_used)| {
+            *last_used = tick;
+            value.clone()
+        })
+    }
+
+    pub fn insert(&self, key: String, value: V) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut inner = self.inner.lock().unwrap();
+        if inner.map.len() >= self.capacity && !inner.map.contains_key(&key) {
+            let oldest = inner
+                .map
+                .iter()
+                .min_by_key(|(_, (_, last_used))| *last_used)
+                .map(|(key, _)| key.clone());
+            if let Some(oldest) = oldest {
+                inner.map.remove(&oldest);
+            }
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.map.insert(key, (value, tick));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evicts_least_recently_used() {
+        let cache = LruCache::new(2);
+        cache.insert("a".to_string(), 1);
+        cache.insert("b".to_string(), 2);
+        assert_eq!(cache.get("a"), Some(1));
+        cache.insert("c".to_string(), 3);
+
+        assert_eq!(cache.get("a"), Some(1));
+        assert_eq!(cache.get("b"), None);
+        assert_eq!(cache.get("c"), Some(3));
+    }
+
+    #[test]
+    fn test_zero_capacity_stores_nothing() {
+        let cache = LruCache::new(0);
+        cache.insert("a".to_string(), 1);
+        assert!(!cache.is_enabled());
+        assert_eq!(cache.get("a"), None);
+    }
+}
@@ -1,20 +1,82 @@
+mod audit;
+mod auth;
+mod cancel;
+mod cli;
+mod config;
+mod context;
+mod error;
+mod expr;
+mod idempotency;
+mod logging;
+mod lru;
+mod metrics;
+mod numeric;
+mod pool;
+mod recording;
+mod request_log;
+mod resources;
+mod session;
+mod telemetry;
+mod tool;
+mod tools_file;
+
 use axum::{
-    extract::Query,
-    http::HeaderMap,
-    response::{IntoResponse, Response, Sse},
+    body::Bytes,
+    async_trait,
+    extract::{
+        rejection::{PathRejection, QueryRejection},
+        ws::{rejection::WebSocketUpgradeRejection, Message, WebSocket, WebSocketUpgrade},
+        DefaultBodyLimit, Extension, FromRequestParts, Path, Query, State,
+    },
+    http::{header, request::Parts, HeaderMap, StatusCode},
+    middleware,
+    response::{sse::KeepAlive, IntoResponse, Response, Sse},
     routing::{get, post},
     Json, Router,
 };
-use futures::stream;
-use serde::{Deserialize, Serialize};
+use futures::{stream, SinkExt};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
 use serde_json::{json, Value};
-use std::{collections::HashMap, convert::Infallible, time::Duration};
+use audit::AuditLog;
+use auth::{ApiKeyAuthenticator, Authenticator, BearerTokenAuthenticator, Principal};
+use cancel::CancellationRegistry;
+use config::Config;
+use context::{ChunkSink, ProgressReporter, SessionVars, ToolContext};
+use error::McpError;
+use expr::{EvalError, ExprCache};
+use idempotency::{IdempotencyStore, Replay, IDEMPOTENCY_HEADER};
+use logging::LogLevel;
+use lru::LruCache;
+use metrics::Metrics;
+use numeric::DivZeroPolicy;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use pool::BlockingPool;
+use recording::Recorder;
+use request_log::RequestLog;
+use resources::{ResourceStore, StoredResource};
+use session::{InMemorySessionStore, Session, SessionManager, SESSION_HEADER};
+use tool::ToolHandler;
+use std::{
+    any::Any,
+    collections::{HashMap, HashSet},
+    convert::Infallible,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::UnboundedSender;
 use tokio_stream::StreamExt;
-use tower_http::cors::CorsLayer;
+use tracing::{debug, info, warn, Instrument};
+use tower::limit::GlobalConcurrencyLimitLayer;
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    timeout::TimeoutLayer,
+};
 
 // MCP Protocol Types
 #[derive(Debug, Deserialize)]
 struct JsonRpcRequest {
+    #[allow(dead_code)]
     jsonrpc: String,
     id: Option<Value>,
     method: String,
@@ -31,6 +93,25 @@ struct JsonRpcResponse {
     error: Option<JsonRpcError>,
 }
 
+impl JsonRpcResponse {
+    fn from_result(id: Option<Value>, result: Result<Value, McpError>) -> Self {
+        let (result, error) = match result {
+            Ok(result) => (Some(result), None),
+            Err(error) => (None, Some(error.into())),
+        };
+        JsonRpcResponse {
+            jsonrpc: "2.0".to_string(),
+            id,
+            result,
+            error,
+        }
+    }
+
+    fn error(id: Option<Value>, error: McpError) -> Self {
+        Self::from_result(id, Err(error))
+    }
+}
+
 #[derive(Debug, Serialize)]
 struct JsonRpcError {
     code: i32,
@@ -40,58 +121,478 @@ struct JsonRpcError {
 }
 
 // Tool Types
-#[derive(Debug, Serialize)]
+#[derive(Debug, Clone, Serialize)]
 struct Tool {
     name: String,
     description: String,
     #[serde(rename = "inputSchema")]
     input_schema: Value,
+    // Hints for the model about the valid input domain, the output unit and,
+    // for conversions, the input unit.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    annotations: Option<Value>,
+    // Categories for grouping tools in a client's picker.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
 }
 
-// Calculator request types
+// Calculator request types. The arithmetic ones reject unknown fields, so
+// a misspelled argument is reported by name rather than as a missing one.
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct AdditionParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
     a: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
     b: f64,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct MultiplicationParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    a: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    b: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct DivisionParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
     a: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
     b: f64,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct SquareParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
     number: f64,
 }
 
 #[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
 struct SqrtParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    number: f64,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LogParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    number: f64,
+    #[serde(default = "default_log_base", deserialize_with = "numeric::deserialize_number")]
+    base: f64,
+}
+
+fn default_log_base() -> f64 {
+    10.0
+}
+
+#[derive(Debug, Deserialize)]
+struct StatsParams {
+    values: Vec<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RoundParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
     number: f64,
+    #[serde(default)]
+    decimals: i32,
+}
+
+#[derive(Debug, Deserialize)]
+struct EvaluateParams {
+    expression: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct IntegerPairParams {
+    a: Value,
+    b: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct ConversionParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    value: f64,
+    from: String,
+    to: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ClampParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    value: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    min: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    max: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompoundInterestParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    principal: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    rate: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    times_per_year: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    years: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct RandomParams {
+    #[serde(default, deserialize_with = "numeric::deserialize_number")]
+    min: f64,
+    #[serde(default = "default_random_max", deserialize_with = "numeric::deserialize_number")]
+    max: f64,
+    seed: Option<u64>,
+}
+
+fn default_random_max() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+struct ConvertBaseParams {
+    value: Value,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    from_base: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    to_base: f64,
+}
+
+#[derive(Debug, Deserialize)]
+struct ShiftParams {
+    value: Value,
+    amount: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct RangeParams {
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    start: f64,
+    #[serde(deserialize_with = "numeric::deserialize_number")]
+    stop: f64,
+    #[serde(default = "default_range_step", deserialize_with = "numeric::deserialize_number")]
+    step: f64,
+}
+
+fn default_range_step() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum Operation {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+}
+
+// Operands are passed on untouched, so the per-operation handlers parse
+// them exactly as the standalone tools do.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CalculateParams {
+    operation: Operation,
+    a: Value,
+    b: Value,
+}
+
+#[derive(Debug, Deserialize)]
+struct MatmulParams {
+    a: Vec<Vec<f64>>,
+    b: Vec<Vec<f64>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct DotParams {
+    a: Vec<f64>,
+    b: Vec<f64>,
+}
+
+#[derive(Debug, Deserialize)]
+struct NormParams {
+    values: Vec<f64>,
+    // 1, 2 or "inf"; checked by the handler.
+    #[serde(default)]
+    p: Option<Value>,
 }
 
 #[derive(Debug, Deserialize)]
 struct ToolCallParams {
     name: String,
     arguments: Value,
+    #[serde(rename = "_meta")]
+    meta: Option<Value>,
+    // Validate the call without running it.
+    #[serde(default, rename = "dryRun")]
+    dry_run: bool,
+}
+
+// MCP protocol revision this server speaks.
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+// Methods understood by `handle_request`, reported back on "Method not found"
+const SUPPORTED_METHODS: &[&str] = &[
+    "initialize",
+    "tools/list",
+    "tools/call",
+    "tools/match",
+    "resources/read",
+    "ping",
+    "notifications/initialized",
+    "notifications/cancelled",
+    "logging/setLevel",
+    "admin/reset",
+];
+
+// Integers within ±2^53 are represented exactly in an f64, so integer
+// arithmetic on them is exact as long as the result stays in range too.
+fn is_exact_integer(x: f64) -> bool {
+    const MAX_EXACT: f64 = 9_007_199_254_740_992.0;
+    x.fract() == 0.0 && x.abs() <= MAX_EXACT
+}
+
+// The text a tool panicked with, when it panicked with a string.
+fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    match payload.downcast::<String>() {
+        Ok(message) => *message,
+        Err(payload) => match payload.downcast::<&'static str>() {
+            Ok(message) => message.to_string(),
+            Err(_) => "panic with a non-string payload".to_string(),
+        },
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+// Parses the `a`/`b` arguments of gcd/lcm, rejecting fractional or
+// out-of-range values since the algorithms only make sense on integers.
+fn integer_pair(tool: &str, arguments: Value) -> Result<(i64, i64), String> {
+    let params: IntegerPairParams =
+        serde_json::from_value(arguments).map_err(|e| format!("Invalid {} parameters: {}", tool, e))?;
+    Ok((exact_integer("a", &params.a)?, exact_integer("b", &params.b)?))
+}
+
+// Rows and columns of a matrix given as rows, which must all be the same
+// non-zero length.
+fn matrix_shape(name: &str, matrix: &[Vec<f64>]) -> Result<(usize, usize), String> {
+    let cols = matrix.first().map_or(0, Vec::len);
+    if cols == 0 {
+        return Err(format!("{} must have at least one row and one column", name));
+    }
+    if let Some((index, row)) = matrix.iter().enumerate().find(|(_, row)| row.len() != cols) {
+        return Err(format!(
+            "{} must be rectangular: row {} has {} columns, row 0 has {}",
+            name,
+            index,
+            row.len(),
+            cols
+        ));
+    }
+    Ok((matrix.len(), cols))
+}
+
+// Reads a 64-bit integer argument. Going through f64 would round anything
+// beyond ±2^53, so numbers are read as integers where JSON has them that
+// way and strings are parsed as integers first; whole floats such as 4.0
+// and "4.0" are still accepted within ±2^53.
+fn exact_integer(name: &str, value: &Value) -> Result<i64, String> {
+    let whole = |x: f64| is_exact_integer(x).then_some(x as i64);
+    let integer = match value {
+        Value::Number(n) => n.as_i64().or_else(|| n.as_f64().and_then(whole)),
+        Value::String(s) => i64::from_str(s.trim())
+            .ok()
+            .or_else(|| numeric::parse_number_str(s).ok().and_then(whole)),
+        other => return Err(format!("{} must be an integer or a numeric string, got {}", name, other)),
+    };
+    integer.ok_or_else(|| format!("{} must be a 64-bit integer, got {}", name, value))
+}
+
+// Reads `digits` as an integer in `base`, with an optional leading '-'.
+// Letters stand for 10 and up in either case, as in `i64::from_str_radix`.
+fn parse_radix(digits: &str, base: u32) -> Result<i64, String> {
+    i64::from_str_radix(digits.trim(), base)
+        .map_err(|e| format!("{:?} is not an integer in base {}: {}", digits, base, e))
+}
+
+// Writes `value` in `base` with lowercase letters for digits above 9.
+fn format_radix(value: i64, base: u32) -> String {
+    let mut magnitude = value.unsigned_abs();
+    let mut digits = Vec::new();
+    loop {
+        digits.push(std::char::from_digit((magnitude % base as u64) as u32, base).unwrap());
+        magnitude /= base as u64;
+        if magnitude == 0 {
+            break;
+        }
+    }
+    if value < 0 {
+        digits.push('-');
+    }
+    digits.iter().rev().collect()
+}
+
+// Sets `key` in a tool result's `_meta`, creating the object if needed.
+fn set_meta(result: &mut Value, key: &str, value: Value) {
+    if let Value::Object(result) = result {
+        if let Value::Object(meta) = result.entry("_meta").or_insert_with(|| json!({})) {
+            meta.insert(key.to_string(), value);
+        }
+    }
+}
+
+// -1, 0 or 1; unlike `f64::signum`, both zeros give 0.
+fn sign(x: f64) -> f64 {
+    if x == 0.0 {
+        0.0
+    } else {
+        x.signum()
+    }
+}
+
+// Numbers per `notifications/message` when a range is streamed.
+const RANGE_CHUNK_LEN: usize = 1000;
+
+const TEMPERATURE_UNITS: [&str; 3] = ["celsius", "fahrenheit", "kelvin"];
+
+// Meters per unit.
+const LENGTH_UNITS: [(&str, f64); 4] = [("meters", 1.0), ("feet", 0.3048), ("miles", 1609.344), ("km", 1000.0)];
+
+fn unknown_unit(unit: &str, valid: &[&str]) -> String {
+    format!("Unknown unit {:?}; valid units are {}", unit, valid.join(", "))
+}
+
+fn convert_temperature(value: f64, from: &str, to: &str) -> Result<f64, String> {
+    let celsius = match from {
+        "celsius" => value,
+        "fahrenheit" => (value - 32.0) * 5.0 / 9.0,
+        "kelvin" => value - 273.15,
+        _ => return Err(unknown_unit(from, &TEMPERATURE_UNITS)),
+    };
+    if celsius < -273.15 {
+        return Err(format!("{} {} is below absolute zero", value, from));
+    }
+    match to {
+        "celsius" => Ok(celsius),
+        "fahrenheit" => Ok(celsius * 9.0 / 5.0 + 32.0),
+        "kelvin" => Ok(celsius + 273.15),
+        _ => Err(unknown_unit(to, &TEMPERATURE_UNITS)),
+    }
 }
 
+fn convert_length(value: f64, from: &str, to: &str) -> Result<f64, String> {
+    let meters_per = |unit: &str| {
+        LENGTH_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, meters)| *meters)
+            .ok_or_else(|| unknown_unit(unit, &LENGTH_UNITS.map(|(name, _)| name)))
+    };
+    Ok(value * meters_per(from)? / meters_per(to)?)
+}
+
+// Values per partial result when `stats` streams its progress.
+const STATS_STREAM_CHUNK: usize = 1000;
+
 // MCP Server Implementation
 struct McpServer {
     server_info: Value,
+    instructions: Option<String>,
     tools: Vec<Tool>,
+    handlers: HashMap<String, Arc<dyn ToolHandler>>,
+    disabled_tools: HashSet<String>,
+    // Alternative names for tools, resolved before anything else in `tools/call`.
+    aliases: HashMap<String, String>,
+    validators: HashMap<String, jsonschema::Validator>,
+    tools_page_size: usize,
+    sort_tools: bool,
+    pool: BlockingPool,
+    sessions: SessionManager,
+    metrics: Arc<Metrics>,
+    resources: ResourceStore,
+    expr_cache: ExprCache,
+    expr_max_steps: usize,
+    sse_keep_alive: KeepAlive,
+    sse_max_event_bytes: usize,
+    result_cache: LruCache<Value>,
+    idempotency: IdempotencyStore,
+    audit: AuditLog,
+    request_log: Option<RequestLog>,
+    recorder: Option<Recorder>,
+    cancellations: CancellationRegistry,
+    // The open `/sse` streams, by session id.
+    sse_streams: Mutex<HashMap<String, SseStream>>,
+    strict_initialization: bool,
+    tool_timeout: Duration,
+    ready_max_latency: Duration,
+    start_time: Instant,
+    pretty_json: bool,
+    detailed_errors: bool,
+    result_precision: Option<usize>,
+    divzero_policy: DivZeroPolicy,
+    include_timing: bool,
+    range_max_count: usize,
+    broadcast_max_elements: usize,
+    // Admin methods are only served when requests must authenticate.
+    admin_enabled: bool,
+    // Set by `main` once the global subscriber is installed.
+    log_level: Option<Arc<LogLevel>>,
 }
 
 impl McpServer {
+    #[cfg(test)]
     fn new() -> Self {
+        Self::with_config(&Config::default())
+    }
+
+    #[cfg(test)]
+    fn with_config(config: &Config) -> Self {
+        Self::with_tools(config, Vec::new())
+    }
+
+    // The server `main` runs: the built-in tools plus any declared in
+    // `MCP_TOOLS_FILE`.
+    fn from_config(config: &Config) -> Result<Self, String> {
+        let custom_tools = match &config.tools_file {
+            Some(path) => tools_file::load(path, config.expr_max_steps, config.expr_max_depth)?,
+            None => Vec::new(),
+        };
+        Self::try_with_tools(config, custom_tools)
+    }
+
+    // Builds a server with extra tools registered after the built-in ones.
+    // Panics if a custom tool reuses an existing tool name.
+    #[cfg(test)]
+    fn with_tools(config: &Config, custom_tools: Vec<(Tool, Arc<dyn ToolHandler>)>) -> Self {
+        Self::try_with_tools(config, custom_tools).unwrap_or_else(|e| panic!("{}", e))
+    }
+
+    // Like `with_tools`, but a name clash or an invalid inputSchema is an
+    // error, for tools that come from configuration rather than code.
+    fn try_with_tools(config: &Config, custom_tools: Vec<(Tool, Arc<dyn ToolHandler>)>) -> Result<Self, String> {
         let server_info = json!({
-            "name": "Calculator MCP Server",
-            "version": "1.0.0",
-            "protocolVersion": "2024-11-05"
+            "name": config.server_name,
+            "version": env!("CARGO_PKG_VERSION"),
+            "protocolVersion": PROTOCOL_VERSION
         });
 
-        let tools = vec![
+        let mut tools = vec![
             Tool {
                 name: "add".to_string(),
                 description: "Add two numbers together".to_string(),
@@ -107,8 +608,11 @@ impl McpServer {
                             "description": "The second number to add"
                         }
                     },
-                    "required": ["a", "b"]
+                    "required": ["a", "b"],
+                    "additionalProperties": false
                 }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
             },
             Tool {
                 name: "multiply".to_string(),
@@ -125,8 +629,53 @@ impl McpServer {
                             "description": "The second number to multiply"
                         }
                     },
-                    "required": ["a", "b"]
+                    "required": ["a", "b"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "divide".to_string(),
+                description: "Divide one number by another".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "number",
+                            "description": "The dividend"
+                        },
+                        "b": {
+                            "type": "number",
+                            "description": "The divisor"
+                        }
+                    },
+                    "required": ["a", "b"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "modulo".to_string(),
+                description: "Remainder of dividing one number by another, with the sign of the dividend".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "number",
+                            "description": "The dividend"
+                        },
+                        "b": {
+                            "type": "number",
+                            "description": "The divisor"
+                        }
+                    },
+                    "required": ["a", "b"],
+                    "additionalProperties": false
                 }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
             },
             Tool {
                 name: "square".to_string(),
@@ -139,8 +688,11 @@ impl McpServer {
                             "description": "The number to square"
                         }
                     },
-                    "required": ["number"]
+                    "required": ["number"],
+                    "additionalProperties": false
                 }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
             },
             Tool {
                 name: "sqrt".to_string(),
@@ -153,409 +705,5964 @@ impl McpServer {
                             "description": "The number to find square root of (must be non-negative)"
                         }
                     },
+                    "required": ["number"],
+                    "additionalProperties": false
+                }),
+                annotations: Some(json!({
+                    "domain": {
+                        "number": "non-negative"
+                    }
+                })),
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "log".to_string(),
+                description: "Calculate the logarithm of a number in a given base (default 10)".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "number": {
+                            "type": "number",
+                            "description": "The number to take the logarithm of (must be positive)"
+                        },
+                        "base": {
+                            "type": "number",
+                            "description": "The logarithm base (positive and not 1, defaults to 10)"
+                        }
+                    },
+                    "required": ["number"],
+                    "additionalProperties": false
+                }),
+                annotations: Some(json!({
+                    "domain": {
+                        "number": "positive",
+                        "base": "positive, not 1"
+                    }
+                })),
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "stats".to_string(),
+                description: "Calculate mean, median, min, max and population standard deviation of a list of numbers".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "values": {
+                            "type": "array",
+                            "items": { "type": "number" },
+                            "minItems": 1,
+                            "description": "The numbers to summarize"
+                        }
+                    },
+                    "required": ["values"]
+                }),
+                annotations: None,
+                tags: vec!["statistics".to_string()],
+            },
+            Tool {
+                name: "round".to_string(),
+                description: "Round a number to a given number of decimal places".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "number": {
+                            "type": "number",
+                            "description": "The number to round"
+                        },
+                        "decimals": {
+                            "type": "integer",
+                            "minimum": -15,
+                            "maximum": 15,
+                            "description": "Decimal places to keep (default 0); negative values round to tens, hundreds, ..."
+                        }
+                    },
                     "required": ["number"]
                 }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
             },
-        ];
-
-        Self {
-            server_info,
-            tools,
-        }
-    }
-
-    fn handle_request(&self, request: JsonRpcRequest) -> JsonRpcResponse {
-        match request.method.as_str() {
-            "initialize" => self.handle_initialize(request.id),
-            "tools/list" => self.handle_tools_list(request.id),
-            "tools/call" => self.handle_tools_call(request.id, request.params),
-            _ => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id: request.id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32601,
-                    message: "Method not found".to_string(),
-                    data: None,
+            Tool {
+                name: "evaluate".to_string(),
+                description: "Evaluate an arithmetic expression using + - * / % ^, parentheses, pi, e and sqrt, abs, ln, log, exp, floor, ceil, round; `name = expression` stores a variable for later calls in the session".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "expression": {
+                            "type": "string",
+                            "description": "The expression to evaluate, e.g. \"2 * (3 + sqrt(16))\" or \"x = 2 ^ 10\""
+                        }
+                    },
+                    "required": ["expression"]
                 }),
+                annotations: None,
+                tags: vec!["expressions".to_string()],
             },
-        }
-    }
-
-    fn handle_initialize(&self, id: Option<Value>) -> JsonRpcResponse {
-        JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(json!({
-                "protocolVersion": "2024-11-05",
-                "capabilities": {
-                    "tools": {}
-                },
-                "serverInfo": self.server_info
-            })),
-            error: None,
-        }
-    }
-
-    fn handle_tools_list(&self, id: Option<Value>) -> JsonRpcResponse {
-        JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(json!({
-                "tools": self.tools
-            })),
-            error: None,
-        }
-    }
-
-    fn handle_tools_call(&self, id: Option<Value>, params: Option<Value>) -> JsonRpcResponse {
-        let params = match params {
-            Some(p) => p,
-            None => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32602,
-                        message: "Invalid params".to_string(),
-                        data: None,
-                    }),
-                }
-            }
-        };
-
-        let tool_call: ToolCallParams = match serde_json::from_value(params) {
-            Ok(tc) => tc,
-            Err(e) => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32602,
-                        message: format!("Invalid params: {}", e),
-                        data: None,
-                    }),
-                }
-            }
-        };
-
-        match tool_call.name.as_str() {
-            "add" => self.handle_addition(id, tool_call.arguments),
-            "multiply" => self.handle_multiplication(id, tool_call.arguments),
-            "square" => self.handle_square(id, tool_call.arguments),
-            "sqrt" => self.handle_sqrt(id, tool_call.arguments),
-            _ => JsonRpcResponse {
-                jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: "Unknown tool".to_string(),
-                    data: None,
+            Tool {
+                name: "gcd".to_string(),
+                description: "Calculate the greatest common divisor of two integers".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "integer",
+                            "description": "The first integer"
+                        },
+                        "b": {
+                            "type": "integer",
+                            "description": "The second integer"
+                        }
+                    },
+                    "required": ["a", "b"],
+                    "additionalProperties": false
                 }),
+                annotations: None,
+                tags: vec!["number-theory".to_string()],
             },
-        }
-    }
-
-    fn handle_addition(&self, id: Option<Value>, arguments: Value) -> JsonRpcResponse {
-        let params: AdditionParams = match serde_json::from_value(arguments) {
-            Ok(p) => p,
-            Err(e) => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32602,
-                        message: format!("Invalid addition parameters: {}", e),
-                        data: None,
-                    }),
-                }
-            }
+            Tool {
+                name: "lcm".to_string(),
+                description: "Calculate the least common multiple of two integers".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "integer",
+                            "description": "The first integer"
+                        },
+                        "b": {
+                            "type": "integer",
+                            "description": "The second integer"
+                        }
+                    },
+                    "required": ["a", "b"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["number-theory".to_string()],
+            },
+            Tool {
+                name: "convert_temperature".to_string(),
+                description: "Convert a temperature between celsius, fahrenheit and kelvin".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "value": {
+                            "type": "number",
+                            "description": "The temperature to convert"
+                        },
+                        "from": {
+                            "type": "string",
+                            "enum": TEMPERATURE_UNITS,
+                            "description": "The unit of value"
+                        },
+                        "to": {
+                            "type": "string",
+                            "enum": TEMPERATURE_UNITS,
+                            "description": "The unit to convert to"
+                        }
+                    },
+                    "required": ["value", "from", "to"]
+                }),
+                annotations: None,
+                tags: vec!["conversion".to_string()],
+            },
+            Tool {
+                name: "convert_length".to_string(),
+                description: "Convert a length between meters, feet, miles and km".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "value": {
+                            "type": "number",
+                            "description": "The length to convert"
+                        },
+                        "from": {
+                            "type": "string",
+                            "enum": LENGTH_UNITS.map(|(unit, _)| unit),
+                            "description": "The unit of value"
+                        },
+                        "to": {
+                            "type": "string",
+                            "enum": LENGTH_UNITS.map(|(unit, _)| unit),
+                            "description": "The unit to convert to"
+                        }
+                    },
+                    "required": ["value", "from", "to"]
+                }),
+                annotations: None,
+                tags: vec!["conversion".to_string()],
+            },
+            Tool {
+                name: "clamp".to_string(),
+                description: "Constrain a number to the range [min, max]".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "value": {
+                            "type": "number",
+                            "description": "The number to clamp"
+                        },
+                        "min": {
+                            "type": "number",
+                            "description": "The lower bound"
+                        },
+                        "max": {
+                            "type": "number",
+                            "description": "The upper bound (must not be below min)"
+                        }
+                    },
+                    "required": ["value", "min", "max"]
+                }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "min".to_string(),
+                description: "Find the smallest of a list of numbers".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "values": {
+                            "type": "array",
+                            "items": { "type": "number" },
+                            "minItems": 1,
+                            "description": "The numbers to compare"
+                        }
+                    },
+                    "required": ["values"]
+                }),
+                annotations: None,
+                tags: vec!["statistics".to_string()],
+            },
+            Tool {
+                name: "max".to_string(),
+                description: "Find the largest of a list of numbers".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "values": {
+                            "type": "array",
+                            "items": { "type": "number" },
+                            "minItems": 1,
+                            "description": "The numbers to compare"
+                        }
+                    },
+                    "required": ["values"]
+                }),
+                annotations: None,
+                tags: vec!["statistics".to_string()],
+            },
+            Tool {
+                name: "abs".to_string(),
+                description: "Take the absolute value of a number".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "number": {
+                            "type": "number",
+                            "description": "The number"
+                        }
+                    },
+                    "required": ["number"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "sign".to_string(),
+                description: "Return the sign of a number: -1, 0 or 1".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "number": {
+                            "type": "number",
+                            "description": "The number"
+                        }
+                    },
+                    "required": ["number"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "negate".to_string(),
+                description: "Negate a number".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "number": {
+                            "type": "number",
+                            "description": "The number to negate"
+                        }
+                    },
+                    "required": ["number"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "compound_interest".to_string(),
+                description: "Final amount of a principal earning compound interest: P(1 + r/n)^(nt)".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "principal": {
+                            "type": "number",
+                            "description": "The starting amount (non-negative)"
+                        },
+                        "rate": {
+                            "type": "number",
+                            "description": "The annual interest rate as a decimal, e.g. 0.05 for 5% (non-negative)"
+                        },
+                        "times_per_year": {
+                            "type": "number",
+                            "description": "How many times a year interest is compounded (at least 1)"
+                        },
+                        "years": {
+                            "type": "number",
+                            "description": "How many years the principal is invested (non-negative)"
+                        }
+                    },
+                    "required": ["principal", "rate", "times_per_year", "years"]
+                }),
+                annotations: Some(json!({
+                    "domain": {
+                        "principal": "non-negative",
+                        "rate": "non-negative",
+                        "times_per_year": ">= 1",
+                        "years": "non-negative"
+                    }
+                })),
+                tags: vec!["finance".to_string()],
+            },
+            Tool {
+                name: "random".to_string(),
+                description: "Uniform random number in [min, max); the same seed always gives the same number".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "min": {
+                            "type": "number",
+                            "description": "Lower bound, inclusive (defaults to 0)"
+                        },
+                        "max": {
+                            "type": "number",
+                            "description": "Upper bound, exclusive (defaults to 1, must not be less than min)"
+                        },
+                        "seed": {
+                            "type": "integer",
+                            "minimum": 0,
+                            "description": "Seed for reproducible output; a fresh random seed is used when omitted"
+                        }
+                    }
+                }),
+                annotations: None,
+                tags: vec!["statistics".to_string()],
+            },
+            Tool {
+                name: "convert_base".to_string(),
+                description: "Write an integer in another base, from 2 to 36".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "value": {
+                            "type": ["integer", "string"],
+                            "description": "Digits in from_base, e.g. \"ff\"; a number is read as its decimal digits"
+                        },
+                        "from_base": {
+                            "type": "integer",
+                            "minimum": 2,
+                            "maximum": 36,
+                            "description": "The base value is written in"
+                        },
+                        "to_base": {
+                            "type": "integer",
+                            "minimum": 2,
+                            "maximum": 36,
+                            "description": "The base to convert to"
+                        }
+                    },
+                    "required": ["value", "from_base", "to_base"]
+                }),
+                annotations: None,
+                tags: vec!["conversion".to_string()],
+            },
+            Tool {
+                name: "bitand".to_string(),
+                description: "Bitwise AND of two integers, in two's complement".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "integer",
+                            "description": "The first integer"
+                        },
+                        "b": {
+                            "type": "integer",
+                            "description": "The second integer"
+                        }
+                    },
+                    "required": ["a", "b"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["bitwise".to_string()],
+            },
+            Tool {
+                name: "bitor".to_string(),
+                description: "Bitwise OR of two integers, in two's complement".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "integer",
+                            "description": "The first integer"
+                        },
+                        "b": {
+                            "type": "integer",
+                            "description": "The second integer"
+                        }
+                    },
+                    "required": ["a", "b"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["bitwise".to_string()],
+            },
+            Tool {
+                name: "bitxor".to_string(),
+                description: "Bitwise XOR of two integers, in two's complement".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "integer",
+                            "description": "The first integer"
+                        },
+                        "b": {
+                            "type": "integer",
+                            "description": "The second integer"
+                        }
+                    },
+                    "required": ["a", "b"],
+                    "additionalProperties": false
+                }),
+                annotations: None,
+                tags: vec!["bitwise".to_string()],
+            },
+            Tool {
+                name: "shift".to_string(),
+                description: "Shift an integer's bits left, or right for a negative amount".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "value": {
+                            "type": "integer",
+                            "description": "The integer to shift"
+                        },
+                        "amount": {
+                            "type": "integer",
+                            "minimum": -63,
+                            "maximum": 63,
+                            "description": "Bits to shift left; negative shifts right, keeping the sign"
+                        }
+                    },
+                    "required": ["value", "amount"]
+                }),
+                annotations: None,
+                tags: vec!["bitwise".to_string()],
+            },
+            Tool {
+                name: "deg_to_rad".to_string(),
+                description: "Convert an angle from degrees to radians".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "number": {
+                            "type": "number",
+                            "description": "The angle in degrees"
+                        }
+                    },
+                    "required": ["number"],
+                    "additionalProperties": false
+                }),
+                annotations: Some(json!({
+                    "unit": "radians",
+                    "inputUnit": "degrees"
+                })),
+                tags: vec!["conversion".to_string()],
+            },
+            Tool {
+                name: "rad_to_deg".to_string(),
+                description: "Convert an angle from radians to degrees".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "number": {
+                            "type": "number",
+                            "description": "The angle in radians"
+                        }
+                    },
+                    "required": ["number"],
+                    "additionalProperties": false
+                }),
+                annotations: Some(json!({
+                    "unit": "degrees",
+                    "inputUnit": "radians"
+                })),
+                tags: vec!["conversion".to_string()],
+            },
+            Tool {
+                name: "range".to_string(),
+                description: "Numbers from start up to (not including) stop, step apart".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "start": {
+                            "type": "number",
+                            "description": "The first number"
+                        },
+                        "stop": {
+                            "type": "number",
+                            "description": "The bound the numbers stop before"
+                        },
+                        "step": {
+                            "type": "number",
+                            "description": "Difference between consecutive numbers; negative counts down (defaults to 1)"
+                        }
+                    },
+                    "required": ["start", "stop"]
+                }),
+                annotations: None,
+                tags: vec!["sequences".to_string()],
+            },
+            Tool {
+                name: "calculate".to_string(),
+                description: "Add, subtract, multiply or divide two numbers, chosen by operation".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "operation": {
+                            "type": "string",
+                            "enum": ["add", "subtract", "multiply", "divide"],
+                            "description": "The operation to apply to a and b"
+                        },
+                        "a": {
+                            "type": "number",
+                            "description": "The first operand"
+                        },
+                        "b": {
+                            "type": "number",
+                            "description": "The second operand"
+                        }
+                    },
+                    "required": ["operation", "a", "b"]
+                }),
+                annotations: None,
+                tags: vec!["arithmetic".to_string()],
+            },
+            Tool {
+                name: "matmul".to_string(),
+                description: "Multiply two matrices, given as arrays of rows".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "array",
+                            "items": {
+                                "type": "array",
+                                "items": { "type": "number" },
+                                "minItems": 1
+                            },
+                            "minItems": 1,
+                            "description": "The left matrix, one array per row"
+                        },
+                        "b": {
+                            "type": "array",
+                            "items": {
+                                "type": "array",
+                                "items": { "type": "number" },
+                                "minItems": 1
+                            },
+                            "minItems": 1,
+                            "description": "The right matrix, with as many rows as a has columns"
+                        }
+                    },
+                    "required": ["a", "b"]
+                }),
+                annotations: None,
+                tags: vec!["linear-algebra".to_string()],
+            },
+            Tool {
+                name: "dot".to_string(),
+                description: "Dot product of two vectors of the same length".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "a": {
+                            "type": "array",
+                            "items": { "type": "number" },
+                            "minItems": 1,
+                            "description": "The first vector"
+                        },
+                        "b": {
+                            "type": "array",
+                            "items": { "type": "number" },
+                            "minItems": 1,
+                            "description": "The second vector, as long as a"
+                        }
+                    },
+                    "required": ["a", "b"]
+                }),
+                annotations: None,
+                tags: vec!["linear-algebra".to_string()],
+            },
+            Tool {
+                name: "norm".to_string(),
+                description: "Length of a vector: the L1, L2 (default) or L-infinity norm".to_string(),
+                input_schema: json!({
+                    "type": "object",
+                    "properties": {
+                        "values": {
+                            "type": "array",
+                            "items": { "type": "number" },
+                            "minItems": 1,
+                            "description": "The vector's components"
+                        },
+                        "p": {
+                            "enum": [1, 2, "inf"],
+                            "description": "Which norm: 1, 2 or \"inf\" (default 2)"
+                        }
+                    },
+                    "required": ["values"]
+                }),
+                annotations: None,
+                tags: vec!["linear-algebra".to_string()],
+            },
+        ];
+
+        let mut handlers = HashMap::new();
+        for (tool, handler) in custom_tools {
+            if tools.iter().any(|t| t.name == tool.name) {
+                return Err(format!("tool {:?} is already registered", tool.name));
+            }
+            handlers.insert(tool.name.clone(), handler);
+            tools.push(tool);
+        }
+
+        let mut disabled_tools = HashSet::new();
+        if let Some(enabled) = &config.enabled_tools {
+            tools.retain(|tool| {
+                let keep = enabled.contains(&tool.name);
+                if !keep {
+                    disabled_tools.insert(tool.name.clone());
+                }
+                keep
+            });
+        }
+
+        let mut aliases = HashMap::new();
+        for (alias, canonical) in &config.tool_aliases {
+            if tools.iter().any(|tool| tool.name == *alias) {
+                return Err(format!("tool alias {:?} is already a tool name", alias));
+            }
+            if !tools.iter().any(|tool| tool.name == *canonical) {
+                return Err(format!("tool alias {:?} refers to unknown tool {:?}", alias, canonical));
+            }
+            aliases.insert(alias.clone(), canonical.clone());
+        }
+
+        let validators = tools
+            .iter()
+            .map(|tool| {
+                let validator = jsonschema::validator_for(&tool.input_schema)
+                    .map_err(|e| format!("invalid inputSchema for tool {:?}: {}", tool.name, e))?;
+                Ok((tool.name.clone(), validator))
+            })
+            .collect::<Result<_, String>>()?;
+        let metrics = Arc::new(Metrics::default());
+        let request_log = match &config.log_file {
+            Some(path) => Some(
+                RequestLog::open(path, config.log_file_max_bytes, config.log_redact_arguments)
+                    .map_err(|e| format!("cannot open log file {}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+        let recorder = match &config.record_path {
+            Some(path) => Some(
+                Recorder::open(path).map_err(|e| format!("cannot open recording file {}: {}", path.display(), e))?,
+            ),
+            None => None,
+        };
+
+        Ok(Self {
+            server_info,
+            instructions: config.instructions.clone(),
+            tools,
+            handlers,
+            disabled_tools,
+            aliases,
+            validators,
+            tools_page_size: config.tools_page_size,
+            sort_tools: config.sort_tools,
+            pool: BlockingPool::new(config.blocking_pool_size, Arc::clone(&metrics)),
+            sessions: SessionManager::new(Arc::new(InMemorySessionStore::default()), config.session_idle_ttl),
+            metrics,
+            resources: ResourceStore::new(config.resource_ttl),
+            expr_cache: ExprCache::new(config.expr_cache_size, config.expr_max_depth),
+            expr_max_steps: config.expr_max_steps,
+            sse_keep_alive: sse_keep_alive(config),
+            sse_max_event_bytes: config.sse_max_event_bytes,
+            result_cache: LruCache::new(config.result_cache_size),
+            idempotency: IdempotencyStore::new(config.idempotency_ttl, config.idempotency_max_keys),
+            audit: AuditLog::new(
+                config.audit_log_size,
+                config.audit_hmac_key.as_ref().map(|key| key.as_bytes().to_vec()),
+            ),
+            request_log,
+            recorder,
+            cancellations: CancellationRegistry::default(),
+            sse_streams: Mutex::new(HashMap::new()),
+            strict_initialization: config.strict_initialization,
+            tool_timeout: config.tool_timeout,
+            ready_max_latency: config.ready_max_latency,
+            start_time: Instant::now(),
+            pretty_json: config.pretty_json,
+            detailed_errors: config.detailed_errors,
+            result_precision: config.result_precision,
+            divzero_policy: config.divzero_policy,
+            include_timing: config.include_timing,
+            range_max_count: config.range_max_count,
+            broadcast_max_elements: config.broadcast_max_elements,
+            admin_enabled: config.auth_token.is_some() || config.api_key.is_some(),
+            log_level: None,
+        })
+    }
+
+    // Lets `logging/setLevel` change the level of the installed subscriber.
+    fn with_log_level(mut self, log_level: Arc<LogLevel>) -> Self {
+        self.log_level = Some(log_level);
+        self
+    }
+
+    // A computed number as it appears in result text: the session's display
+    // precision when its client declared one, else the server-wide setting.
+    fn format_result(&self, x: f64, ctx: &ToolContext) -> String {
+        match ctx.display_precision {
+            Some(places) => numeric::format_decimals(x, places),
+            None => numeric::format_significant(x, self.result_precision),
+        }
+    }
+
+    // JSON response body, pretty-printed when configured.
+    fn json_body(&self, value: &impl Serialize) -> Response {
+        let body = if self.pretty_json {
+            serde_json::to_string_pretty(value)
+        } else {
+            serde_json::to_string(value)
+        };
+        match body {
+            Ok(body) => ([(header::CONTENT_TYPE, "application/json")], body).into_response(),
+            Err(e) => (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response(),
+        }
+    }
+
+    // Tool bodies are synchronous CPU work, so tool calls run on the bounded
+    // blocking pool instead of tying up the async workers. The session is
+    // loaded from the store up front and written back once the request is
+    // done, so handlers stay synchronous; concurrent requests in one session
+    // are last-write-wins.
+    async fn dispatch(self: &Arc<Self>, session_id: Option<String>, request: JsonRpcRequest) -> JsonRpcResponse {
+        self.dispatch_with_notifications(session_id, request, None).await
+    }
+
+    // Like `dispatch`, pushing server notifications for the request (such as
+    // the still-running warning) to `notifications` when the transport has
+    // a way to deliver them.
+    async fn dispatch_with_notifications(
+        self: &Arc<Self>,
+        session_id: Option<String>,
+        request: JsonRpcRequest,
+        notifications: Option<UnboundedSender<Value>>,
+    ) -> JsonRpcResponse {
+        // Without a sink of its own, a request in a session with an open
+        // `/sse` stream has its notifications delivered there.
+        let notifications = notifications.or_else(|| self.sse_sender(session_id.as_deref()?));
+        let mut session = match &session_id {
+            Some(id) => self.sessions.get(id).await,
+            None => None,
+        };
+
+        let response = if request.method != "tools/call" {
+            self.handle_session_request(session.as_mut(), request, notifications.as_ref())
+        } else {
+            let id = request.id.clone();
+            let tool = request
+                .params
+                .as_ref()
+                .and_then(|p| p.get("name"))
+                .cloned()
+                .unwrap_or(Value::Null);
+            // Only requests with both a session and an id can be named by
+            // `notifications/cancelled`. A session reusing the id of a call
+            // still in flight couldn't tell the two apart, so that's refused.
+            let cancellation = match (session_id.as_deref(), &id) {
+                (Some(session_id), Some(request_id)) => match self.cancellations.register(session_id, request_id) {
+                    Some(token) => Some(token),
+                    None => {
+                        let message = format!("Request id {} is already in flight in this session", request_id);
+                        return JsonRpcResponse::error(id, McpError::InvalidRequest(message));
+                    }
+                },
+                _ => None,
+            };
+            let server = Arc::clone(self);
+            let sink = notifications.clone();
+            let call = move || {
+                let response = server.handle_session_request(session.as_mut(), request, sink.as_ref());
+                (response, session)
+            };
+
+            // A timed-out call keeps its blocking thread (and pool slot) until
+            // the tool returns; only the client stops waiting for it.
+            let mut run = Box::pin(self.pool.run(call));
+            let cancelled = async {
+                match &cancellation {
+                    Some(token) => token.cancelled().await,
+                    None => std::future::pending().await,
+                }
+            };
+            let timed = tokio::time::timeout(self.tool_timeout, async {
+                tokio::select! {
+                    result = &mut run => return result,
+                    _ = tokio::time::sleep(self.tool_timeout.mul_f64(0.8)) => {
+                        warn!("Tool {} still running after {:?}", tool, self.tool_timeout.mul_f64(0.8));
+                        if let Some(notifications) = &notifications {
+                            let _ = notifications.send(json!({
+                                "jsonrpc": "2.0",
+                                "method": "notifications/message",
+                                "params": {
+                                    "level": "warning",
+                                    "logger": "tools",
+                                    "data": {
+                                        "message": "tool still running...",
+                                        "tool": tool,
+                                        "requestId": id
+                                    }
+                                }
+                            }));
+                        }
+                    }
+                }
+                run.await
+            });
+            // A cancelled call, like a timed-out one, keeps running until the
+            // tool notices the token or finishes.
+            let outcome = tokio::select! {
+                outcome = timed => Some(outcome),
+                _ = cancelled => None,
+            };
+            if let (Some(session_id), Some(request_id), Some(_)) = (&session_id, &id, &cancellation) {
+                self.cancellations.remove(session_id, request_id);
+            }
+            let Some(outcome) = outcome else {
+                info!("Tool {} cancelled by the client", tool);
+                return JsonRpcResponse::error(id, McpError::Cancelled.with_data(json!({ "tool": tool })));
+            };
+
+            match outcome {
+                Ok(Ok((response, updated))) => {
+                    session = updated;
+                    response
+                }
+                // A panicking tool only fails its own request: the pool permit
+                // is released while unwinding, and the session keeps the state
+                // it had before the call.
+                Ok(Err(e)) => {
+                    let reason = if e.is_panic() {
+                        panic_message(e.into_panic())
+                    } else {
+                        e.to_string()
+                    };
+                    warn!("Tool {} failed: {}", tool, reason);
+                    let message = if self.detailed_errors {
+                        format!("Internal error: {}", reason)
+                    } else {
+                        "Internal error".to_string()
+                    };
+                    return JsonRpcResponse::error(id, McpError::InternalError(message));
+                }
+                Err(_) => {
+                    let message = format!("Tool call timed out after {} ms", self.tool_timeout.as_millis());
+                    return JsonRpcResponse::error(id, McpError::InternalError(message).with_data(json!({ "tool": tool })));
+                }
+            }
+        };
+
+        if let Some(session) = session {
+            self.sessions.save(session).await;
+        }
+        response
+    }
+
+    #[cfg(test)]
+    fn handle_request(&self, request: JsonRpcRequest) -> JsonRpcResponse {
+        self.handle_session_request(None, request, None)
+    }
+
+    fn handle_session_request(
+        &self,
+        session: Option<&mut Session>,
+        request: JsonRpcRequest,
+        notifications: Option<&UnboundedSender<Value>>,
+    ) -> JsonRpcResponse {
+        // Unknown methods share one label so clients can't blow up cardinality
+        let method = if SUPPORTED_METHODS.contains(&request.method.as_str()) {
+            request.method.as_str()
+        } else {
+            "unknown"
+        };
+        self.metrics.record_request(method);
+        let logged = self.request_log.as_ref().map(|_| {
+            let tool = request.params.as_ref().and_then(|p| p.get("name")).and_then(Value::as_str);
+            let arguments = request.params.as_ref().and_then(|p| p.get("arguments"));
+            (request.method.clone(), tool.map(str::to_string), arguments.cloned())
+        });
+
+        let response = self.route_request(session, request, notifications);
+        if response.error.is_some() {
+            self.metrics.record_error();
+        }
+        if let (Some(log), Some((method, tool, arguments))) = (&self.request_log, logged) {
+            log.record(&method, tool.as_deref(), arguments.as_ref(), response.error.is_some());
+        }
+        response
+    }
+
+    fn route_request(
+        &self,
+        session: Option<&mut Session>,
+        request: JsonRpcRequest,
+        notifications: Option<&UnboundedSender<Value>>,
+    ) -> JsonRpcResponse {
+        // JSON-RPC ids are strings, numbers or null. Any other id isn't
+        // echoed back; the error carries a null id instead.
+        if matches!(request.id, Some(Value::Object(_) | Value::Array(_) | Value::Bool(_))) {
+            let error = McpError::InvalidRequest("Invalid Request: id must be a string, number or null".to_string());
+            return JsonRpcResponse::error(None, error);
+        }
+        let result = self.route_method(session, &request.method, request.id.clone(), request.params, notifications);
+        JsonRpcResponse::from_result(request.id, result)
+    }
+
+    // Every method handler reports failure as an `McpError`; `route_request`
+    // turns the outcome into the JSON-RPC response in one place.
+    fn route_method(
+        &self,
+        session: Option<&mut Session>,
+        method: &str,
+        id: Option<Value>,
+        params: Option<Value>,
+        notifications: Option<&UnboundedSender<Value>>,
+    ) -> Result<Value, McpError> {
+        // Many clients skip `notifications/initialized`, so by default a
+        // successful `initialize` is enough; strict mode waits for the ack.
+        // Sessionless calls have nothing to track and are never gated.
+        if self.strict_initialization && !matches!(method, "initialize" | "notifications/initialized" | "ping") {
+            if let Some(session) = &session {
+                if !session.initialized {
+                    return Err(McpError::InvalidRequest(
+                        "Session not initialized: send notifications/initialized first".to_string(),
+                    ));
+                }
+            }
+        }
+
+        match method {
+            "initialize" => self.handle_initialize(params, session),
+            "tools/list" => self.handle_tools_list(params),
+            "tools/match" => self.handle_tools_match(params),
+            "tools/call" => {
+                // Clients that declared text-only rendering get no structuredContent.
+                let structured = session.as_ref().is_none_or(|s| s.accepts_structured_content());
+                let mut result = self.handle_tools_call(id, params, session, notifications)?;
+                if !structured {
+                    if let Value::Object(result) = &mut result {
+                        result.remove("structuredContent");
+                    }
+                }
+                Ok(result)
+            }
+            "resources/read" => self.handle_resources_read(params),
+            "logging/setLevel" => self.handle_logging_set_level(params),
+            "admin/reset" => self.handle_admin_reset(),
+            "ping" => Ok(json!({})),
+            "notifications/cancelled" => {
+                let session_id = session.as_ref().map(|s| s.id.as_str());
+                let request_id = params.as_ref().and_then(|p| p.get("requestId"));
+                if let (Some(session_id), Some(request_id)) = (session_id, request_id) {
+                    if self.cancellations.cancel(session_id, request_id) {
+                        info!("Cancelled request {}", request_id);
+                    }
+                }
+                Ok(json!({}))
+            }
+            "notifications/initialized" => {
+                if let Some(session) = session {
+                    session.initialized = true;
+                }
+                Ok(json!({}))
+            }
+            _ => Err(McpError::MethodNotFound("Method not found".to_string()).with_data(json!(SUPPORTED_METHODS))),
+        }
+    }
+
+    fn handle_initialize(&self, params: Option<Value>, mut session: Option<&mut Session>) -> Result<Value, McpError> {
+        if let Some(client_info) = params.as_ref().and_then(|p| p.get("clientInfo")) {
+            info!("Client connected: {}", client_info);
+            if let Some(session) = session.as_deref_mut() {
+                session.client_info = Some(client_info.clone());
+            }
+            self.sessions.record_client(client_info.clone());
+        }
+        let content_types = params
+            .as_ref()
+            .and_then(|p| p.pointer("/capabilities/contentTypes"))
+            .and_then(Value::as_array)
+            .map(|types| types.iter().filter_map(Value::as_str).map(str::to_string).collect());
+        let display_precision = params
+            .as_ref()
+            .and_then(|p| p.pointer("/_meta/displayPrecision"))
+            .and_then(Value::as_u64)
+            .filter(|places| *places <= 15)
+            .map(|places| places as usize);
+        if let Some(session) = session {
+            session.content_types = content_types;
+            session.display_precision = display_precision;
+            session.protocol_version = Some(PROTOCOL_VERSION.to_string());
+        }
+
+        let mut result = json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": {
+                "tools": {},
+                "resources": {},
+                "logging": {}
+            },
+            "serverInfo": self.server_info
+        });
+        if let Some(instructions) = &self.instructions {
+            result["instructions"] = json!(instructions);
+        }
+        Ok(result)
+    }
+
+    fn handle_logging_set_level(&self, params: Option<Value>) -> Result<Value, McpError> {
+        let level = params
+            .as_ref()
+            .and_then(|p| p.get("level"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| McpError::InvalidParams("Missing level".to_string()))?;
+        let filter = logging::mcp_level_filter(level)
+            .ok_or_else(|| McpError::InvalidParams(format!("Unknown log level {:?}", level)))?;
+        let log_level = self
+            .log_level
+            .as_ref()
+            .ok_or_else(|| McpError::InternalError("Log level cannot be changed on this server".to_string()))?;
+        log_level.set(filter);
+        info!("Log level set to {} by client", filter);
+        Ok(json!({}))
+    }
+
+    // Clears the audit log and zeroes the metrics without a restart.
+    fn handle_admin_reset(&self) -> Result<Value, McpError> {
+        if !self.admin_enabled {
+            return Err(McpError::MethodNotFound(
+                "admin/reset is disabled: set MCP_AUTH_TOKEN or MCP_API_KEY to enable admin methods".to_string(),
+            ));
+        }
+        let cleared = self.audit.clear();
+        self.metrics.reset();
+        warn!("Audit log and metrics reset by admin/reset ({} audit entries cleared)", cleared);
+        Ok(json!({ "cleared": cleared }))
+    }
+
+    fn handle_tools_list(&self, params: Option<Value>) -> Result<Value, McpError> {
+        // Aliases are listed, as copies of their tool under the alias name,
+        // only for clients that ask with `includeAliases`.
+        let mut tools: Vec<Tool> = self.tools.clone();
+        let include_aliases = params.as_ref().and_then(|p| p.get("includeAliases")).and_then(Value::as_bool);
+        if include_aliases == Some(true) {
+            for (alias, canonical) in &self.aliases {
+                if let Some(tool) = self.tools.iter().find(|tool| tool.name == *canonical) {
+                    tools.push(Tool {
+                        name: alias.clone(),
+                        description: format!("Alias for {}. {}", canonical, tool.description),
+                        ..tool.clone()
+                    });
+                }
+            }
+        }
+        if self.sort_tools {
+            tools.sort_by(|a, b| a.name.cmp(&b.name));
+        }
+
+        // The cursor is the offset of the next page, opaque to clients.
+        let cursor = params.as_ref().and_then(|p| p.get("cursor"));
+        let start = match cursor {
+            None | Some(Value::Null) => 0,
+            Some(cursor) => match cursor.as_str().and_then(|c| c.parse::<usize>().ok()) {
+                Some(offset) if offset <= tools.len() => offset,
+                _ => return Err(McpError::InvalidParams(format!("Invalid cursor: {}", cursor))),
+            },
+        };
+        let end = (start + self.tools_page_size).min(tools.len());
+        let mut result = json!({
+            "tools": &tools[start..end]
+        });
+        if end < tools.len() {
+            result["nextCursor"] = json!(end.to_string());
+        }
+        // Summarizes every tool, not just this page, so a client can lay out
+        // a categorized picker before fetching the rest.
+        let mut categories: HashMap<&str, usize> = HashMap::new();
+        for tag in tools.iter().flat_map(|tool| &tool.tags) {
+            *categories.entry(tag).or_default() += 1;
+        }
+        result["_meta"] = json!({
+            "count": tools.len(),
+            "categories": categories
+        });
+
+        Ok(result)
+    }
+
+    // Lists the tools whose inputSchema accepts the given arguments, to help
+    // a model pick a tool for the values it has.
+    fn handle_tools_match(&self, params: Option<Value>) -> Result<Value, McpError> {
+        let arguments = params
+            .as_ref()
+            .and_then(|p| p.get("arguments"))
+            .filter(|arguments| arguments.is_object())
+            .ok_or_else(|| McpError::InvalidParams("Invalid params: arguments must be an object".to_string()))?;
+
+        let matches: Vec<&Tool> = self
+            .tools
+            .iter()
+            .filter(|tool| self.validators[&tool.name].is_valid(arguments))
+            .collect();
+
+        Ok(json!({
+            "tools": matches
+        }))
+    }
+
+    fn handle_tools_call(
+        &self,
+        id: Option<Value>,
+        params: Option<Value>,
+        session: Option<&mut Session>,
+        notifications: Option<&UnboundedSender<Value>>,
+    ) -> Result<Value, McpError> {
+        let params = params.ok_or_else(|| McpError::InvalidParams("Invalid params".to_string()))?;
+        let mut tool_call: ToolCallParams =
+            serde_json::from_value(params).map_err(|e| McpError::InvalidParams(format!("Invalid params: {}", e)))?;
+        if let Some(canonical) = self.aliases.get(&tool_call.name) {
+            tool_call.name = canonical.clone();
+        }
+
+        // Disabled tools still have match arms in `run_tool`, so they have
+        // to be turned away before dispatch.
+        if self.disabled_tools.contains(&tool_call.name) {
+            return Err(McpError::MethodNotFound(format!("Tool {} is disabled", tool_call.name)));
+        }
+
+        // A repeated idempotency key replays the first successful result
+        // without running the tool again, whichever session sends it. The
+        // retry has to be the same call; reusing a key for another is an error.
+        let idempotency_key = tool_call
+            .meta
+            .as_ref()
+            .and_then(|m| m.get("idempotencyKey"))
+            .and_then(Value::as_str)
+            .map(str::to_string);
+        let idempotent_call = json!({ "name": tool_call.name, "arguments": tool_call.arguments });
+        match idempotency_key
+            .as_deref()
+            .filter(|_| !tool_call.dry_run)
+            .and_then(|key| self.idempotency.get(key, &idempotent_call))
+        {
+            Some(Replay::Result(result)) => {
+                debug!("Replaying result for idempotency key {:?}", idempotency_key);
+                return Ok(result);
+            }
+            Some(Replay::Mismatch) => {
+                return Err(McpError::InvalidParams(format!(
+                    "Idempotency key {:?} was already used for a different call",
+                    idempotency_key.unwrap_or_default()
+                )));
+            }
+            None => {}
+        }
+
+        // Arrays in place of numbers make one call per element.
+        let broadcast = self.broadcast_arguments(&tool_call.name, &tool_call.arguments)?;
+        match &broadcast {
+            Some(calls) => calls
+                .iter()
+                .try_for_each(|arguments| self.validate_arguments(&tool_call.name, arguments))?,
+            None => self.validate_arguments(&tool_call.name, &tool_call.arguments)?,
+        }
+
+        // A dry run stops once the arguments pass validation: nothing is
+        // computed, counted, cached or audited.
+        if tool_call.dry_run {
+            if !self.tools.iter().any(|tool| tool.name == tool_call.name) {
+                return Err(McpError::InvalidParams("Unknown tool".to_string()));
+            }
+            return Ok(json!({ "content": [], "dryRun": true }));
+        }
+
+        // Only listed tools get metrics, so unknown names can't add series.
+        let known_tool = self.tools.iter().any(|tool| tool.name == tool_call.name);
+        if known_tool {
+            self.metrics.record_tool_call(&tool_call.name);
+        }
+
+        let as_resource = tool_call
+            .meta
+            .as_ref()
+            .and_then(|m| m.get("asResource"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        let vars = SessionVars::new(Mutex::new(
+            session.as_ref().map(|s| s.vars.clone()).unwrap_or_default(),
+        ));
+        let mut ctx = ToolContext::new(session.as_ref().map(|s| s.id.clone()), tool_call.meta, vars);
+        ctx.display_precision = session.as_ref().and_then(|s| s.display_precision);
+        if let Some(token) = ctx
+            .session_id
+            .as_deref()
+            .zip(id.as_ref())
+            .and_then(|(session_id, request_id)| self.cancellations.get(session_id, request_id))
+        {
+            ctx.cancellation = token;
+        }
+        if let Some(sink) = notifications {
+            // `_meta.stream` asks for progress even without a progressToken;
+            // the request id stands in for it then.
+            let stream = ctx
+                .meta
+                .as_ref()
+                .and_then(|m| m.get("stream"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+            let token = ctx
+                .meta
+                .as_ref()
+                .and_then(|m| m.get("progressToken"))
+                .cloned()
+                .or_else(|| stream.then(|| id.clone()).flatten());
+            ctx.progress = ProgressReporter::new(token, Some(sink.clone()));
+            // It also has tools with large results send them in chunks.
+            if stream {
+                ctx.chunks = ChunkSink::new(&tool_call.name, Some(sink.clone()));
+            }
+        }
+
+        // Only built-in tools are cached: they're pure functions of their
+        // arguments, which custom handlers aren't guaranteed to be. `evaluate`
+        // is an exception since it reads and writes session variables, and so
+        // is `random`, whose unseeded calls must differ. A streamed result
+        // lives in its notifications, so there's nothing to replay.
+        let cacheable = self.result_cache.is_enabled()
+            && !matches!(tool_call.name.as_str(), "evaluate" | "random")
+            && !ctx.chunks.is_streaming()
+            && !self.handlers.contains_key(&tool_call.name)
+            && self.tools.iter().any(|tool| tool.name == tool_call.name);
+        // The result text depends on the session's display precision too.
+        let cache_key = format!("{}:{:?}:{}", tool_call.name, ctx.display_precision, tool_call.arguments);
+        let cached = if cacheable { self.result_cache.get(&cache_key) } else { None };
+
+        let started = Instant::now();
+        let (outcome, cache_status) = match cached {
+            Some(result) => (Ok(result), "hit"),
+            None => {
+                let outcome = match broadcast {
+                    Some(calls) => self.run_broadcast(&tool_call.name, calls, &ctx),
+                    None => self.run_tool(&tool_call.name, tool_call.arguments.clone(), &ctx),
+                };
+                if !cacheable {
+                    (outcome, "bypass")
+                } else {
+                    if let Ok(result) = &outcome {
+                        self.result_cache.insert(cache_key, result.clone());
+                    }
+                    (outcome, "miss")
+                }
+            }
+        };
+
+        self.audit.record(ctx.session_id.clone(), &tool_call.name, tool_call.arguments, outcome.is_err());
+
+        if let Some(session) = session {
+            session.vars = ctx.vars.lock().unwrap().clone();
+        }
+
+        let duration = started.elapsed();
+        if known_tool {
+            self.metrics.observe_tool_duration(&tool_call.name, duration);
+        }
+        let mut result = outcome?;
+        if self.result_cache.is_enabled() {
+            set_meta(&mut result, "cache", json!(cache_status));
+        }
+        // Covers the tool itself, or the cache lookup on a hit.
+        if self.include_timing {
+            set_meta(&mut result, "durationMicros", json!(duration.as_micros() as u64));
+        }
+
+        let result = if as_resource {
+            self.store_result_as_resource(result)
+        } else {
+            result
+        };
+        if let Some(key) = idempotency_key {
+            self.idempotency.insert(key, idempotent_call, result.clone());
+        }
+        Ok(result)
+    }
+
+    // Splits a built-in tool call with arrays where its schema expects
+    // numbers into one call per element. Scalars are repeated for every
+    // element; arrays must all have the same length and at most
+    // `broadcast_max_elements` long. `None` when no argument is broadcast.
+    // Custom tools get their arguments unchanged.
+    fn broadcast_arguments(&self, name: &str, arguments: &Value) -> Result<Option<Vec<Value>>, McpError> {
+        let Some(tool) = self.tools.iter().find(|tool| tool.name == name) else {
+            return Ok(None);
+        };
+        let (Some(properties), Some(arguments)) = (tool.input_schema["properties"].as_object(), arguments.as_object())
+        else {
+            return Ok(None);
+        };
+        if self.handlers.contains_key(name) {
+            return Ok(None);
+        }
+
+        let broadcast: Vec<(&String, &Vec<Value>)> = arguments
+            .iter()
+            .filter_map(|(key, value)| {
+                let kind = properties.get(key).and_then(|p| p["type"].as_str());
+                let numeric = matches!(kind, Some("number" | "integer"));
+                Some((key, value.as_array().filter(|_| numeric)?))
+            })
+            .collect();
+        let Some(&(first, first_values)) = broadcast.first() else {
+            return Ok(None);
+        };
+        if let Some((key, values)) = broadcast.iter().find(|(_, values)| values.len() != first_values.len()) {
+            return Err(McpError::InvalidParams(format!(
+                "Cannot broadcast {} values of {} with {} values of {}",
+                first_values.len(),
+                first,
+                values.len(),
+                key
+            )));
+        }
+        if first_values.len() > self.broadcast_max_elements {
+            return Err(McpError::InvalidParams(format!(
+                "Cannot broadcast {} values of {}: at most {} elements are allowed",
+                first_values.len(),
+                first,
+                self.broadcast_max_elements
+            )));
+        }
+
+        let calls = (0..first_values.len())
+            .map(|i| {
+                let mut element = arguments.clone();
+                for (key, values) in &broadcast {
+                    element.insert((*key).clone(), values[i].clone());
+                }
+                Value::Object(element)
+            })
+            .collect();
+        Ok(Some(calls))
+    }
+
+    // Runs each broadcast call and combines the answers: one line of text
+    // per element, and the element results as an array in structuredContent.
+    // Each element only passes the tool's own per-call limits, so tools
+    // returning lists are also held to their limit across all elements.
+    fn run_broadcast(&self, name: &str, calls: Vec<Value>, ctx: &ToolContext) -> Result<Value, McpError> {
+        let total_limit = (name == "range").then_some(self.range_max_count);
+        let mut total = 0;
+        let mut lines = Vec::with_capacity(calls.len());
+        let mut results = Vec::with_capacity(calls.len());
+        let mut exact = true;
+        for (i, arguments) in calls.into_iter().enumerate() {
+            let result = self.run_tool(name, arguments, ctx).map_err(|e| {
+                let mut error = JsonRpcError::from(e);
+                error.message = format!("element {}: {}", i, error.message);
+                McpError::Rpc(error)
+            })?;
+            if let Some(limit) = total_limit {
+                let structured = &result["structuredContent"];
+                total += structured["result"]
+                    .as_array()
+                    .map(Vec::len)
+                    .or_else(|| structured["count"].as_u64().map(|count| count as usize))
+                    .unwrap_or(1);
+                if total > limit {
+                    return Err(McpError::InvalidParams(format!(
+                        "{} broadcast would produce more than {} numbers in total",
+                        name, limit
+                    )));
+                }
+            }
+            lines.push(result["content"][0]["text"].as_str().unwrap_or_default().to_string());
+            results.push(result["structuredContent"]["result"].clone());
+            exact &= result["_meta"]["exact"].as_bool().unwrap_or(false);
+        }
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": lines.join("\n")
+            }],
+            "structuredContent": {
+                "result": results,
+                "operation": name
+            },
+            "_meta": {
+                "exact": exact
+            }
+        }))
+    }
+
+    // Checks arguments against the tool's inputSchema, reporting every
+    // violation in `data`. Numeric strings pass where a number is expected,
+    // matching what the handlers accept. Unknown tools are left to `run_tool`.
+    // Unexpected properties are reported first: `{"aa": 1}` is more likely a
+    // typo of `a` than an argument left out, so the message names `aa`.
+    fn validate_arguments(&self, name: &str, arguments: &Value) -> Result<(), McpError> {
+        let Some(tool) = self.tools.iter().find(|tool| tool.name == name) else {
+            return Ok(());
+        };
+        let arguments = numeric::coerce_numeric_strings(&tool.input_schema, arguments);
+        let mut errors: Vec<_> = self.validators[name].iter_errors(&arguments).collect();
+        errors.sort_by_key(|e| e.kind().keyword() != "additionalProperties");
+        let violations: Vec<Value> = errors
+            .iter()
+            .map(|e| {
+                json!({
+                    "path": e.instance_path().to_string(),
+                    "message": e.to_string()
+                })
+            })
+            .collect();
+        let Some(first) = violations.first() else {
+            return Ok(());
+        };
+
+        let message = format!("Invalid arguments for {}: {}", name, first["message"].as_str().unwrap_or_default());
+        Err(McpError::InvalidParams(message).with_data(json!(violations)))
+    }
+
+    fn run_tool(&self, name: &str, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        match name {
+            "add" => self.handle_addition(arguments, ctx),
+            "multiply" => self.handle_multiplication(arguments, ctx),
+            "divide" => self.handle_division(arguments, ctx, "divide", "÷", |a, b| a / b),
+            "modulo" => self.handle_division(arguments, ctx, "modulo", "mod", |a, b| a % b),
+            "square" => self.handle_square(arguments, ctx),
+            "sqrt" => self.handle_sqrt(arguments, ctx),
+            "log" => self.handle_log(arguments, ctx),
+            "stats" => self.handle_stats(arguments, ctx),
+            "round" => self.handle_round(arguments, ctx),
+            "evaluate" => self.handle_evaluate(arguments, ctx),
+            "gcd" => self.handle_gcd(arguments, ctx),
+            "lcm" => self.handle_lcm(arguments, ctx),
+            "convert_temperature" => self.handle_convert_temperature(arguments, ctx),
+            "convert_length" => self.handle_convert_length(arguments, ctx),
+            "clamp" => self.handle_clamp(arguments, ctx),
+            "min" => self.handle_extremum(arguments, ctx, "min", f64::min),
+            "max" => self.handle_extremum(arguments, ctx, "max", f64::max),
+            "abs" => self.handle_unary(arguments, ctx, "abs", f64::abs, true),
+            "sign" => self.handle_unary(arguments, ctx, "sign", sign, true),
+            "negate" => self.handle_unary(arguments, ctx, "negate", |x| -x, true),
+            "deg_to_rad" => self.handle_unary(arguments, ctx, "deg_to_rad", f64::to_radians, false),
+            "rad_to_deg" => self.handle_unary(arguments, ctx, "rad_to_deg", f64::to_degrees, false),
+            "compound_interest" => self.handle_compound_interest(arguments, ctx),
+            "random" => self.handle_random(arguments, ctx),
+            "convert_base" => self.handle_convert_base(arguments, ctx),
+            "bitand" => self.handle_bitwise(arguments, "bitand", "&", |a, b| a & b),
+            "bitor" => self.handle_bitwise(arguments, "bitor", "|", |a, b| a | b),
+            "bitxor" => self.handle_bitwise(arguments, "bitxor", "^", |a, b| a ^ b),
+            "shift" => self.handle_shift(arguments),
+            "range" => self.handle_range(arguments, ctx),
+            "calculate" => self.handle_calculate(arguments, ctx),
+            "matmul" => self.handle_matmul(arguments, ctx),
+            "dot" => self.handle_dot(arguments, ctx),
+            "norm" => self.handle_norm(arguments, ctx),
+            name => match self.handlers.get(name) {
+                Some(handler) => self.call_handler(name, handler.as_ref(), arguments, ctx),
+                None => Err(McpError::InvalidParams("Unknown tool".to_string())),
+            },
+        }
+    }
+
+    fn call_handler(
+        &self,
+        name: &str,
+        handler: &dyn ToolHandler,
+        arguments: Value,
+        ctx: &ToolContext,
+    ) -> Result<Value, McpError> {
+        let output = handler.call(ctx, arguments)?;
+        output.into_result().map_err(|reason| {
+            warn!("Tool {} produced an unserializable result: {}", name, reason);
+            let error = McpError::InternalError("tool produced unserializable result".to_string());
+            if self.detailed_errors {
+                error.with_data(json!({ "tool": name, "reason": reason }))
+            } else {
+                error
+            }
+        })
+    }
+
+    // Swaps an inline result for a `resource` content item pointing at a
+    // temporary copy the client can fetch with `resources/read`.
+    fn store_result_as_resource(&self, result: Value) -> Value {
+        let mime_type = "application/json".to_string();
+        let uri = self.resources.insert(StoredResource {
+            mime_type: mime_type.clone(),
+            text: result.to_string(),
+        });
+        json!({
+            "content": [{
+                "type": "resource",
+                "resource": {
+                    "uri": uri,
+                    "mimeType": mime_type
+                }
+            }]
+        })
+    }
+
+    // Appends the exchange to the MCP_RECORD_PATH recording, if there is one.
+    // Notifications are recorded without a response.
+    fn record_exchange(&self, request: Option<&Value>, response: Option<&JsonRpcResponse>) {
+        if let (Some(recorder), Some(request)) = (&self.recorder, request) {
+            let response = response.and_then(|response| serde_json::to_value(response).ok());
+            recorder.record(request, response.as_ref());
+        }
+    }
+
+    // Sender for the `/sse` stream open for `session_id`, if there is one.
+    fn sse_sender(&self, session_id: &str) -> Option<UnboundedSender<Value>> {
+        self.sse_streams.lock().unwrap().get(session_id).map(|stream| stream.sender.clone())
+    }
+
+    // Messages waiting on the session's `/sse` stream for the client to read.
+    fn sse_queue_len(&self, session_id: &str) -> usize {
+        let streams = self.sse_streams.lock().unwrap();
+        let queued = streams.get(session_id).and_then(|stream| stream.queued.upgrade());
+        queued.map_or(0, |queued| queued.lock().unwrap().len())
+    }
+
+    // Name and data of the SSE event carrying `message`. A payload too big
+    // for client buffers is stored as a resource and replaced by an `error`
+    // event pointing at it, so the full data can still be fetched with
+    // `resources/read`.
+    fn sse_event_data(&self, message: &impl Serialize) -> (&'static str, String) {
+        let data = serde_json::to_string(message).unwrap_or_else(|_| "{}".to_string());
+        if data.len() <= self.sse_max_event_bytes {
+            return ("message", data);
+        }
+
+        let size = data.len();
+        warn!("SSE event of {} bytes exceeds {} bytes; sending a resource reference", size, self.sse_max_event_bytes);
+        let uri = self.resources.insert(StoredResource {
+            mime_type: "application/json".to_string(),
+            text: data,
+        });
+        let reference = json!({
+            "error": "event data too large",
+            "size": size,
+            "limit": self.sse_max_event_bytes,
+            "uri": uri
+        });
+        ("error", reference.to_string())
+    }
+
+    fn handle_resources_read(&self, params: Option<Value>) -> Result<Value, McpError> {
+        let uri = params
+            .as_ref()
+            .and_then(|p| p.get("uri"))
+            .and_then(Value::as_str)
+            .ok_or_else(|| McpError::InvalidParams("Invalid params: missing uri".to_string()))?;
+
+        let resource = self.resources.get(uri).ok_or_else(|| {
+            McpError::ResourceNotFound("Resource not found".to_string()).with_data(json!({ "uri": uri }))
+        })?;
+        Ok(json!({
+            "contents": [{
+                "uri": uri,
+                "mimeType": resource.mime_type,
+                "text": resource.text
+            }]
+        }))
+    }
+
+    fn handle_addition(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: AdditionParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid addition parameters: {}", e)))?;
+
+        let result = params.a + params.b;
+        info!("Performed addition: {} + {} = {}", params.a, params.b, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} + {} = {}", params.a, params.b, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "add"
+            },
+            "_meta": {
+                "exact": is_exact_integer(params.a) && is_exact_integer(params.b) && is_exact_integer(result)
+            }
+        }))
+    }
+
+    // Only reachable through `calculate`; there is no standalone subtract tool.
+    fn handle_subtraction(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: AdditionParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid subtraction parameters: {}", e)))?;
+
+        let result = params.a - params.b;
+        info!("Performed subtraction: {} - {} = {}", params.a, params.b, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} - {} = {}", params.a, params.b, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "subtract"
+            },
+            "_meta": {
+                "exact": is_exact_integer(params.a) && is_exact_integer(params.b) && is_exact_integer(result)
+            }
+        }))
+    }
+
+    fn handle_multiplication(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: MultiplicationParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid multiplication parameters: {}", e)))?;
+
+        let result = params.a * params.b;
+        info!("Performed multiplication: {} × {} = {}", params.a, params.b, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} × {} = {}", params.a, params.b, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "multiply"
+            },
+            "_meta": {
+                "exact": is_exact_integer(params.a) && is_exact_integer(params.b) && is_exact_integer(result)
+            }
+        }))
+    }
+
+    // Shared by `divide` and `modulo`; a zero divisor is answered according
+    // to MCP_DIVZERO_POLICY.
+    fn handle_division(
+        &self,
+        arguments: Value,
+        ctx: &ToolContext,
+        tool: &str,
+        symbol: &str,
+        op: fn(f64, f64) -> f64,
+    ) -> Result<Value, McpError> {
+        let params: DivisionParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid {} parameters: {}", tool, e)))?;
+
+        let (result, text) = if params.b != 0.0 {
+            let result = op(params.a, params.b);
+            (json!(result), self.format_result(result, ctx))
+        } else {
+            match self.divzero_policy {
+                DivZeroPolicy::Error => {
+                    return Err(McpError::InvalidParams(format!(
+                        "Division by zero: {} {} {}",
+                        params.a, symbol, params.b
+                    )));
+                }
+                DivZeroPolicy::Null => (Value::Null, "null".to_string()),
+                DivZeroPolicy::Infinity => {
+                    let result = numeric::non_finite_str(op(params.a, params.b));
+                    (json!(result), result.to_string())
+                }
+            }
+        };
+        info!("Performed {}: {} {} {} = {}", tool, params.a, symbol, params.b, text);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} {} {} = {}", params.a, symbol, params.b, text)
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": tool
+            },
+            "_meta": {
+                "exact": result.as_f64().is_some_and(|r| {
+                    is_exact_integer(params.a) && is_exact_integer(params.b) && is_exact_integer(r)
+                })
+            }
+        }))
+    }
+
+    // One tool for the four basic operations, for clients that would rather
+    // not list each of them.
+    fn handle_calculate(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: CalculateParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid calculate parameters: {}", e)))?;
+
+        let operands = json!({ "a": params.a, "b": params.b });
+        match params.operation {
+            Operation::Add => self.handle_addition(operands, ctx),
+            Operation::Subtract => self.handle_subtraction(operands, ctx),
+            Operation::Multiply => self.handle_multiplication(operands, ctx),
+            Operation::Divide => self.handle_division(operands, ctx, "divide", "÷", |a, b| a / b),
+        }
+    }
+
+    fn handle_square(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: SquareParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid square parameters: {}", e)))?;
+
+        let result = params.number * params.number;
+        info!("Performed square: {}² = {}", params.number, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{}² = {}", params.number, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "square"
+            },
+            "_meta": {
+                "exact": is_exact_integer(params.number) && is_exact_integer(result)
+            }
+        }))
+    }
+
+    fn handle_sqrt(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: SqrtParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid sqrt parameters: {}", e)))?;
+
+        if params.number < 0.0 {
+            return Err(McpError::InvalidParams(format!(
+                "Cannot calculate square root of negative number: {}",
+                params.number
+            )));
+        }
+
+        let result = params.number.sqrt();
+        info!("Performed square root: √{} = {}", params.number, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("√{} = {}", params.number, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "sqrt"
+            },
+            "_meta": {
+                "exact": result * result == params.number
+            }
+        }))
+    }
+
+    fn handle_log(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: LogParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid log parameters: {}", e)))?;
+
+        if params.number <= 0.0 {
+            return Err(McpError::InvalidParams(format!(
+                "Cannot calculate logarithm of non-positive number: {}",
+                params.number
+            )));
+        }
+
+        if params.base <= 0.0 || params.base == 1.0 {
+            return Err(McpError::InvalidParams(format!(
+                "Invalid logarithm base: {} (must be positive and not 1)",
+                params.base
+            )));
+        }
+
+        // log10 is exact for powers of ten, where ln(x) / ln(10) is not.
+        let result = if params.base == 10.0 {
+            params.number.log10()
+        } else {
+            params.number.ln() / params.base.ln()
+        };
+        info!("Performed logarithm: log_{}({}) = {}", params.base, params.number, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("log_{}({}) = {}", params.base, params.number, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "log"
+            },
+            "_meta": {
+                "exact": is_exact_integer(result) && params.base.powf(result) == params.number
+            }
+        }))
+    }
+
+    // With `_meta.stream`, running count and mean are sent as progress
+    // notifications every STATS_STREAM_CHUNK values before the summary.
+    fn handle_stats(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: StatsParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid stats parameters: {}", e)))?;
+
+        if params.values.is_empty() {
+            return Err(McpError::InvalidParams("Cannot calculate statistics of an empty list".to_string()));
+        }
+
+        let stream = ctx
+            .meta
+            .as_ref()
+            .and_then(|m| m.get("stream"))
+            .and_then(Value::as_bool)
+            .unwrap_or(false);
+        if stream {
+            let total = params.values.len();
+            let (mut count, mut sum) = (0, 0.0);
+            for chunk in params.values.chunks(STATS_STREAM_CHUNK) {
+                if ctx.cancellation.is_cancelled() {
+                    return Err(McpError::Cancelled);
+                }
+                count += chunk.len();
+                sum += chunk.iter().sum::<f64>();
+                ctx.progress.report_partial(
+                    count as f64,
+                    Some(total as f64),
+                    json!({ "count": count, "mean": sum / count as f64 }),
+                );
+            }
+        }
+
+        let mut sorted = params.values;
+        sorted.sort_by(f64::total_cmp);
+        let count = sorted.len();
+        let mean = sorted.iter().sum::<f64>() / count as f64;
+        let median = if count.is_multiple_of(2) {
+            (sorted[count / 2 - 1] + sorted[count / 2]) / 2.0
+        } else {
+            sorted[count / 2]
+        };
+        let variance = sorted.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / count as f64;
+        let summary = json!({
+            "count": count,
+            "mean": mean,
+            "median": median,
+            "min": sorted[0],
+            "max": sorted[count - 1],
+            "stdDev": variance.sqrt()
+        });
+        info!("Performed stats over {} values: {}", count, summary);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": summary.to_string()
+            }],
+            "structuredContent": summary,
+            "_meta": {
+                "exact": false
+            }
+        }))
+    }
+
+    fn handle_matmul(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: MatmulParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid matmul parameters: {}", e)))?;
+
+        let (rows, inner) = matrix_shape("a", &params.a).map_err(McpError::InvalidParams)?;
+        let (b_rows, cols) = matrix_shape("b", &params.b).map_err(McpError::InvalidParams)?;
+        if inner != b_rows {
+            return Err(McpError::InvalidParams(format!(
+                "Cannot multiply a {}x{} matrix by a {}x{} matrix: a has {} columns but b has {} rows",
+                rows, inner, b_rows, cols, inner, b_rows
+            )));
+        }
+
+        let mut product: Vec<Vec<f64>> = Vec::with_capacity(rows);
+        for row in &params.a {
+            if ctx.cancellation.is_cancelled() {
+                return Err(McpError::Cancelled);
+            }
+            let dot = |j: usize| row.iter().zip(&params.b).map(|(x, b_row)| x * b_row[j]).sum();
+            product.push((0..cols).map(dot).collect());
+        }
+        info!("Performed matmul: {}x{} × {}x{}", rows, inner, b_rows, cols);
+
+        let all_exact = |matrix: &[Vec<f64>]| matrix.iter().flatten().all(|&x| is_exact_integer(x));
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": json!(product).to_string()
+            }],
+            "structuredContent": {
+                "result": product,
+                "operation": "matmul"
+            },
+            "_meta": {
+                "exact": all_exact(&params.a) && all_exact(&params.b) && all_exact(&product)
+            }
+        }))
+    }
+
+    fn handle_dot(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: DotParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid dot parameters: {}", e)))?;
+
+        if params.a.len() != params.b.len() {
+            return Err(McpError::InvalidParams(format!(
+                "Cannot take the dot product of vectors of length {} and {}",
+                params.a.len(),
+                params.b.len()
+            )));
+        }
+        let result: f64 = params.a.iter().zip(&params.b).map(|(x, y)| x * y).sum();
+        info!("Performed dot over {} components = {}", params.a.len(), result);
+
+        let all_exact = params.a.iter().chain(&params.b).all(|&x| is_exact_integer(x));
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} · {} = {}", json!(params.a), json!(params.b), self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "dot"
+            },
+            "_meta": {
+                "exact": all_exact && is_exact_integer(result)
+            }
+        }))
+    }
+
+    fn handle_norm(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: NormParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid norm parameters: {}", e)))?;
+
+        let magnitudes = params.values.iter().map(|x| x.abs());
+        let p = params.p.unwrap_or(json!(2));
+        let (label, result) = match p.as_f64() {
+            Some(2.0) => ("L2", magnitudes.map(|x| x * x).sum::<f64>().sqrt()),
+            Some(1.0) => ("L1", magnitudes.sum()),
+            _ if p == "inf" => ("L∞", magnitudes.fold(0.0, f64::max)),
+            _ => return Err(McpError::InvalidParams(format!("p must be 1, 2 or \"inf\", got {}", p))),
+        };
+        info!("Performed {} norm over {} components = {}", label, params.values.len(), result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("‖{}‖{} = {}", json!(params.values), label, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "norm"
+            },
+            "_meta": {
+                "exact": label != "L2" && params.values.iter().all(|&x| is_exact_integer(x))
+            }
+        }))
+    }
+
+    fn handle_round(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: RoundParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid round parameters: {}", e)))?;
+
+        if !(-15..=15).contains(&params.decimals) {
+            return Err(McpError::InvalidParams(format!(
+                "decimals must be between -15 and 15, got {}",
+                params.decimals
+            )));
+        }
+
+        // Dividing by a whole power of ten for negative places avoids
+        // multiplying by an inexact 0.1, 0.01, ...
+        let result = if params.decimals >= 0 {
+            let factor = 10f64.powi(params.decimals);
+            (params.number * factor).round() / factor
+        } else {
+            let factor = 10f64.powi(-params.decimals);
+            (params.number / factor).round() * factor
+        };
+        info!("Performed round: round({}, {}) = {}", params.number, params.decimals, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("round({}, {}) = {}", params.number, params.decimals, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "round"
+            },
+            "_meta": {
+                "exact": params.decimals <= 0 && is_exact_integer(result)
+            }
+        }))
+    }
+
+    // `name = expression` stores the result as a session variable that later
+    // expressions in the same session can refer to.
+    fn handle_evaluate(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: EvaluateParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid evaluate parameters: {}", e)))?;
+
+        let mut vars = ctx.vars.lock().unwrap();
+        let result = expr::split_assignment(&params.expression).map_err(EvalError::from).and_then(|(target, source)| {
+            let expr = self.expr_cache.get_or_parse(source.trim())?;
+            let value = expr.eval(&|name| vars.get(name).and_then(Value::as_f64), self.expr_max_steps)?;
+            if let Some(target) = target {
+                vars.insert(target.to_string(), json!(value));
+            }
+            Ok(value)
+        });
+        let result = result.map_err(|e| match e {
+            // Running out of budget is a server-side limit, not bad input.
+            EvalError::BudgetExceeded => {
+                warn!("Evaluation of {:?} exceeded {} steps", params.expression, self.expr_max_steps);
+                McpError::InternalError("evaluation budget exceeded".to_string())
+                    .with_data(json!({ "maxSteps": self.expr_max_steps }))
+            }
+            e => McpError::InvalidParams(format!("Cannot evaluate {:?}: {}", params.expression, e)),
+        })?;
+        info!("Performed evaluate: {} = {}", params.expression, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} = {}", params.expression, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "evaluate"
+            },
+            "_meta": {
+                "exact": is_exact_integer(result)
+            }
+        }))
+    }
+
+    fn handle_gcd(&self, arguments: Value, _ctx: &ToolContext) -> Result<Value, McpError> {
+        let (a, b) = integer_pair("gcd", arguments).map_err(McpError::InvalidParams)?;
+
+        let result = gcd(a.unsigned_abs(), b.unsigned_abs());
+        info!("Performed gcd: gcd({}, {}) = {}", a, b, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("gcd({}, {}) = {}", a, b, result)
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "gcd"
+            },
+            "_meta": {
+                "exact": true
+            }
+        }))
+    }
+
+    fn handle_lcm(&self, arguments: Value, _ctx: &ToolContext) -> Result<Value, McpError> {
+        let (a, b) = integer_pair("lcm", arguments).map_err(McpError::InvalidParams)?;
+
+        // Dividing before multiplying keeps intermediates small; lcm(0, 0)
+        // is defined as 0.
+        let (x, y) = (a.unsigned_abs(), b.unsigned_abs());
+        let result = match gcd(x, y) {
+            0 => Some(0),
+            divisor => (x / divisor).checked_mul(y),
+        };
+        let result = result
+            .filter(|r| is_exact_integer(*r as f64))
+            .ok_or_else(|| McpError::InvalidParams(format!("lcm({}, {}) is too large to represent exactly", a, b)))?;
+        info!("Performed lcm: lcm({}, {}) = {}", a, b, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("lcm({}, {}) = {}", a, b, result)
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "lcm"
+            },
+            "_meta": {
+                "exact": true
+            }
+        }))
+    }
+
+    fn handle_convert_temperature(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: ConversionParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid convert_temperature parameters: {}", e)))?;
+
+        let result = convert_temperature(params.value, &params.from, &params.to).map_err(McpError::InvalidParams)?;
+        info!("Performed convert_temperature: {} {} in {} = {}", params.value, params.from, params.to, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!(
+                    "{} {} in {} = {}",
+                    params.value,
+                    params.from,
+                    params.to,
+                    self.format_result(result, ctx)
+                )
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "convert_temperature"
+            },
+            "_meta": {
+                "exact": false
+            }
+        }))
+    }
+
+    fn handle_convert_length(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: ConversionParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid convert_length parameters: {}", e)))?;
+
+        let result = convert_length(params.value, &params.from, &params.to).map_err(McpError::InvalidParams)?;
+        info!("Performed convert_length: {} {} in {} = {}", params.value, params.from, params.to, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!(
+                    "{} {} in {} = {}",
+                    params.value,
+                    params.from,
+                    params.to,
+                    self.format_result(result, ctx)
+                )
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "convert_length"
+            },
+            "_meta": {
+                "exact": false
+            }
+        }))
+    }
+
+    fn handle_clamp(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: ClampParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid clamp parameters: {}", e)))?;
+
+        if params.min > params.max {
+            return Err(McpError::InvalidParams(format!(
+                "min ({}) must not be greater than max ({})",
+                params.min,
+                params.max
+            )));
+        }
+
+        let result = params.value.clamp(params.min, params.max);
+        info!("Performed clamp: clamp({}, {}, {}) = {}", params.value, params.min, params.max, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!(
+                    "clamp({}, {}, {}) = {}",
+                    params.value,
+                    params.min,
+                    params.max,
+                    self.format_result(result, ctx)
+                )
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "clamp"
+            },
+            "_meta": {
+                "exact": true
+            }
+        }))
+    }
+
+    // Shared by `min` and `max`; the answer is always one of the inputs, so
+    // it's exact.
+    fn handle_extremum(
+        &self,
+        arguments: Value,
+        ctx: &ToolContext,
+        tool: &str,
+        pick: fn(f64, f64) -> f64,
+    ) -> Result<Value, McpError> {
+        let params: StatsParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid {} parameters: {}", tool, e)))?;
+
+        let Some(result) = params.values.iter().copied().reduce(pick) else {
+            return Err(McpError::InvalidParams(format!("Cannot take the {} of an empty list", tool)));
+        };
+        info!("Performed {}: {}({:?}) = {}", tool, tool, params.values, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{}({:?}) = {}", tool, params.values, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": tool
+            },
+            "_meta": {
+                "exact": true
+            }
+        }))
+    }
+
+    // Single-number tools that can't fail once the input parses.
+    fn handle_unary(
+        &self,
+        arguments: Value,
+        ctx: &ToolContext,
+        tool: &str,
+        op: fn(f64) -> f64,
+        exact: bool,
+    ) -> Result<Value, McpError> {
+        let params: SquareParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid {} parameters: {}", tool, e)))?;
+
+        let result = op(params.number);
+        info!("Performed {}: {}({}) = {}", tool, tool, params.number, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{}({}) = {}", tool, params.number, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": tool
+            },
+            "_meta": {
+                "exact": exact
+            }
+        }))
+    }
+
+    fn handle_compound_interest(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: CompoundInterestParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid compound_interest parameters: {}", e)))?;
+
+        for (name, value) in [("principal", params.principal), ("rate", params.rate), ("years", params.years)] {
+            if value < 0.0 {
+                return Err(McpError::InvalidParams(format!("{} must be non-negative, got {}", name, value)));
+            }
+        }
+        if params.times_per_year < 1.0 {
+            return Err(McpError::InvalidParams(format!(
+                "times_per_year must be at least 1, got {}",
+                params.times_per_year
+            )));
+        }
+
+        let n = params.times_per_year;
+        let result = params.principal * (1.0 + params.rate / n).powf(n * params.years);
+        info!(
+            "Performed compound_interest: {} at {} compounded {} times a year for {} years = {}",
+            params.principal, params.rate, n, params.years, result
+        );
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!(
+                    "{} at {} compounded {} times a year for {} years = {}",
+                    params.principal,
+                    params.rate,
+                    n,
+                    params.years,
+                    self.format_result(result, ctx)
+                )
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "compound_interest"
+            },
+            "_meta": {
+                "exact": false
+            }
+        }))
+    }
+
+    fn handle_random(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: RandomParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid random parameters: {}", e)))?;
+
+        if params.min > params.max {
+            return Err(McpError::InvalidParams(format!(
+                "min ({}) must not be greater than max ({})",
+                params.min, params.max
+            )));
+        }
+        // gen_range panics when the width of the range overflows f64.
+        if !(params.max - params.min).is_finite() {
+            return Err(McpError::InvalidParams("max - min is too large to sample from".to_string()));
+        }
+
+        let mut rng = match params.seed {
+            Some(seed) => StdRng::seed_from_u64(seed),
+            None => StdRng::from_entropy(),
+        };
+        // gen_range panics on an empty range; min == max has one answer.
+        let result = if params.min < params.max {
+            rng.gen_range(params.min..params.max)
+        } else {
+            params.min
+        };
+        info!("Performed random: [{}, {}) seed {:?} = {}", params.min, params.max, params.seed, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("random({}, {}) = {}", params.min, params.max, self.format_result(result, ctx))
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "random"
+            },
+            "_meta": {
+                "exact": false
+            }
+        }))
+    }
+
+    fn handle_convert_base(&self, arguments: Value, _ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: ConvertBaseParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid convert_base parameters: {}", e)))?;
+
+        let base = |name: &str, value: f64| {
+            if value.fract() != 0.0 || !(2.0..=36.0).contains(&value) {
+                return Err(McpError::InvalidParams(format!("{} must be an integer from 2 to 36, got {}", name, value)));
+            }
+            Ok(value as u32)
+        };
+        let (from_base, to_base) = (base("from_base", params.from_base)?, base("to_base", params.to_base)?);
+
+        // A number is taken as the digits it's written with, so 1010 in
+        // base 2 is ten just like "1010".
+        let digits = match &params.value {
+            Value::String(digits) => digits.clone(),
+            Value::Number(n) => match n.as_i64() {
+                Some(n) => n.to_string(),
+                None => return Err(McpError::InvalidParams(format!("value must be an integer, got {}", n))),
+            },
+            other => {
+                return Err(McpError::InvalidParams(format!("value must be an integer or a string, got {}", other)));
+            }
+        };
+        let value = parse_radix(&digits, from_base).map_err(McpError::InvalidParams)?;
+        let result = format_radix(value, to_base);
+        info!("Performed convert_base: {} in base {} to base {} = {}", digits, from_base, to_base, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} (base {}) in base {} = {}", digits, from_base, to_base, result)
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "convert_base"
+            },
+            "_meta": {
+                "exact": true
+            }
+        }))
+    }
+
+    fn handle_bitwise(
+        &self,
+        arguments: Value,
+        tool: &str,
+        symbol: &str,
+        op: fn(i64, i64) -> i64,
+    ) -> Result<Value, McpError> {
+        let (a, b) = integer_pair(tool, arguments).map_err(McpError::InvalidParams)?;
+
+        let result = op(a, b);
+        info!("Performed {}: {} {} {} = {}", tool, a, symbol, b, result);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} {} {} = {}", a, symbol, b, result)
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": tool
+            },
+            "_meta": {
+                "exact": true
+            }
+        }))
+    }
+
+    fn handle_shift(&self, arguments: Value) -> Result<Value, McpError> {
+        let params: ShiftParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid shift parameters: {}", e)))?;
+        let value = exact_integer("value", &params.value).map_err(McpError::InvalidParams)?;
+        let amount = exact_integer("amount", &params.amount).map_err(McpError::InvalidParams)?;
+        if amount.abs() > 63 {
+            return Err(McpError::InvalidParams(format!("Cannot shift by {} bits; the limit is 63", amount)));
+        }
+
+        // Right shifts are arithmetic. A left shift must not push set bits
+        // (or the sign) out of the 64-bit range.
+        let (result, symbol) = if amount >= 0 {
+            let shifted = value << amount;
+            if shifted >> amount != value {
+                return Err(McpError::InvalidParams(format!("{} << {} overflows a 64-bit integer", value, amount)));
+            }
+            (shifted, "<<")
+        } else {
+            (value >> -amount, ">>")
+        };
+        let text = format!("{} {} {} = {}", value, symbol, amount.abs(), result);
+        info!("Performed shift: {}", text);
+
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": text
+            }],
+            "structuredContent": {
+                "result": result,
+                "operation": "shift"
+            },
+            "_meta": {
+                "exact": true
+            }
+        }))
+    }
+
+    fn handle_range(&self, arguments: Value, ctx: &ToolContext) -> Result<Value, McpError> {
+        let params: RangeParams = serde_json::from_value(arguments)
+            .map_err(|e| McpError::InvalidParams(format!("Invalid range parameters: {}", e)))?;
+
+        let label = format!("range({}, {}, {})", params.start, params.stop, params.step);
+        if params.step == 0.0 {
+            return Err(McpError::InvalidParams(format!("{}: step must not be zero", label)));
+        }
+        // NaN here means an infinite bound or step, which is too long too.
+        let count = ((params.stop - params.start) / params.step).ceil().max(0.0);
+        if count.is_nan() || count > self.range_max_count as f64 {
+            return Err(McpError::InvalidParams(format!(
+                "{} would produce more than {} numbers",
+                label, self.range_max_count
+            )));
+        }
+        // Multiplying rather than adding step keeps rounding from piling up.
+        let values: Vec<f64> = (0..count as usize).map(|i| params.start + i as f64 * params.step).collect();
+        let exact = values.iter().all(|value| is_exact_integer(*value));
+        info!("Performed range: {} produced {} numbers", label, values.len());
+
+        if ctx.chunks.is_streaming() {
+            let chunks = values.chunks(RANGE_CHUNK_LEN);
+            let chunk_count = chunks.len();
+            for (index, chunk) in chunks.enumerate() {
+                ctx.chunks.send(index, json!(chunk));
+            }
+            return Ok(json!({
+                "content": [{
+                    "type": "text",
+                    "text": format!("{}: {} numbers sent in {} chunks", label, values.len(), chunk_count)
+                }],
+                "structuredContent": {
+                    "count": values.len(),
+                    "chunks": chunk_count,
+                    "operation": "range"
+                },
+                "_meta": {
+                    "exact": exact
+                }
+            }));
+        }
+
+        let text: Vec<String> = values.iter().map(|value| self.format_result(*value, ctx)).collect();
+        Ok(json!({
+            "content": [{
+                "type": "text",
+                "text": format!("{} = [{}]", label, text.join(", "))
+            }],
+            "structuredContent": {
+                "result": values,
+                "operation": "range"
+            },
+            "_meta": {
+                "exact": exact
+            }
+        }))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct SseParams {
+    // Per-connection keep-alive interval in seconds, for clients behind
+    // proxies with shorter idle timeouts than the server default.
+    keepalive: Option<u64>,
+}
+
+// SSE transport: each connection gets its own session, whose id comes back
+// in the Mcp-Session-Id header, and every message pushed to that session is
+// sent as a `message` event. The session ends when the client disconnects.
+async fn sse_handler(
+    State(shared): State<Arc<McpServer>>,
+    RpcQuery(params): RpcQuery<SseParams>,
+    _headers: HeaderMap,
+) -> Response {
+    let mut keep_alive = shared.sse_keep_alive.clone();
+    if let Some(secs) = params.keepalive.filter(|secs| *secs > 0) {
+        keep_alive = keep_alive.interval(Duration::from_secs(secs));
+    }
+
+    let session_id = shared.sessions.connect().await;
+    info!("SSE connection established (session {})", session_id);
+    let (sender, queued) = tokio::sync::mpsc::unbounded_channel::<Value>();
+    let queued = Arc::new(Mutex::new(queued));
+    let stream = SseStream {
+        sender: sender.clone(),
+        queued: Arc::downgrade(&queued),
+    };
+    shared.sse_streams.lock().unwrap().insert(session_id.clone(), stream);
+
+    // The receiver is dropped with the response body, which closes the channel.
+    let server = Arc::clone(&shared);
+    let closed_session = session_id.clone();
+    tokio::spawn(async move {
+        sender.closed().await;
+        server.sse_streams.lock().unwrap().remove(&closed_session);
+        server.sessions.remove(&closed_session).await;
+        info!("SSE connection closed (session {})", closed_session);
+    });
+
+    // The lock is only held while polling, so `sse_queue_len` can read the
+    // backlog in between.
+    let messages = stream::poll_fn(move |cx| queued.lock().unwrap().poll_recv(cx));
+    let events = messages.map(move |message| {
+        let (event, data) = shared.sse_event_data(&message);
+        Ok::<_, Infallible>(axum::response::sse::Event::default().event(event).data(data))
+    });
+    ([(SESSION_HEADER, session_id)], Sse::new(events).keep_alive(keep_alive)).into_response()
+}
+
+// An open `/sse` stream. The response body owns the receiver; holding it
+// weakly here lets it drop with the body, which closes the channel.
+struct SseStream {
+    sender: UnboundedSender<Value>,
+    queued: std::sync::Weak<Mutex<tokio::sync::mpsc::UnboundedReceiver<Value>>>,
+}
+
+fn sse_keep_alive(config: &Config) -> KeepAlive {
+    let keep_alive = KeepAlive::new().interval(config.sse_keepalive);
+    match &config.sse_keepalive_text {
+        Some(text) => keep_alive.text(text.as_str()),
+        None => keep_alive,
+    }
+}
+
+// WebSocket transport: one JSON-RPC request per text frame, answered with a
+// text frame. Each connection is its own session, and server notifications
+// for a request are written to the socket as they happen.
+async fn ws_handler(
+    State(server): State<Arc<McpServer>>,
+    upgrade: Result<WebSocketUpgrade, WebSocketUpgradeRejection>,
+) -> Response {
+    match upgrade {
+        Ok(upgrade) => upgrade.on_upgrade(move |socket| serve_websocket(server, socket)),
+        Err(rejection) => RpcRejection::from(rejection).into_response(),
+    }
+}
+
+async fn serve_websocket(server: Arc<McpServer>, socket: WebSocket) {
+    let session_id = server.sessions.connect().await;
+    info!("WebSocket connection established (session {})", session_id);
+
+    let (mut sink, mut frames) = futures::StreamExt::split(socket);
+    let (outgoing, mut queued) = tokio::sync::mpsc::unbounded_channel::<Value>();
+    let writer = tokio::spawn(async move {
+        while let Some(message) = queued.recv().await {
+            if sink.send(Message::Text(message.to_string())).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    let mut in_flight = tokio::task::JoinSet::new();
+    while let Some(Ok(frame)) = frames.next().await {
+        let text = match frame {
+            Message::Text(text) => text,
+            Message::Close(_) => break,
+            // axum answers pings itself; binary frames aren't part of MCP.
+            Message::Ping(_) | Message::Pong(_) | Message::Binary(_) => continue,
+        };
+
+        let request: JsonRpcRequest = match serde_json::from_str(&text) {
+            Ok(request) => request,
+            Err(e) => {
+                let response = JsonRpcResponse::error(None, McpError::ParseError(format!("Parse error: {}", e)));
+                let _ = outgoing.send(json!(response));
+                continue;
+            }
+        };
+
+        debug!("Received WebSocket request: {:?}", request);
+        // Notifications are handled in order, so `notifications/cancelled`
+        // reaches a call that is still running. Requests each get a task and
+        // answer through `outgoing` once done, so a slow tool doesn't hold
+        // up the rest of the connection.
+        if request.id.is_none() && request.method.starts_with("notifications/") {
+            server
+                .dispatch_with_notifications(Some(session_id.clone()), request, Some(outgoing.clone()))
+                .await;
+            continue;
+        }
+        while in_flight.try_join_next().is_some() {}
+        let server = Arc::clone(&server);
+        let session_id = session_id.clone();
+        let outgoing = outgoing.clone();
+        in_flight.spawn(async move {
+            let response = server
+                .dispatch_with_notifications(Some(session_id), request, Some(outgoing.clone()))
+                .await;
+            let _ = outgoing.send(json!(response));
+        });
+    }
+
+    // Nobody is left to read the answers of calls still running.
+    in_flight.shutdown().await;
+    drop(outgoing);
+    let _ = writer.await;
+    server.sessions.remove(&session_id).await;
+    info!("WebSocket connection closed (session {})", session_id);
+}
+
+// Non-null ids used by more than one request in `batch`, in the order they
+// are first repeated. Notifications (no id) and null ids are skipped.
+fn duplicate_ids(batch: &[Value]) -> Vec<Value> {
+    let mut seen = HashSet::new();
+    let mut duplicates = Vec::new();
+    for id in batch.iter().filter_map(|request| request.get("id")).filter(|id| !id.is_null()) {
+        if !seen.insert(id.to_string()) && !duplicates.contains(id) {
+            duplicates.push(id.clone());
+        }
+    }
+    duplicates
+}
+
+// JSON-RPC endpoint for MCP
+async fn jsonrpc_handler(
+    State(server): State<Arc<McpServer>>,
+    principal: Option<Extension<Principal>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    // A valid object followed by junk gets a specific message rather than
+    // serde's generic "trailing characters" error.
+    let recorded_request = server.recorder.as_ref().and_then(|_| serde_json::from_slice::<Value>(&body).ok());
+    let mut deserializer = serde_json::Deserializer::from_slice(&body);
+    let request = match JsonRpcRequest::deserialize(&mut deserializer) {
+        Ok(_) if deserializer.end().is_err() => {
+            return parse_error("trailing data after JSON-RPC object");
+        }
+        Ok(request) => request,
+        Err(e) if e.is_syntax() || e.is_eof() => return parse_error(&format!("Parse error: {}", e)),
+        // Batches aren't supported; say so instead of serde's type error,
+        // naming any ids the batch reuses since those would be ambiguous
+        // even where batches are accepted.
+        Err(_) if body.trim_ascii_start().starts_with(b"[") => {
+            let batch: Vec<Value> = serde_json::from_slice(&body).unwrap_or_default();
+            let duplicates = duplicate_ids(&batch);
+            if duplicates.is_empty() {
+                return invalid_request("Invalid Request: batch requests are not supported; send one request per call");
+            }
+            let ids: Vec<String> = duplicates.iter().map(Value::to_string).collect();
+            return invalid_request(&format!(
+                "Invalid Request: duplicate ids in batch: {}; batch requests are not supported either, \
+                 send one request per call",
+                ids.join(", ")
+            ));
+        }
+        Err(e) => return invalid_request(&format!("Invalid Request: {}", e)),
+    };
+
+    match principal {
+        Some(Extension(principal)) => debug!("Received request from {}: {:?}", principal.id, request),
+        None => debug!("Received request: {:?}", request),
+    }
+    // Clients without a session get one when they initialize; the id is
+    // returned in the Mcp-Session-Id header for them to send back.
+    let mut session_id = headers
+        .get(SESSION_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string);
+    let new_session = session_id.is_none() && request.method == "initialize";
+    if new_session {
+        session_id = Some(server.sessions.create().await);
+    }
+
+    // The header form of `_meta.idempotencyKey`; an explicit `_meta` key wins.
+    let mut request = request;
+    if let Some(key) = headers.get(IDEMPOTENCY_HEADER).and_then(|v| v.to_str().ok()) {
+        if request.method == "tools/call" {
+            if let Some(Value::Object(params)) = request.params.as_mut() {
+                if let Value::Object(meta) = params.entry("_meta").or_insert_with(|| json!({})) {
+                    meta.entry("idempotencyKey").or_insert_with(|| json!(key));
+                }
+            }
+        }
+    }
+
+    // Notifications get no JSON-RPC response, just an acknowledgement.
+    let is_notification = request.id.is_none() && request.method.starts_with("notifications/");
+    // Streamable HTTP clients ask for an SSE stream instead: it carries any
+    // notifications for the request as they happen, then the response.
+    let wants_stream = headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("text/event-stream"));
+    let tool = request.params.as_ref().and_then(|p| p.get("name")).and_then(Value::as_str);
+    let span = telemetry::request_span(&request.method, tool.filter(|_| request.method == "tools/call"));
+    let body = if wants_stream && !is_notification {
+        span.in_scope(|| event_stream(Arc::clone(&server), session_id.clone(), request, recorded_request))
+    } else {
+        let response = server.dispatch(session_id.clone(), request).instrument(span).await;
+        server.record_exchange(recorded_request.as_ref(), Some(&response).filter(|_| !is_notification));
+        if is_notification {
+            return StatusCode::ACCEPTED.into_response();
+        }
+        debug!("Sending response: {:?}", response);
+        server.json_body(&response).into_response()
+    };
+    match session_id {
+        Some(session_id) if new_session => ([(SESSION_HEADER, session_id)], body).into_response(),
+        _ => body.into_response(),
+    }
+}
+
+// Runs `request` in the background and streams its notifications and then
+// its response as SSE `message` events, closing the stream after the response.
+fn event_stream(
+    server: Arc<McpServer>,
+    session_id: Option<String>,
+    request: JsonRpcRequest,
+    recorded_request: Option<Value>,
+) -> Response {
+    let (sink, queued) = tokio::sync::mpsc::unbounded_channel::<Value>();
+    let responses = sink.clone();
+    let dispatcher = Arc::clone(&server);
+    tokio::spawn(
+        async move {
+            let response = dispatcher.dispatch_with_notifications(session_id, request, Some(sink)).await;
+            dispatcher.record_exchange(recorded_request.as_ref(), Some(&response));
+            let _ = responses.send(json!(response));
+        }
+        .instrument(tracing::Span::current()),
+    );
+
+    // Notifications carry a `method` and the response doesn't. A timed-out
+    // tool may still hold a sender, so the stream can't wait for the
+    // channel to close.
+    let messages = stream::unfold((queued, false), |(mut queued, done)| async move {
+        if done {
+            return None;
+        }
+        let message = queued.recv().await?;
+        let done = message.get("method").is_none();
+        Some((message, (queued, done)))
+    });
+    let keep_alive = server.sse_keep_alive.clone();
+    let events = messages.map(move |message| {
+        let (event, data) = server.sse_event_data(&message);
+        Ok::<_, Infallible>(axum::response::sse::Event::default().data(data).event(event))
+    });
+    Sse::new(events).keep_alive(keep_alive).into_response()
+}
+
+// REST shortcut for a single tool call: `POST /tools/{name}` with the
+// arguments as a JSON object or as form fields, merged over any query-string
+// parameters. Form and query values arrive as strings, which the numeric
+// parameters already accept.
+async fn rest_tool_handler(
+    State(server): State<Arc<McpServer>>,
+    RpcPath(name): RpcPath<String>,
+    RpcQuery(query): RpcQuery<HashMap<String, String>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let mut arguments: serde_json::Map<String, Value> =
+        query.into_iter().map(|(k, v)| (k, Value::String(v))).collect();
+
+    let content_type = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or("")
+        .to_ascii_lowercase();
+    if content_type.starts_with("application/x-www-form-urlencoded") {
+        match serde_urlencoded::from_bytes::<Vec<(String, String)>>(&body) {
+            Ok(fields) => arguments.extend(fields.into_iter().map(|(k, v)| (k, Value::String(v)))),
+            Err(e) => return parse_error(&format!("Invalid form body: {}", e)),
+        }
+    } else if content_type.starts_with("application/json") || (content_type.is_empty() && !body.is_empty()) {
+        match serde_json::from_slice::<Value>(&body) {
+            Ok(Value::Object(fields)) => arguments.extend(fields),
+            Ok(_) => return invalid_request("Tool arguments must be a JSON object"),
+            Err(e) => return parse_error(&format!("Parse error: {}", e)),
+        }
+    } else if !body.is_empty() {
+        return (
+            StatusCode::UNSUPPORTED_MEDIA_TYPE,
+            Json(json!({
+                "error": format!("unsupported content type {:?}; use application/json or application/x-www-form-urlencoded", content_type)
+            })),
+        )
+            .into_response();
+    }
+
+    let request = JsonRpcRequest {
+        jsonrpc: "2.0".to_string(),
+        id: None,
+        method: "tools/call".to_string(),
+        params: Some(json!({ "name": name, "arguments": arguments })),
+    };
+    let response = server.dispatch(None, request).await;
+    match (response.result, response.error) {
+        (Some(result), _) => server.json_body(&result).into_response(),
+        (None, error) => (StatusCode::BAD_REQUEST, Json(json!({ "error": error }))).into_response(),
+    }
+}
+
+// Routes served by `app`, reported back on 404.
+const ENDPOINTS: &[&str] = &[
+    "GET /sse",
+    "GET /ws",
+    "POST /mcp",
+    "POST /tools/{name}",
+    "GET /audit",
+    "GET /debug/sessions",
+    "GET /health",
+    "GET /health/ready",
+    "GET /health/detailed",
+    "GET /readyz",
+    "GET /metrics",
+    "GET /version",
+];
+
+async fn not_found(uri: axum::http::Uri) -> (StatusCode, Json<Value>) {
+    (
+        StatusCode::NOT_FOUND,
+        Json(json!({
+            "error": "not found",
+            "path": uri.path(),
+            "data": ENDPOINTS
+        })),
+    )
+}
+
+fn parse_error(message: &str) -> Response {
+    let response = JsonRpcResponse::error(None, McpError::ParseError(message.to_string()));
+    (StatusCode::BAD_REQUEST, Json(response)).into_response()
+}
+
+// Well-formed JSON that isn't a JSON-RPC request object.
+fn invalid_request(message: &str) -> Response {
+    let response = JsonRpcResponse::error(None, McpError::InvalidRequest(message.to_string()));
+    (StatusCode::BAD_REQUEST, Json(response)).into_response()
+}
+
+// Extractor failures on MCP routes (a malformed query string or path, a
+// missing WebSocket upgrade), answered as JSON-RPC errors instead of axum's
+// plain-text rejections.
+struct RpcRejection {
+    status: StatusCode,
+    error: McpError,
+}
+
+impl IntoResponse for RpcRejection {
+    fn into_response(self) -> Response {
+        let response = JsonRpcResponse::error(None, self.error);
+        (self.status, Json(response)).into_response()
+    }
+}
+
+impl From<QueryRejection> for RpcRejection {
+    fn from(rejection: QueryRejection) -> Self {
+        Self {
+            status: rejection.status(),
+            error: McpError::InvalidParams(rejection.body_text()),
+        }
+    }
+}
+
+impl From<PathRejection> for RpcRejection {
+    fn from(rejection: PathRejection) -> Self {
+        Self {
+            status: rejection.status(),
+            error: McpError::InvalidParams(rejection.body_text()),
+        }
+    }
+}
+
+impl From<WebSocketUpgradeRejection> for RpcRejection {
+    fn from(rejection: WebSocketUpgradeRejection) -> Self {
+        Self {
+            status: rejection.status(),
+            error: McpError::InvalidRequest(rejection.body_text()),
+        }
+    }
+}
+
+// `Query` rejecting with `RpcRejection`.
+struct RpcQuery<T>(T);
+
+#[async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequestParts<S> for RpcQuery<T> {
+    type Rejection = RpcRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+// `Path` rejecting with `RpcRejection`.
+struct RpcPath<T>(T);
+
+#[async_trait]
+impl<T: DeserializeOwned + Send, S: Send + Sync> FromRequestParts<S> for RpcPath<T> {
+    type Rejection = RpcRejection;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Path(value) = Path::<T>::from_request_parts(parts, state).await?;
+        Ok(Self(value))
+    }
+}
+
+// Health check endpoint
+async fn health(State(server): State<Arc<McpServer>>) -> (StatusCode, Json<Value>) {
+    let (status, sse) = match server.sessions.self_check().await {
+        Ok(()) => (StatusCode::OK, "ok".to_string()),
+        Err(e) => (StatusCode::SERVICE_UNAVAILABLE, e),
+    };
+
+    (
+        status,
+        Json(json!({
+            "status": if status.is_success() { "healthy" } else { "unhealthy" },
+            "server": "mcp-calculator-server",
+            "version": env!("CARGO_PKG_VERSION"),
+            "sse": sse,
+            "lastClient": server.sessions.last_client(),
+            "uptimeSeconds": server.start_time.elapsed().as_secs()
+        })),
+    )
+}
+
+// `/health` plus resource figures for capacity planning. Process figures
+// the platform doesn't expose are left out rather than reported as zero.
+async fn health_detailed(State(server): State<Arc<McpServer>>) -> (StatusCode, Json<Value>) {
+    let (status, Json(mut body)) = health(State(server)).await;
+    body["runtimeWorkers"] = json!(tokio::runtime::Handle::current().metrics().num_workers());
+    if let Some(stats) = process_stats() {
+        body["memoryBytes"] = json!(stats.memory_bytes);
+        body["threads"] = json!(stats.threads);
+    }
+    (status, Json(body))
+}
+
+struct ProcessStats {
+    memory_bytes: u64,
+    threads: u64,
+}
+
+// Resident memory and OS thread count from `/proc/self/status`.
+#[cfg(target_os = "linux")]
+fn process_stats() -> Option<ProcessStats> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let field = |name: &str| {
+        let line = status.lines().find(|line| line.starts_with(name))?;
+        line[name.len()..].split_whitespace().next()?.parse::<u64>().ok()
+    };
+    Some(ProcessStats {
+        memory_bytes: field("VmRSS:")? * 1024,
+        threads: field("Threads:")?,
+    })
+}
+
+#[cfg(not(target_os = "linux"))]
+fn process_stats() -> Option<ProcessStats> {
+    None
+}
+
+// Deeper readiness than `/health`: runs an `add` call through the blocking
+// pool the way `tools/call` does and checks the answer. Bypasses metrics and
+// the audit log, which should only reflect client traffic.
+async fn health_ready(State(server): State<Arc<McpServer>>) -> (StatusCode, Json<Value>) {
+    let sse = server.sessions.self_check().await;
+
+    let probe = Arc::clone(&server);
+    let call = server.pool.run(move || {
+        let ctx = ToolContext::new(None, None, SessionVars::default());
+        probe.run_tool("add", json!({ "a": 2, "b": 3 }), &ctx)
+    });
+    let dispatch = match tokio::time::timeout(server.tool_timeout, call).await {
+        Ok(Ok(Ok(result))) if result["structuredContent"]["result"] == json!(5.0) => Ok(()),
+        Ok(Ok(Ok(result))) => Err(format!("add returned an unexpected result: {}", result)),
+        Ok(Ok(Err(e))) => Err(e.to_string()),
+        Ok(Err(e)) => Err(e.to_string()),
+        Err(_) => Err(format!("add timed out after {} ms", server.tool_timeout.as_millis())),
+    };
+
+    let ready = sse.is_ok() && dispatch.is_ok();
+    if let Err(e) = &dispatch {
+        warn!("Readiness check failed: tool dispatch: {}", e);
+    }
+    let check = |outcome: Result<(), String>| outcome.err().unwrap_or_else(|| "ok".to_string());
+    (
+        if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE },
+        Json(json!({
+            "status": if ready { "ready" } else { "unavailable" },
+            "checks": {
+                "sse": check(sse),
+                "dispatch": check(dispatch)
+            }
+        })),
+    )
+}
+
+// Readiness for load balancers: a saturated runtime can't serve requests in
+// time even though the process is healthy, so this fails while a freshly
+// spawned task waits too long to be scheduled.
+async fn readyz(State(server): State<Arc<McpServer>>) -> (StatusCode, Json<Value>) {
+    let threshold = server.ready_max_latency;
+    let start = Instant::now();
+    let probe = tokio::spawn(async move { start.elapsed() });
+    // Past the threshold the answer is known, so don't wait any longer.
+    let latency = match tokio::time::timeout(threshold, probe).await {
+        Ok(Ok(latency)) => latency,
+        _ => start.elapsed(),
+    };
+    readiness(latency, threshold)
+}
+
+fn readiness(latency: Duration, threshold: Duration) -> (StatusCode, Json<Value>) {
+    let ready = latency <= threshold;
+    if !ready {
+        warn!("Runtime saturated: probe waited {:?} to be scheduled", latency);
+    }
+    (
+        if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE },
+        Json(json!({
+            "status": if ready { "ready" } else { "saturated" },
+            "schedulingLatencyMs": latency.as_secs_f64() * 1000.0,
+            "thresholdMs": threshold.as_millis() as u64
+        })),
+    )
+}
+
+// Build identification, for checking what a deployment is running.
+async fn version() -> Json<Value> {
+    Json(json!({
+        "name": env!("CARGO_PKG_NAME"),
+        "version": env!("CARGO_PKG_VERSION")
+    }))
+}
+
+// Recent tool calls, oldest first; signed when MCP_AUDIT_HMAC_KEY is set.
+async fn audit_handler(State(server): State<Arc<McpServer>>) -> Json<Value> {
+    Json(json!({ "entries": server.audit.entries() }))
+}
+
+// Stored sessions, for diagnosing stuck clients. `queuedMessages` counts
+// messages the client hasn't read yet from the session's `/sse` stream.
+async fn debug_sessions(State(server): State<Arc<McpServer>>) -> Json<Value> {
+    let sessions: Vec<Value> = server
+        .sessions
+        .list()
+        .await
+        .into_iter()
+        .map(|session| {
+            json!({
+                "id": session.id,
+                "createdAt": session.created_at,
+                "lastActiveAt": session.last_active_at,
+                "protocolVersion": session.protocol_version,
+                "clientInfo": session.client_info,
+                "queuedMessages": server.sse_queue_len(&session.id)
+            })
+        })
+        .collect();
+    Json(json!({ "sessions": sessions }))
+}
+
+// Prometheus scrape endpoint
+async fn metrics_handler(State(server): State<Arc<McpServer>>) -> impl IntoResponse {
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        server.metrics.render(),
+    )
+}
+
+fn app(server: Arc<McpServer>, config: &Config) -> Router {
+    app_with_authenticator(server, config, default_authenticator(config))
+}
+
+fn default_authenticator(config: &Config) -> Option<Arc<dyn Authenticator>> {
+    if let Some(token) = &config.auth_token {
+        return Some(Arc::new(BearerTokenAuthenticator::new(token.clone())));
+    }
+    config
+        .api_key
+        .as_ref()
+        .map(|key| Arc::new(ApiKeyAuthenticator::new(key.clone())) as Arc<dyn Authenticator>)
+}
+
+// Builds the router with a custom authentication scheme guarding the MCP
+// routes; `None` leaves them open. `/health` is never authenticated.
+fn app_with_authenticator(
+    server: Arc<McpServer>,
+    config: &Config,
+    authenticator: Option<Arc<dyn Authenticator>>,
+) -> Router {
+    let mut mcp_routes = Router::new()
+        .route("/sse", get(sse_handler))
+        .route("/ws", get(ws_handler))
+        .route(
+            "/mcp",
+            post(jsonrpc_handler).layer(DefaultBodyLimit::max(config.max_body_bytes)),
+        )
+        .route(
+            "/tools/:name",
+            post(rest_tool_handler).layer(DefaultBodyLimit::max(config.max_body_bytes)),
+        );
+    // Session ids are the only session credential, so they are never listed
+    // to unauthenticated callers: without an authenticator these routes are
+    // off. Audit entries carry session ids and raw arguments too.
+    if let Some(authenticator) = authenticator {
+        mcp_routes = mcp_routes
+            .route("/audit", get(audit_handler))
+            .route("/debug/sessions", get(debug_sessions))
+            .route_layer(middleware::from_fn_with_state(authenticator, auth::require_auth));
+    }
+
+    let routes = limit_concurrency(mcp_routes, config)
+        .route("/health", get(health))
+        .route("/health/ready", get(health_ready))
+        .route("/health/detailed", get(health_detailed))
+        .route("/readyz", get(readyz))
+        .route("/metrics", get(metrics_handler))
+        .route("/version", get(version))
+        .fallback(not_found);
+    with_middleware(routes, config).with_state(server)
+}
+
+// Caps the requests `router` handles at once. `Router::layer` layers each
+// route separately, so the layer must share one semaphore across them for
+// the cap to be global. Applied before `with_middleware`, the limit sits
+// inside the timeout, so a request queued for too long gets the usual 408.
+// Health, readiness and metrics routes are added afterwards and stay
+// outside it, so probes still answer while the server is saturated.
+fn limit_concurrency(router: Router<Arc<McpServer>>, config: &Config) -> Router<Arc<McpServer>> {
+    router.layer(GlobalConcurrencyLimitLayer::new(config.max_concurrency))
+}
+
+// Layers shared by every route. CORS wraps the timeout so that timeout
+// responses still carry CORS headers, and request logging wraps everything
+// so even rejected requests get an id.
+fn with_middleware(router: Router<Arc<McpServer>>, config: &Config) -> Router<Arc<McpServer>> {
+    let allow_origin = match &config.cors_origins {
+        None => AllowOrigin::any(),
+        Some(origins) => AllowOrigin::list(origins.clone()),
+    };
+
+    let router = if config.json_charset {
+        router.layer(middleware::map_response(add_json_charset))
+    } else {
+        router
+    };
+    router
+        .layer(TimeoutLayer::new(config.request_timeout))
+        .layer(
+            CorsLayer::new()
+                .allow_origin(allow_origin)
+                .allow_methods(tower_http::cors::Any)
+                .allow_headers(tower_http::cors::Any)
+                .expose_headers([header::HeaderName::from_static(logging::REQUEST_ID_HEADER)]),
+        )
+        .layer(middleware::from_fn(logging::log_requests))
+}
+
+// Spells out the charset on plain `application/json` responses, for clients
+// that insist on it. JSON is always UTF-8, so the header is all that changes.
+async fn add_json_charset(mut response: Response) -> Response {
+    if response.headers().get(header::CONTENT_TYPE).is_some_and(|v| v == "application/json") {
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            header::HeaderValue::from_static("application/json; charset=utf-8"),
+        );
+    }
+    response
+}
+
+// Main application
+#[tokio::main]
+async fn main() {
+    let cli = <cli::Cli as clap::Parser>::parse();
+    // `--replay <file> [url]` checks a running server against a recording
+    // made with MCP_RECORD_PATH instead of starting one.
+    if let Some([file, rest @ ..]) = cli.replay.as_deref() {
+        let url = rest.first().map_or("http://127.0.0.1:3000/mcp", String::as_str);
+        match recording::replay(std::path::Path::new(file), url).await {
+            Ok(0) => println!("All responses match"),
+            Ok(mismatches) => {
+                println!("{} response(s) differ", mismatches);
+                std::process::exit(1);
+            }
+            Err(e) => {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+
+    let config = Config::from_env(&cli);
+    let traces = match config.otlp_endpoint.as_deref().map(telemetry::tracer_provider).transpose() {
+        Ok(traces) => traces,
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let log_level = Arc::new(logging::init(config.log_level, config.log_format, traces.as_ref()));
+    #[cfg(unix)]
+    if let Err(e) = logging::cycle_on_sighup(Arc::clone(&log_level)) {
+        warn!("Could not install SIGHUP handler: {}", e);
+    }
+    numeric::set_max_number_string_len(config.max_number_string_len);
+    let server = match McpServer::from_config(&config) {
+        Ok(server) => Arc::new(server.with_log_level(Arc::clone(&log_level))),
+        Err(e) => {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        }
+    };
+    let app = app(Arc::clone(&server), &config);
+
+    let listener = match tokio::net::TcpListener::bind(config.bind_address).await {
+        Ok(listener) => listener,
+        Err(e) => {
+            eprintln!("cannot listen on {}: {}", config.bind_address, e);
+            std::process::exit(1);
+        }
+    };
+
+    // SocketAddr brackets IPv6 hosts, as URLs need.
+    let base_url = format!("http://{}", config.bind_address);
+    info!("Calculator MCP Server running on {}", base_url);
+    info!("MCP JSON-RPC endpoint: {}/mcp", base_url);
+    info!("SSE endpoint: {}/sse", base_url);
+    info!("Health check: {}/health", base_url);
+    info!("Metrics: {}/metrics", base_url);
+    let tool_names: Vec<_> = server.tools.iter().map(|tool| tool.name.as_str()).collect();
+    info!("Available tools: {}", tool_names.join(", "));
+    info!("Blocking pool size: {}", server.pool.size());
+    if config.auth_token.is_some() {
+        info!("Auth: bearer token required on /mcp and /sse");
+    } else if config.api_key.is_some() {
+        info!("Auth: X-API-Key required on /mcp and /sse");
+    }
+    match &config.cors_origins {
+        None => info!("CORS: allowing any origin"),
+        Some(origins) if origins.is_empty() => info!("CORS: no cross-origin requests allowed"),
+        Some(origins) => {
+            let origins: Vec<_> = origins.iter().filter_map(|o| o.to_str().ok()).collect();
+            info!("CORS: allowing origins {}", origins.join(", "));
+        }
+    }
+
+    axum::serve(listener, app).await.unwrap();
+    if let Some(traces) = traces {
+        // Sends whatever the batch exporter still holds.
+        let _ = traces.shutdown();
+    }
+}
+
+// Example usage and testing
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{body::Body, http::Request};
+    use tower::ServiceExt;
+
+    #[test]
+    fn test_addition_tool() {
+        let server = McpServer::new();
+        
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "add",
+                "arguments": {
+                    "a": 5.0,
+                    "b": 3.0
+                }
+            })),
+        };
+
+        let response = server.handle_request(request);
+        assert!(response.error.is_none());
+        assert!(response.result.is_some());
+    }
+
+    #[test]
+    fn test_multiplication_tool() {
+        let server = McpServer::new();
+        
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "multiply",
+                "arguments": {
+                    "a": 4.0,
+                    "b": 3.0
+                }
+            })),
+        };
+
+        let response = server.handle_request(request);
+        assert!(response.error.is_none());
+        assert!(response.result.is_some());
+    }
+
+    #[test]
+    fn test_tools_list() {
+        let server = McpServer::new();
+        
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params: None,
+        };
+
+        let response = server.handle_request(request);
+        assert!(response.error.is_none());
+        assert!(response.result.is_some());
+    }
+
+    #[test]
+    fn test_tools_list_annotations() {
+        let server = McpServer::new();
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params: None,
+        };
+
+        let result = server.handle_request(request).result.unwrap();
+        let tools = result["tools"].as_array().unwrap();
+        let sqrt = tools.iter().find(|t| t["name"] == "sqrt").unwrap();
+        assert_eq!(sqrt["annotations"]["domain"]["number"], "non-negative");
+
+        let add = tools.iter().find(|t| t["name"] == "add").unwrap();
+        assert!(add.get("annotations").is_none());
+    }
+
+    #[test]
+    fn test_unknown_method_lists_supported_methods() {
+        let server = McpServer::new();
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "foobar".to_string(),
+            params: None,
+        };
+
+        let error = server.handle_request(request).error.unwrap();
+        assert_eq!(error.code, -32601);
+        let methods = error.data.unwrap();
+        assert!(methods.as_array().unwrap().contains(&json!("tools/list")));
+    }
+
+    #[test]
+    fn test_ping() {
+        let server = McpServer::new();
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "ping".to_string(),
+            params: None,
+        };
+
+        let response = server.handle_request(request);
+        assert!(response.error.is_none());
+        assert_eq!(response.result, Some(json!({})));
+    }
+
+    fn call_tool(server: &McpServer, name: &str, arguments: Value) -> JsonRpcResponse {
+        server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": name,
+                "arguments": arguments
+            })),
+        })
+    }
+
+    // Parses the number after the final " = " in a tool's text result.
+    fn result_number(response: &JsonRpcResponse) -> f64 {
+        let text = response.result.as_ref().unwrap()["content"][0]["text"].as_str().unwrap();
+        text.rsplit(" = ").next().unwrap().parse().unwrap()
+    }
+
+    #[test]
+    fn test_log_tool() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "log", json!({ "number": 1000.0 }));
+        assert!(response.error.is_none());
+        assert_eq!(result_number(&response), 3.0);
+
+        let e = std::f64::consts::E;
+        let response = call_tool(&server, "log", json!({ "number": e * e, "base": e }));
+        assert!((result_number(&response) - 2.0).abs() < 1e-12);
+    }
+
+    async fn post_mcp(body: &'static str) -> (StatusCode, Value) {
+        let response = app(Arc::new(McpServer::new()), &Config::default())
+            .oneshot(
+                Request::post("/mcp")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_trailing_data_is_parse_error() {
+        let (status, body) =
+            post_mcp(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}garbage"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], -32700);
+        assert_eq!(body["error"]["message"], "trailing data after JSON-RPC object");
+        assert!(body["id"].is_null());
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_is_a_parse_error() {
+        let (status, body) = post_mcp("{not json").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], -32700);
+        assert_eq!(body["id"], Value::Null);
+
+        let (status, body) = post_mcp(r#"{"jsonrpc":"2.0","id":1}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], -32600);
+    }
+
+    #[tokio::test]
+    async fn test_oversized_body_is_rejected() {
+        let config = Config {
+            max_body_bytes: 1024,
+            ..Config::default()
+        };
+        let values = vec!["1"; 1024].join(",");
+        let body = format!(
+            r#"{{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{{"name":"stats","arguments":{{"values":[{}]}}}}}}"#,
+            values
+        );
+        let response = app(Arc::new(McpServer::new()), &config)
+            .oneshot(
+                Request::post("/mcp")
+                    .header("content-type", "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+    }
+
+    async fn post_tool(uri: &str, content_type: &str, body: &'static str) -> (StatusCode, Value) {
+        let response = app(Arc::new(McpServer::new()), &Config::default())
+            .oneshot(
+                Request::post(uri)
+                    .header("content-type", content_type)
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_rest_tool_call_accepts_form_and_json() {
+        let (status, body) = post_tool("/tools/add", "application/x-www-form-urlencoded", "a=2&b=3").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["content"][0]["text"], "2 + 3 = 5");
+
+        let (status, body) = post_tool("/tools/add?a=1&b=1", "application/json", r#"{"b":4}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["content"][0]["text"], "1 + 4 = 5");
+
+        let (status, body) = post_tool("/tools/sqrt", "application/json", r#"{"number":-1}"#).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"]["code"], -32602);
+
+        let (status, _) = post_tool("/tools/add", "text/plain", "a=2").await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+    }
+
+    #[tokio::test]
+    async fn test_unknown_route_returns_json_404() {
+        let response = app(Arc::new(McpServer::new()), &Config::default())
+            .oneshot(Request::get("/nonexistent").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"], "not found");
+        assert_eq!(body["path"], "/nonexistent");
+        assert!(body["data"].as_array().unwrap().contains(&json!("POST /mcp")));
+    }
+
+    #[tokio::test]
+    async fn test_pretty_json_responses() {
+        let config = Config {
+            pretty_json: true,
+            ..Config::default()
+        };
+        let response = app(Arc::new(McpServer::with_config(&config)), &config)
+            .oneshot(
+                Request::post("/mcp")
+                    .header("content-type", "application/json")
+                    .body(Body::from(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.headers()["content-type"], "application/json");
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert!(std::str::from_utf8(&bytes).unwrap().contains("\n  \"id\": 1"));
+    }
+
+    #[tokio::test]
+    async fn test_post_mcp() {
+        let (status, body) = post_mcp(r#"{"jsonrpc":"2.0","id":7,"method":"ping"}"#).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], 7);
+        assert_eq!(body["result"], json!({}));
+    }
+
+    // Accepts only requests carrying `X-Team: calc`.
+    struct TeamHeaderAuthenticator;
+
+    #[async_trait::async_trait]
+    impl Authenticator for TeamHeaderAuthenticator {
+        async fn authenticate(&self, headers: &HeaderMap) -> Result<Principal, auth::AuthError> {
+            match headers.get("x-team") {
+                Some(team) if team == "calc" => Ok(Principal {
+                    id: "team-calc".to_string(),
+                }),
+                Some(_) => Err(auth::AuthError::InvalidCredentials),
+                None => Err(auth::AuthError::MissingCredentials),
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_custom_authenticator() {
+        let app = app_with_authenticator(
+            Arc::new(McpServer::new()),
+            &Config::default(),
+            Some(Arc::new(TeamHeaderAuthenticator)),
+        );
+        let ping = |team: Option<&str>| {
+            let mut request = Request::post("/mcp").header("content-type", "application/json");
+            if let Some(team) = team {
+                request = request.header("x-team", team);
+            }
+            request
+                .body(Body::from(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(ping(Some("calc"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = app.clone().oneshot(ping(Some("other"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["code"], -32001);
+
+        let response = app.clone().oneshot(ping(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let health = Request::get("/health").body(Body::empty()).unwrap();
+        let response = app.oneshot(health).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_request_timeout() {
+        let config = Config {
+            request_timeout: Duration::from_millis(50),
+            ..Config::default()
+        };
+        let slow = Router::new().route(
+            "/slow",
+            get(|| async {
+                tokio::time::sleep(Duration::from_secs(5)).await;
+                "done"
+            }),
+        );
+        let app = with_middleware(slow, &config).with_state(Arc::new(McpServer::new()));
+
+        let response = app
+            .oneshot(Request::get("/slow").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::REQUEST_TIMEOUT);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_queues_extra_requests() {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let config = Config {
+            max_concurrency: 2,
+            ..Config::default()
+        };
+        // Tracks how many requests are inside a handler at once, across routes.
+        let in_flight = Arc::new((AtomicUsize::new(0), AtomicUsize::new(0)));
+        let slow = || {
+            let counters = Arc::clone(&in_flight);
+            get(move || {
+                let counters = Arc::clone(&counters);
+                async move {
+                    let now = counters.0.fetch_add(1, Ordering::SeqCst) + 1;
+                    counters.1.fetch_max(now, Ordering::SeqCst);
+                    tokio::time::sleep(Duration::from_millis(50)).await;
+                    counters.0.fetch_sub(1, Ordering::SeqCst);
+                    "done"
+                }
+            })
+        };
+        let routes = limit_concurrency(Router::new().route("/a", slow()).route("/b", slow()), &config);
+        let app = with_middleware(routes, &config).with_state(Arc::new(McpServer::new()));
+
+        let requests = ["/a", "/b", "/a", "/b", "/a"]
+            .map(|path| app.clone().oneshot(Request::get(path).body(Body::empty()).unwrap()));
+        for response in futures::future::join_all(requests).await {
+            assert_eq!(response.unwrap().status(), StatusCode::OK);
+        }
+        assert_eq!(in_flight.1.load(Ordering::SeqCst), 2);
+    }
+
+    #[tokio::test]
+    async fn test_concurrency_limit_spares_health_probes() {
+        // With no slots at all, every limited request waits indefinitely.
+        let config = Config {
+            max_concurrency: 0,
+            ..Config::default()
+        };
+        let app = app(Arc::new(McpServer::new()), &config);
+        let send = |request: Request<Body>| tokio::time::timeout(Duration::from_millis(100), app.clone().oneshot(request));
+
+        let ping = Request::post("/mcp")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#))
+            .unwrap();
+        assert!(send(ping).await.is_err(), "/mcp should wait for a slot");
+
+        for path in ["/health", "/health/ready", "/readyz", "/metrics"] {
+            let response = send(Request::get(path).body(Body::empty()).unwrap()).await.unwrap().unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", path);
+        }
+    }
+
+    #[test]
+    fn test_exactness_metadata() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "add", json!({ "a": 2, "b": 3 }));
+        assert_eq!(response.result.unwrap()["_meta"]["exact"], true);
+
+        let response = call_tool(&server, "add", json!({ "a": 0.1, "b": 0.2 }));
+        assert_eq!(response.result.unwrap()["_meta"]["exact"], false);
+
+        let response = call_tool(&server, "sqrt", json!({ "number": 2 }));
+        assert_eq!(response.result.unwrap()["_meta"]["exact"], false);
+
+        let response = call_tool(&server, "sqrt", json!({ "number": 16 }));
+        assert_eq!(response.result.unwrap()["_meta"]["exact"], true);
+    }
+
+    #[tokio::test]
+    async fn test_initialize_stores_client_info() {
+        let server = Arc::new(McpServer::new());
+        let session_id = server.sessions.create().await;
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "initialize".to_string(),
+            params: Some(json!({
+                "protocolVersion": "2024-11-05",
+                "capabilities": {},
+                "clientInfo": { "name": "claude-desktop", "version": "0.9.0" }
+            })),
+        };
+
+        let response = server.dispatch(Some(session_id.clone()), request).await;
+        assert!(response.error.is_none());
+
+        let client_info = server.sessions.get(&session_id).await.unwrap().client_info.unwrap();
+        assert_eq!(client_info["name"], "claude-desktop");
+        assert_eq!(server.sessions.last_client(), Some(client_info));
+    }
+
+    #[tokio::test]
+    async fn test_health_reports_last_client() {
+        let server = Arc::new(McpServer::new());
+        let app = app(Arc::clone(&server), &Config::default());
+
+        let initialize = Request::post("/mcp")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"jsonrpc":"2.0","id":1,"method":"initialize","params":{"clientInfo":{"name":"cursor","version":"1.0.0"}}}"#,
+            ))
+            .unwrap();
+        let response = app.clone().oneshot(initialize).await.unwrap();
+        assert!(response.headers().contains_key(SESSION_HEADER));
+
+        let response = app
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["lastClient"]["name"], "cursor");
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_tool_calls() {
+        let server = Arc::new(McpServer::new());
+        call_tool(&server, "add", json!({ "a": 1, "b": 2 }));
+        call_tool(&server, "add", json!({ "a": 3, "b": 4 }));
+        call_tool(&server, "sqrt", json!({ "number": -1 }));
+
+        let response = app(server, &Config::default())
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+
+        assert!(text.contains("mcp_requests_total 3\n"));
+        assert!(text.contains("mcp_method_calls_total{method=\"tools/call\"} 3\n"));
+        assert!(text.contains("mcp_tool_calls_total{tool=\"add\"} 2\n"));
+        assert!(text.contains("mcp_errors_total 1\n"));
+    }
+
+    #[tokio::test]
+    async fn test_health_checks_sse_subsystem() {
+        let response = app(Arc::new(McpServer::new()), &Config::default())
+            .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["sse"], "ok");
+        assert_eq!(body["status"], "healthy");
+        assert!(body["uptimeSeconds"].as_u64().is_some());
+    }
+
+    #[test]
+    fn test_numeric_string_inputs() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "add", json!({ "a": "5", "b": 3 }));
+        assert_eq!(result_number(&response), 8.0);
+        let response = call_tool(&server, "multiply", json!({ "a": " 2.5 ", "b": "4" }));
+        assert_eq!(result_number(&response), 10.0);
+
+        let error = call_tool(&server, "add", json!({ "a": "abc", "b": 3 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("\"abc\""), "{}", error.message);
+
+        let digits = "1".repeat(numeric::DEFAULT_MAX_NUMBER_STRING_LEN + 1);
+        let error = call_tool(&server, "add", json!({ "a": digits, "b": 3 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("number string too long"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn test_cors_restricted_origins() {
+        let config = Config {
+            cors_origins: Some(config::parse_origins("https://a.example, https://b.example")),
+            ..Config::default()
+        };
+        let app = app(Arc::new(McpServer::new()), &config);
+        let health = |origin: &str| {
+            Request::get("/health")
+                .header("origin", origin)
+                .body(Body::empty())
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(health("https://b.example")).await.unwrap();
+        assert_eq!(response.headers()["access-control-allow-origin"], "https://b.example");
+
+        let response = app.oneshot(health("https://evil.example")).await.unwrap();
+        assert!(!response.headers().contains_key("access-control-allow-origin"));
+    }
+
+    #[test]
+    fn test_tool_result_as_resource() {
+        let server = McpServer::new();
+
+        let response = server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "multiply",
+                "arguments": { "a": 6, "b": 7 },
+                "_meta": { "asResource": true }
+            })),
+        });
+        let content = &response.result.unwrap()["content"][0];
+        assert_eq!(content["type"], "resource");
+        let uri = content["resource"]["uri"].as_str().unwrap().to_string();
+
+        let response = server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(2)),
+            method: "resources/read".to_string(),
+            params: Some(json!({ "uri": uri })),
+        });
+        let contents = &response.result.unwrap()["contents"][0];
+        let stored: Value = serde_json::from_str(contents["text"].as_str().unwrap()).unwrap();
+        assert_eq!(stored["content"][0]["text"], "6 × 7 = 42");
+
+        let response = server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(3)),
+            method: "resources/read".to_string(),
+            params: Some(json!({ "uri": "calc://results/unknown" })),
+        });
+        assert_eq!(response.error.unwrap().code, -32002);
+    }
+
+    #[test]
+    fn test_log_rejects_invalid_input() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "log", json!({ "number": 0.0 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+
+        let response = call_tool(&server, "log", json!({ "number": -5.0 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+
+        let response = call_tool(&server, "log", json!({ "number": 8.0, "base": 1.0 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_stats_tool() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "stats", json!({ "values": [2, 4, 4, 4, 5, 5, 7, 9] }));
+        let summary = &response.result.unwrap()["structuredContent"];
+        assert_eq!(summary["mean"], 5.0);
+        assert_eq!(summary["median"], 4.5);
+        assert_eq!(summary["min"], 2.0);
+        assert_eq!(summary["max"], 9.0);
+        assert_eq!(summary["stdDev"], 2.0);
+
+        let response = call_tool(&server, "stats", json!({ "values": [9, 1, 5] }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["median"], 5.0);
+    }
+
+    #[test]
+    fn test_stats_rejects_empty_list() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "stats", json!({ "values": [] }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    // Third-party tool returning a fixed number, NaN included.
+    struct ConstantTool(f64);
+
+    impl ToolHandler for ConstantTool {
+        fn call(&self, _ctx: &ToolContext, _arguments: Value) -> Result<tool::ToolOutput, JsonRpcError> {
+            Ok(tool::ToolOutput::Number(self.0))
+        }
+    }
+
+    fn constant_tool(name: &str, value: f64) -> (Tool, Arc<dyn ToolHandler>) {
+        let tool = Tool {
+            name: name.to_string(),
+            description: "Return a constant".to_string(),
+            input_schema: json!({ "type": "object" }),
+            annotations: None,
+            tags: Vec::new(),
+        };
+        (tool, Arc::new(ConstantTool(value)))
+    }
+
+    #[test]
+    fn test_custom_tool_handler() {
+        let server = McpServer::with_tools(&Config::default(), vec![constant_tool("answer", 42.0)]);
+
+        let response = call_tool(&server, "answer", json!({}));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], 42.0);
+    }
+
+    #[test]
+    fn test_unserializable_tool_result() {
+        let server = McpServer::with_tools(&Config::default(), vec![constant_tool("nan", f64::NAN)]);
+
+        let error = call_tool(&server, "nan", json!({})).error.unwrap();
+        assert_eq!(error.code, -32603);
+        assert_eq!(error.message, "tool produced unserializable result");
+        assert_eq!(error.data.unwrap()["tool"], "nan");
+    }
+
+    #[test]
+    #[should_panic(expected = "already registered")]
+    fn test_custom_tool_name_clash() {
+        McpServer::with_tools(&Config::default(), vec![constant_tool("add", 1.0)]);
+    }
+
+    #[tokio::test]
+    async fn test_bearer_token_auth() {
+        let config = Config {
+            auth_token: Some("s3cret".to_string()),
+            ..Config::default()
+        };
+        let app = app(Arc::new(McpServer::new()), &config);
+        let ping = |authorization: Option<&str>| {
+            let mut request = Request::post("/mcp").header("content-type", "application/json");
+            if let Some(authorization) = authorization {
+                request = request.header("authorization", authorization);
+            }
+            request
+                .body(Body::from(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#))
+                .unwrap()
+        };
+
+        let response = app.clone().oneshot(ping(None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["jsonrpc"], "2.0");
+        assert_eq!(body["error"]["code"], -32001);
+
+        let response = app.clone().oneshot(ping(Some("Bearer wrong"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let response = app.clone().oneshot(ping(Some("Bearer s3cret"))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let sse = Request::get("/sse").body(Body::empty()).unwrap();
+        let response = app.clone().oneshot(sse).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let health = Request::get("/health").body(Body::empty()).unwrap();
+        let response = app.oneshot(health).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[test]
+    fn test_tools_match() {
+        let server = McpServer::new();
+
+        let response = server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/match".to_string(),
+            params: Some(json!({ "arguments": { "a": 2, "b": 3 } })),
+        });
+        let result = response.result.unwrap();
+        let names: Vec<_> = result["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert!(names.contains(&"add"));
+        assert!(names.contains(&"multiply"));
+        assert!(!names.contains(&"sqrt"));
+
+        let response = server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(2)),
+            method: "tools/match".to_string(),
+            params: Some(json!({ "arguments": [1, 2] })),
+        });
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_tools_list_pagination() {
+        let config = Config {
+            tools_page_size: 2,
+            ..Config::default()
+        };
+        let server = McpServer::with_config(&config);
+
+        let mut names = Vec::new();
+        let mut cursor = Value::Null;
+        let mut pages = 0;
+        loop {
+            let response = server.handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(pages)),
+                method: "tools/list".to_string(),
+                params: Some(json!({ "cursor": cursor })),
+            });
+            let result = response.result.unwrap();
+            let page = result["tools"].as_array().unwrap();
+            assert!(page.len() <= 2);
+            names.extend(page.iter().map(|tool| tool["name"].as_str().unwrap().to_string()));
+            pages += 1;
+            match result.get("nextCursor") {
+                Some(next) => cursor = next.clone(),
+                None => break,
+            }
+        }
+
+        let all: Vec<_> = server.tools.iter().map(|tool| tool.name.clone()).collect();
+        assert_eq!(names, all);
+        assert_eq!(pages, all.len().div_ceil(2));
+    }
+
+    #[test]
+    fn test_tools_list_invalid_cursor() {
+        let server = McpServer::new();
+
+        let response = server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params: Some(json!({ "cursor": "bogus" })),
+        });
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    #[allow(clippy::approx_constant)]
+    fn test_round_tool() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "round", json!({ "number": 3.14159, "decimals": 2 }));
+        assert_eq!(result_number(&response), 3.14);
+
+        let response = call_tool(&server, "round", json!({ "number": 2.5 }));
+        assert_eq!(result_number(&response), 3.0);
+
+        let response = call_tool(&server, "round", json!({ "number": 1234.5, "decimals": -1 }));
+        assert_eq!(result_number(&response), 1230.0);
+
+        let response = call_tool(&server, "round", json!({ "number": 1250, "decimals": -2 }));
+        assert_eq!(result_number(&response), 1300.0);
+    }
+
+    #[test]
+    fn test_round_rejects_out_of_range_decimals() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "round", json!({ "number": 1.5, "decimals": 16 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+
+        let response = call_tool(&server, "round", json!({ "number": 1.5, "decimals": -16 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_evaluate_tool() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "evaluate", json!({ "expression": "2 * (3 + sqrt(16))" }));
+        assert!(response.error.is_none());
+        assert_eq!(result_number(&response), 14.0);
+
+        let response = call_tool(&server, "evaluate", json!({ "expression": "1 / (2 - 2)" }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_evaluate_reuses_cached_ast() {
+        let server = McpServer::new();
+
+        for _ in 0..2 {
+            let response = call_tool(&server, "evaluate", json!({ "expression": "6 * 7" }));
+            assert_eq!(result_number(&response), 42.0);
+        }
+        assert_eq!(server.expr_cache.parse_count(), 1);
+    }
+
+    #[test]
+    fn test_gcd_and_lcm_tools() {
+        let server = McpServer::new();
+
+        assert_eq!(result_number(&call_tool(&server, "gcd", json!({ "a": 48, "b": 18 }))), 6.0);
+        assert_eq!(result_number(&call_tool(&server, "gcd", json!({ "a": -48, "b": 0 }))), 48.0);
+        assert_eq!(result_number(&call_tool(&server, "lcm", json!({ "a": 4, "b": 6 }))), 12.0);
+        assert_eq!(result_number(&call_tool(&server, "lcm", json!({ "a": 0, "b": 0 }))), 0.0);
+
+        let response = call_tool(&server, "gcd", json!({ "a": 4.5, "b": 6 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+        let response = call_tool(&server, "lcm", json!({ "a": 9_007_199_254_740_991i64, "b": 9_007_199_254_740_990i64 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_result_cache_status() {
+        let config = Config {
+            result_cache_size: 16,
+            ..Config::default()
+        };
+        // A custom tool stands in for anything non-deterministic.
+        let server = McpServer::with_tools(&config, vec![constant_tool("coin_flip", 4.0)]);
+
+        let first = call_tool(&server, "add", json!({ "a": 2, "b": 3 }));
+        let second = call_tool(&server, "add", json!({ "a": 2, "b": 3 }));
+        assert_eq!(first.result.unwrap()["_meta"]["cache"], "miss");
+        let second = second.result.unwrap();
+        assert_eq!(second["_meta"]["cache"], "hit");
+        assert_eq!(second["content"][0]["text"], "2 + 3 = 5");
+
+        for _ in 0..2 {
+            let response = call_tool(&server, "coin_flip", json!({}));
+            assert_eq!(response.result.unwrap()["_meta"]["cache"], "bypass");
+        }
+
+        let response = call_tool(&McpServer::new(), "add", json!({ "a": 2, "b": 3 }));
+        assert!(response.result.unwrap()["_meta"].get("cache").is_none());
+    }
+
+    async fn initialize_session(server: &Arc<McpServer>) -> String {
+        initialize_session_with(server, json!({ "clientInfo": { "name": "eager-client" } })).await
+    }
+
+    async fn initialize_session_with(server: &Arc<McpServer>, params: Value) -> String {
+        let session_id = server.sessions.create().await;
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "initialize".to_string(),
+            params: Some(params),
+        };
+        let response = server.dispatch(Some(session_id.clone()), request).await;
+        assert!(response.error.is_none());
+        session_id
+    }
+
+    async fn session_call(server: &Arc<McpServer>, session_id: &str, name: &str, arguments: Value) -> JsonRpcResponse {
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(2)),
+            method: "tools/call".to_string(),
+            params: Some(json!({ "name": name, "arguments": arguments })),
+        };
+        server.dispatch(Some(session_id.to_string()), request).await
+    }
+
+    #[tokio::test]
+    async fn test_lenient_mode_allows_calls_before_initialized_notification() {
+        let server = Arc::new(McpServer::new());
+        let session_id = initialize_session(&server).await;
+
+        let response = session_call(&server, &session_id, "add", json!({ "a": 1, "b": 2 })).await;
+        assert!(response.error.is_none());
+        assert_eq!(result_number(&response), 3.0);
+    }
+
+    #[tokio::test]
+    async fn test_strict_mode_requires_initialized_notification() {
+        let config = Config {
+            strict_initialization: true,
+            ..Config::default()
+        };
+        let server = Arc::new(McpServer::with_config(&config));
+        let session_id = initialize_session(&server).await;
+
+        let response = session_call(&server, &session_id, "add", json!({ "a": 1, "b": 2 })).await;
+        assert_eq!(response.error.unwrap().code, -32600);
+
+        let notification = Request::post("/mcp")
+            .header("content-type", "application/json")
+            .header(SESSION_HEADER, &session_id)
+            .body(Body::from(r#"{"jsonrpc":"2.0","method":"notifications/initialized"}"#))
+            .unwrap();
+        let response = app(Arc::clone(&server), &config).oneshot(notification).await.unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+
+        let response = session_call(&server, &session_id, "add", json!({ "a": 1, "b": 2 })).await;
+        assert!(response.error.is_none());
+    }
+
+    #[tokio::test]
+    async fn test_session_variables_persist_across_requests() {
+        let server = Arc::new(McpServer::new());
+        let session_id = initialize_session(&server).await;
+
+        let response = session_call(&server, &session_id, "evaluate", json!({ "expression": "x = 6" })).await;
+        assert_eq!(result_number(&response), 6.0);
+        let response = session_call(&server, &session_id, "evaluate", json!({ "expression": "x * 7" })).await;
+        assert_eq!(result_number(&response), 42.0);
+
+        // Other sessions and sessionless calls don't see the variable.
+        let other = initialize_session(&server).await;
+        let response = session_call(&server, &other, "evaluate", json!({ "expression": "x * 7" })).await;
+        assert_eq!(response.error.unwrap().code, -32602);
+        let response = call_tool(&server, "evaluate", json!({ "expression": "x * 7" }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_unit_conversion_tools() {
+        let server = McpServer::new();
+
+        let response = call_tool(
+            &server,
+            "convert_temperature",
+            json!({ "value": 100, "from": "celsius", "to": "fahrenheit" }),
+        );
+        assert!((result_number(&response) - 212.0).abs() < 1e-9);
+        let response = call_tool(
+            &server,
+            "convert_temperature",
+            json!({ "value": 0, "from": "kelvin", "to": "celsius" }),
+        );
+        assert!((result_number(&response) + 273.15).abs() < 1e-9);
+
+        let response = call_tool(&server, "convert_length", json!({ "value": 1, "from": "miles", "to": "meters" }));
+        assert!((result_number(&response) - 1609.34).abs() < 0.01);
+
+        let response = call_tool(&server, "convert_length", json!({ "value": 1, "from": "parsecs", "to": "meters" }));
+        let error = response.error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("\"parsecs\" is not one of"), "{}", error.message);
+
+        let response = call_tool(
+            &server,
+            "convert_temperature",
+            json!({ "value": -500, "from": "celsius", "to": "kelvin" }),
+        );
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    // Third-party tool that takes a while to answer.
+    struct SlowTool(Duration);
+
+    impl ToolHandler for SlowTool {
+        fn call(&self, _ctx: &ToolContext, _arguments: Value) -> Result<tool::ToolOutput, JsonRpcError> {
+            std::thread::sleep(self.0);
+            Ok(tool::ToolOutput::Number(1.0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_slow_tool_warns_before_timing_out() {
+        let config = Config {
+            tool_timeout: Duration::from_millis(100),
+            ..Config::default()
+        };
+        let slow: (Tool, Arc<dyn ToolHandler>) = (
+            Tool {
+                name: "slow".to_string(),
+                description: "Answer slowly".to_string(),
+                input_schema: json!({ "type": "object" }),
+                annotations: None,
+                tags: Vec::new(),
+            },
+            Arc::new(SlowTool(Duration::from_millis(300))),
+        );
+        let server = Arc::new(McpServer::with_tools(&config, vec![slow]));
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(9)),
+            method: "tools/call".to_string(),
+            params: Some(json!({ "name": "slow", "arguments": {} })),
+        };
+        let response = server.dispatch_with_notifications(None, request, Some(tx)).await;
+
+        let warning = rx.try_recv().expect("warning should be sent before the timeout");
+        assert_eq!(warning["method"], "notifications/message");
+        assert_eq!(warning["params"]["level"], "warning");
+        assert_eq!(warning["params"]["data"]["requestId"], 9);
+        let error = response.error.unwrap();
+        assert_eq!(error.code, -32603);
+        assert!(error.message.contains("timed out"));
+    }
+
+    #[test]
+    fn test_enabled_tools_filter() {
+        let config = Config {
+            enabled_tools: Some(vec!["add".to_string()]),
+            ..Config::default()
+        };
+        let server = McpServer::with_config(&config);
+        assert_eq!(server.tools.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(), vec!["add"]);
+
+        assert_eq!(result_number(&call_tool(&server, "add", json!({ "a": 1, "b": 2 }))), 3.0);
+        let response = call_tool(&server, "multiply", json!({ "a": 2, "b": 3 }));
+        assert_eq!(response.error.unwrap().code, -32601);
+    }
+
+    #[test]
+    fn test_clamp_tool() {
+        let server = McpServer::new();
+
+        let clamp = |value: f64| {
+            result_number(&call_tool(&server, "clamp", json!({ "value": value, "min": 0, "max": 10 })))
+        };
+        assert_eq!(clamp(15.0), 10.0);
+        assert_eq!(clamp(-5.0), 0.0);
+        assert_eq!(clamp(4.5), 4.5);
+
+        let response = call_tool(&server, "clamp", json!({ "value": 1, "min": 10, "max": 0 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_min_and_max_tools() {
+        let server = McpServer::new();
+
+        assert_eq!(result_number(&call_tool(&server, "min", json!({ "values": [3, -1.5, 7] }))), -1.5);
+        assert_eq!(result_number(&call_tool(&server, "max", json!({ "values": [3, -1.5, 7] }))), 7.0);
+        let response = call_tool(&server, "max", json!({ "values": [] }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_stats_streams_partial_results() {
+        let server = Arc::new(McpServer::new());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let values: Vec<f64> = (1..=10_000).map(f64::from).collect();
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!("big")),
+            method: "tools/call".to_string(),
+            params: Some(json!({
+                "name": "stats",
+                "arguments": { "values": values },
+                "_meta": { "stream": true }
+            })),
+        };
+        let response = server.dispatch_with_notifications(None, request, Some(tx)).await;
+
+        let mut partials = Vec::new();
+        while let Ok(notification) = rx.try_recv() {
+            assert_eq!(notification["method"], "notifications/progress");
+            assert_eq!(notification["params"]["progressToken"], "big");
+            partials.push(notification["params"]["partial"].clone());
+        }
+        assert_eq!(partials.len(), 10);
+        assert_eq!(partials[0], json!({ "count": 1000, "mean": 500.5 }));
+        assert_eq!(partials[9]["count"], 10_000);
+
+        let result = response.result.unwrap();
+        assert_eq!(result["structuredContent"]["mean"], 5000.5);
+    }
+
+    #[tokio::test]
+    async fn test_websocket_transport() {
+        use tokio_tungstenite::tungstenite::Message as Frame;
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = app(Arc::new(McpServer::new()), &Config::default());
+        tokio::spawn(async move { axum::serve(listener, server).await.unwrap() });
+
+        let (mut socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        socket
+            .send(Frame::Text(r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#.to_string()))
+            .await
+            .unwrap();
+
+        let frame = socket.next().await.unwrap().unwrap();
+        let response: Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(response["id"], 1);
+        assert!(response["result"]["tools"].as_array().unwrap().iter().any(|t| t["name"] == "add"));
+
+        socket.send(Frame::Text("{not json".to_string())).await.unwrap();
+        let frame = socket.next().await.unwrap().unwrap();
+        let response: Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+        assert_eq!(response["error"]["code"], -32700);
+
+        socket.close(None).await.unwrap();
+    }
+
+    // Runs until its call is cancelled, or for at most five seconds.
+    struct BlockingTool;
+
+    impl ToolHandler for BlockingTool {
+        fn call(&self, ctx: &ToolContext, _arguments: Value) -> Result<tool::ToolOutput, JsonRpcError> {
+            let start = Instant::now();
+            while !ctx.cancellation.is_cancelled() && start.elapsed() < Duration::from_secs(5) {
+                std::thread::sleep(Duration::from_millis(5));
+            }
+            Ok(tool::ToolOutput::Number(0.0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_websocket_handles_requests_concurrently() {
+        use tokio_tungstenite::tungstenite::Message as Frame;
+
+        let tool = Tool {
+            name: "block".to_string(),
+            description: "Wait for cancellation".to_string(),
+            input_schema: json!({ "type": "object" }),
+            annotations: None,
+            tags: Vec::new(),
+        };
+        let tools = vec![(tool, Arc::new(BlockingTool) as Arc<dyn ToolHandler>)];
+        let server = McpServer::with_tools(&Config::default(), tools);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = app(Arc::new(server), &Config::default());
+        tokio::spawn(async move { axum::serve(listener, router).await.unwrap() });
+
+        let (socket, _) = tokio_tungstenite::connect_async(format!("ws://{}/ws", addr)).await.unwrap();
+        let (mut sink, mut stream) = futures::StreamExt::split(socket);
+        let frames = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"block","arguments":{}}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"ping"}"#,
+        ];
+        for frame in frames {
+            sink.send(Frame::Text(frame.to_string())).await.unwrap();
+        }
+        let mut next_response = async || loop {
+            let frame = tokio::time::timeout(Duration::from_secs(2), stream.next()).await.unwrap().unwrap().unwrap();
+            let message: Value = serde_json::from_str(frame.to_text().unwrap()).unwrap();
+            if message.get("id").is_some() {
+                return message;
+            }
+        };
+
+        // The ping is answered while the call is still blocked.
+        let response = next_response().await;
+        assert_eq!(response["id"], 2);
+
+        // Reusing the id of the running call would make cancelling it ambiguous.
+        sink.send(Frame::Text(frames[0].to_string())).await.unwrap();
+        let response = next_response().await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], -32600);
+
+        let cancel = r#"{"jsonrpc":"2.0","method":"notifications/cancelled","params":{"requestId":1}}"#;
+        sink.send(Frame::Text(cancel.to_string())).await.unwrap();
+        let response = next_response().await;
+        assert_eq!(response["id"], 1);
+        assert_eq!(response["error"]["code"], error::ErrorCode::RequestCancelled as i32);
+    }
+
+    // Counts how often its body actually runs.
+    struct CountingTool(Arc<std::sync::atomic::AtomicUsize>);
+
+    impl ToolHandler for CountingTool {
+        fn call(&self, _ctx: &ToolContext, _arguments: Value) -> Result<tool::ToolOutput, JsonRpcError> {
+            let calls = self.0.fetch_add(1, std::sync::atomic::Ordering::SeqCst) + 1;
+            Ok(tool::ToolOutput::Number(calls as f64))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_idempotency_key_runs_tool_once_across_sessions() {
+        let calls = Arc::new(std::sync::atomic::AtomicUsize::new(0));
+        let tool = Tool {
+            name: "counter".to_string(),
+            description: "Count calls".to_string(),
+            input_schema: json!({ "type": "object" }),
+            annotations: None,
+            tags: Vec::new(),
+        };
+        let server = Arc::new(McpServer::with_tools(
+            &Config::default(),
+            vec![(tool, Arc::new(CountingTool(Arc::clone(&calls))) as Arc<dyn ToolHandler>)],
+        ));
+        let router = app(Arc::clone(&server), &Config::default());
+
+        let mut results = Vec::new();
+        for _ in 0..2 {
+            let session_id = initialize_session(&server).await;
+            let response = router
+                .clone()
+                .oneshot(
+                    Request::post("/mcp")
+                        .header("content-type", "application/json")
+                        .header(SESSION_HEADER, session_id)
+                        .header("idempotency-key", "retry-42")
+                        .body(Body::from(
+                            r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"counter","arguments":{}}}"#,
+                        ))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            let body: Value = serde_json::from_slice(&bytes).unwrap();
+            results.push(body["result"]["structuredContent"]["result"].clone());
+        }
+
+        assert_eq!(calls.load(std::sync::atomic::Ordering::SeqCst), 1);
+        assert_eq!(results, vec![json!(1.0), json!(1.0)]);
+
+        // Calls without a key always run.
+        let response = call_tool(&server, "counter", json!({}));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_idempotent_retry_replays_without_counting_again() {
+        let server = Arc::new(McpServer::new());
+        let router = app(Arc::clone(&server), &Config::default());
+        let send = |arguments: &str| {
+            let body = format!(
+                r#"{{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{{"name":"add","arguments":{}}}}}"#,
+                arguments
+            );
+            let request = Request::post("/mcp")
+                .header("content-type", "application/json")
+                .header("idempotency-key", "retry-7")
+                .body(Body::from(body))
+                .unwrap();
+            let router = router.clone();
+            async move {
+                let response = router.oneshot(request).await.unwrap();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                serde_json::from_slice::<Value>(&bytes).unwrap()
+            }
+        };
+
+        let first = send(r#"{"a":2,"b":3}"#).await;
+        let retry = send(r#"{"a":2,"b":3}"#).await;
+        assert_eq!(retry["result"], first["result"]);
+        assert!(server.metrics.render().contains("mcp_tool_calls_total{tool=\"add\"} 1\n"));
+
+        // The same key for a different call is refused rather than replayed.
+        let reused = send(r#"{"a":2,"b":4}"#).await;
+        assert_eq!(reused["error"]["code"], -32602);
+        assert!(server.metrics.render().contains("mcp_tool_calls_total{tool=\"add\"} 1\n"));
+    }
+
+    #[test]
+    fn test_abs_sign_and_negate_tools() {
+        let server = McpServer::new();
+
+        assert_eq!(result_number(&call_tool(&server, "abs", json!({ "number": -2.5 }))), 2.5);
+        assert_eq!(result_number(&call_tool(&server, "abs", json!({ "number": 3 }))), 3.0);
+        assert_eq!(result_number(&call_tool(&server, "negate", json!({ "number": -4 }))), 4.0);
+        assert_eq!(result_number(&call_tool(&server, "negate", json!({ "number": 4 }))), -4.0);
+        assert_eq!(result_number(&call_tool(&server, "sign", json!({ "number": -7 }))), -1.0);
+        assert_eq!(result_number(&call_tool(&server, "sign", json!({ "number": 0.5 }))), 1.0);
+
+        // Both zeros are 0, not -0 or 1.
+        for zero in [json!(0.0), json!(-0.0)] {
+            let response = call_tool(&server, "sign", json!({ "number": zero }));
+            let text = response.result.unwrap()["content"][0]["text"].as_str().unwrap().to_string();
+            assert!(text.ends_with(" = 0"), "{}", text);
+        }
+    }
+
+    #[test]
+    fn test_oversized_sse_event_becomes_resource_reference() {
+        let server = McpServer::with_config(&Config {
+            sse_max_event_bytes: 1024,
+            ..Config::default()
+        });
+        let notification = json!({
+            "jsonrpc": "2.0",
+            "method": "notifications/message",
+            "params": { "level": "info", "data": "x".repeat(4096) }
+        });
+
+        let (event, data) = server.sse_event_data(&notification);
+        assert_eq!(event, "error");
+        let reference: Value = serde_json::from_str(&data).unwrap();
+        assert_eq!(reference["limit"], 1024);
+        assert!(reference["size"].as_u64().unwrap() > 4096);
+
+        let uri = reference["uri"].as_str().unwrap();
+        let stored = server.resources.get(uri).unwrap();
+        assert_eq!(serde_json::from_str::<Value>(&stored.text).unwrap(), notification);
+
+        let (event, data) = server.sse_event_data(&json!({ "jsonrpc": "2.0", "method": "ping" }));
+        assert_eq!(event, "message");
+        assert_eq!(data, r#"{"jsonrpc":"2.0","method":"ping"}"#);
+    }
+
+    #[test]
+    fn test_arguments_are_validated_against_input_schema() {
+        let server = McpServer::new();
+
+        let error = call_tool(&server, "add", json!({ "a": "five", "b": 3 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.starts_with("Invalid arguments for add:"), "{}", error.message);
+        let violations = error.data.unwrap();
+        assert_eq!(violations[0]["path"], "/a");
+        assert!(violations[0]["message"].as_str().unwrap().contains("number"));
+
+        let error = call_tool(&server, "add", json!({ "a": 1 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("\"b\" is a required property"), "{}", error.message);
+    }
+
+    #[tokio::test]
+    async fn test_version_matches_crate_version() {
+        let response = app(Arc::new(McpServer::new()), &Config::default())
+            .oneshot(Request::get("/version").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body, json!({ "name": env!("CARGO_PKG_NAME"), "version": env!("CARGO_PKG_VERSION") }));
+
+        let server = McpServer::new();
+        let response = server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "initialize".to_string(),
+            params: None,
+        });
+        assert_eq!(response.result.unwrap()["serverInfo"]["version"], env!("CARGO_PKG_VERSION"));
+    }
+
+    #[test]
+    fn test_initialize_reports_configured_name_and_instructions() {
+        let initialize = |server: &McpServer| {
+            server
+                .handle_request(JsonRpcRequest {
+                    jsonrpc: "2.0".to_string(),
+                    id: Some(json!(1)),
+                    method: "initialize".to_string(),
+                    params: None,
+                })
+                .result
+                .unwrap()
+        };
+
+        let result = initialize(&McpServer::new());
+        assert_eq!(result["serverInfo"]["name"], "Calculator MCP Server");
+        assert!(result.get("instructions").is_none());
+
+        let result = initialize(&McpServer::with_config(&Config {
+            server_name: "Acme Calculator".to_string(),
+            instructions: Some("Prefer evaluate for multi-step arithmetic.".to_string()),
+            ..Config::default()
+        }));
+        assert_eq!(result["serverInfo"]["name"], "Acme Calculator");
+        assert_eq!(result["instructions"], "Prefer evaluate for multi-step arithmetic.");
+    }
+
+    #[tokio::test]
+    async fn test_health_detailed_reports_resources() {
+        let response = app(Arc::new(McpServer::new()), &Config::default())
+            .oneshot(Request::get("/health/detailed").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["status"], "healthy");
+        assert_eq!(body["runtimeWorkers"], 1);
+        if cfg!(target_os = "linux") {
+            assert!(body["memoryBytes"].as_u64().unwrap() > 0);
+            assert!(body["threads"].as_u64().unwrap() > 0);
+        } else {
+            assert!(body.get("memoryBytes").is_none());
+        }
+    }
+
+    #[tokio::test]
+    async fn test_readyz_reports_runtime_saturation() {
+        let response = app(Arc::new(McpServer::new()), &Config::default())
+            .oneshot(Request::get("/readyz").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        // Saturating the runtime on demand is flaky, so feed in the latency.
+        let threshold = Duration::from_millis(100);
+        let (status, Json(body)) = readiness(Duration::from_millis(20), threshold);
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["status"], "ready");
+
+        let (status, Json(body)) = readiness(Duration::from_millis(250), threshold);
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["status"], "saturated");
+        assert_eq!(body["thresholdMs"], 100);
+    }
+
+    #[tokio::test]
+    async fn test_audit_entries_are_signed() {
+        let config = Config {
+            audit_hmac_key: Some("audit-secret".to_string()),
+            auth_token: Some("s3cret".to_string()),
+            ..Config::default()
+        };
+        let server = Arc::new(McpServer::with_config(&config));
+        call_tool(&server, "add", json!({ "a": 2, "b": 3 }));
+
+        let entry = server.audit.entries().remove(0);
+        assert_eq!(entry.tool, "add");
+        assert!(entry.verify(b"audit-secret"));
+        assert!(!entry.verify(b"not-the-secret"));
+
+        let request = Request::get("/audit").header("authorization", "Bearer s3cret").body(Body::empty()).unwrap();
+        let response = app(Arc::clone(&server), &config).oneshot(request).await.unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["entries"][0]["signature"], json!(entry.signature));
+        assert_eq!(body["entries"][0]["arguments"], json!({ "a": 2, "b": 3 }));
+    }
+
+    #[tokio::test]
+    async fn test_audit_is_off_without_authentication() {
+        let server = Arc::new(McpServer::new());
+        call_tool(&server, "add", json!({ "a": 2, "b": 3 }));
+
+        let response = app(server, &Config::default())
+            .oneshot(Request::get("/audit").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
+
+    #[tokio::test]
+    async fn test_responses_carry_request_id() {
+        let app = app(Arc::new(McpServer::new()), &Config::default());
+
+        let mut ids = Vec::new();
+        for _ in 0..2 {
+            let response = app
+                .clone()
+                .oneshot(Request::get("/health").body(Body::empty()).unwrap())
+                .await
+                .unwrap();
+            let id = response.headers()[logging::REQUEST_ID_HEADER].to_str().unwrap().to_string();
+            assert!(uuid::Uuid::parse_str(&id).is_ok(), "{}", id);
+            ids.push(id);
+        }
+        assert_ne!(ids[0], ids[1]);
+    }
+
+    #[tokio::test]
+    async fn test_text_only_clients_get_no_structured_content() {
+        let server = Arc::new(McpServer::new());
+        let text_only = initialize_session_with(
+            &server,
+            json!({ "clientInfo": { "name": "terminal" }, "capabilities": { "contentTypes": ["text"] } }),
+        )
+        .await;
+        let structured = initialize_session_with(
+            &server,
+            json!({ "clientInfo": { "name": "ide" }, "capabilities": { "contentTypes": ["text", "structured"] } }),
+        )
+        .await;
+
+        let result = session_call(&server, &text_only, "stats", json!({ "values": [1, 2, 3] })).await.result.unwrap();
+        assert!(result.get("structuredContent").is_none());
+        assert_eq!(result["content"][0]["type"], "text");
+
+        let result = session_call(&server, &structured, "stats", json!({ "values": [1, 2, 3] })).await.result.unwrap();
+        assert_eq!(result["structuredContent"]["mean"], 2.0);
+    }
+
+    #[test]
+    fn test_result_precision_applies_to_text_only() {
+        let server = McpServer::with_config(&Config {
+            result_precision: Some(10),
+            ..Config::default()
+        });
+
+        let response = call_tool(&server, "add", json!({ "a": 0.1, "b": 0.2 }));
+        let text = response.result.as_ref().unwrap()["content"][0]["text"].as_str().unwrap();
+        assert_eq!(text, "0.1 + 0.2 = 0.3");
+
+        let response = call_tool(&server, "stats", json!({ "values": [0.1, 0.2] }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["mean"], (0.1 + 0.2) / 2.0);
+    }
+
+    #[tokio::test]
+    async fn test_extractor_rejections_are_json_rpc_errors() {
+        let app = app(Arc::new(McpServer::new()), &Config::default());
+
+        let response = app
+            .clone()
+            .oneshot(Request::get("/sse?keepalive=soon").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["jsonrpc"], "2.0");
+        assert_eq!(body["error"]["code"], -32602);
+        assert!(body["error"]["message"].as_str().unwrap().contains("query string"), "{}", body);
+
+        let response = app
+            .oneshot(Request::get("/ws").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert!(response.status().is_client_error());
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["error"]["code"], -32600);
+    }
+
+    #[tokio::test]
+    async fn test_cancelled_notification_triggers_token() {
+        let server = Arc::new(McpServer::new());
+        let session_id = initialize_session(&server).await;
+        let token = server.cancellations.register(&session_id, &json!(5)).unwrap();
+        let other = server.cancellations.register("other-session", &json!(5)).unwrap();
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: None,
+            method: "notifications/cancelled".to_string(),
+            params: Some(json!({ "requestId": 5, "reason": "user aborted" })),
+        };
+        server.dispatch(Some(session_id), request).await;
+
+        assert!(token.is_cancelled());
+        assert!(!other.is_cancelled());
+    }
+
+    #[test]
+    fn test_cancelled_stream_stops_early() {
+        let server = McpServer::new();
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let meta = json!({ "progressToken": "t", "stream": true });
+        let mut ctx = ToolContext::new(None, Some(meta), SessionVars::default());
+        ctx.progress = ProgressReporter::new(Some(json!("t")), Some(tx));
+        ctx.cancellation.cancel();
+
+        let values: Vec<f64> = (0..5000).map(f64::from).collect();
+        let result = server.handle_stats(json!({ "values": values }), &ctx);
+        assert!(matches!(result, Err(McpError::Cancelled)));
+        assert!(rx.try_recv().is_err());
+    }
+
+    #[test]
+    fn test_evaluate_step_budget() {
+        let server = McpServer::with_config(&Config {
+            expr_max_steps: 50,
+            ..Config::default()
+        });
+
+        let long_sum = vec!["1"; 40].join(" + ");
+        let error = call_tool(&server, "evaluate", json!({ "expression": long_sum })).error.unwrap();
+        assert_eq!(error.code, -32603);
+        assert_eq!(error.message, "evaluation budget exceeded");
+
+        assert_eq!(result_number(&call_tool(&server, "evaluate", json!({ "expression": "1 + 2" }))), 3.0);
+    }
+
+    #[test]
+    fn test_evaluate_rejects_deep_nesting() {
+        let server = McpServer::new();
+
+        let nested = format!("{}1{}", "(".repeat(200), ")".repeat(200));
+        let error = call_tool(&server, "evaluate", json!({ "expression": nested })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.ends_with("expression too deeply nested"), "{}", error.message);
+    }
+
+    #[test]
+    fn test_structured_content_alongside_text() {
+        let server = McpServer::new();
+
+        let result = call_tool(&server, "add", json!({ "a": 5, "b": 3 })).result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], 8.0);
+        assert_eq!(result["structuredContent"]["operation"], "add");
+        assert_eq!(result["content"][0]["text"], "5 + 3 = 8");
+
+        let result = call_tool(&server, "negate", json!({ "number": 2 })).result.unwrap();
+        assert_eq!(result["structuredContent"], json!({ "result": -2.0, "operation": "negate" }));
+    }
+
+    #[tokio::test]
+    async fn test_debug_sessions_lists_active_sessions() {
+        let server = Arc::new(McpServer::new());
+        let first = initialize_session(&server).await;
+        let second = initialize_session(&server).await;
+        session_call(&server, &second, "add", json!({ "a": 1, "b": 2 })).await;
+
+        let response = app(Arc::clone(&server), &Config::default())
+            .oneshot(Request::get("/debug/sessions").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let config = Config {
+            auth_token: Some("s3cret".to_string()),
+            ..Config::default()
+        };
+        let debug_sessions = |authorization: &str| {
+            Request::get("/debug/sessions")
+                .header("authorization", authorization)
+                .body(Body::empty())
+                .unwrap()
+        };
+        let app = app(Arc::clone(&server), &config);
+        let response = app.clone().oneshot(debug_sessions("Bearer wrong")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        let response = app.oneshot(debug_sessions("Bearer s3cret")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        let sessions = body["sessions"].as_array().unwrap();
+        assert_eq!(sessions.len(), 2);
+
+        for id in [&first, &second] {
+            let session = sessions.iter().find(|s| s["id"] == json!(id)).unwrap();
+            assert_eq!(session["protocolVersion"], PROTOCOL_VERSION);
+            assert_eq!(session["clientInfo"]["name"], "eager-client");
+            assert_eq!(session["queuedMessages"], 0);
+            let created = session["createdAt"].as_u64().unwrap();
+            assert!(created > 0);
+            assert!(session["lastActiveAt"].as_u64().unwrap() >= created);
+        }
+    }
+
+    #[test]
+    fn test_sorted_tools_list() {
+        let server = McpServer::with_tools(
+            &Config {
+                sort_tools: true,
+                ..Config::default()
+            },
+            vec![constant_tool("aardvark", 1.0)],
+        );
+
+        let response = server.handle_request(JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params: None,
+        });
+        let names: Vec<String> = response.result.unwrap()["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap().to_string())
+            .collect();
+        let mut sorted = names.clone();
+        sorted.sort();
+        assert_eq!(names, sorted);
+        assert_eq!(names[0], "aardvark");
+    }
+
+    #[tokio::test]
+    async fn test_session_display_precision() {
+        let server = Arc::new(McpServer::new());
+        let rounded = initialize_session_with(
+            &server,
+            json!({ "clientInfo": { "name": "chat" }, "_meta": { "displayPrecision": 2 } }),
+        )
+        .await;
+        let full = initialize_session(&server).await;
+
+        let result = session_call(&server, &rounded, "sqrt", json!({ "number": 2 })).await.result.unwrap();
+        assert_eq!(result["content"][0]["text"], "√2 = 1.41");
+        assert_eq!(result["structuredContent"]["result"], 2f64.sqrt());
+
+        let result = session_call(&server, &full, "sqrt", json!({ "number": 2 })).await.result.unwrap();
+        assert_eq!(result["content"][0]["text"], format!("√2 = {}", 2f64.sqrt()));
+    }
+
+    #[tokio::test]
+    async fn test_health_ready_exercises_tool_dispatch() {
+        let server = Arc::new(McpServer::new());
+        let app = app(Arc::clone(&server), &Config::default());
+
+        let response = app
+            .oneshot(Request::get("/health/ready").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body: Value = serde_json::from_slice(&bytes).unwrap();
+        assert_eq!(body["checks"], json!({ "sse": "ok", "dispatch": "ok" }));
+        assert!(!server.metrics.render().contains("tool=\"add\""));
+    }
+
+    // Third-party tool with a bug.
+    struct PanickingTool;
+
+    impl ToolHandler for PanickingTool {
+        fn call(&self, _ctx: &ToolContext, _arguments: Value) -> Result<tool::ToolOutput, JsonRpcError> {
+            panic!("index out of bounds");
+        }
+    }
+
+    #[tokio::test]
+    async fn test_panicking_tool_is_an_internal_error() {
+        let config = Config {
+            detailed_errors: true,
+            ..Config::default()
+        };
+        let panicking: (Tool, Arc<dyn ToolHandler>) = (
+            Tool {
+                name: "buggy".to_string(),
+                description: "Always panics".to_string(),
+                input_schema: json!({ "type": "object" }),
+                annotations: None,
+                tags: Vec::new(),
+            },
+            Arc::new(PanickingTool),
+        );
+        let server = Arc::new(McpServer::with_tools(&config, vec![panicking]));
+        let call = |name: &str, arguments: Value| JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/call".to_string(),
+            params: Some(json!({ "name": name, "arguments": arguments })),
+        };
+
+        let error = server.dispatch(None, call("buggy", json!({}))).await.error.unwrap();
+        assert_eq!(error.code, -32603);
+        assert!(error.message.contains("index out of bounds"));
+
+        let response = server.dispatch(None, call("add", json!({ "a": 2, "b": 3 }))).await;
+        assert_eq!(result_number(&response), 5.0);
+    }
+
+    #[test]
+    fn test_tools_list_meta_summarizes_tags() {
+        let server = McpServer::with_tools(&Config::default(), vec![constant_tool("answer", 42.0)]);
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params: None,
+        };
+
+        let result = server.handle_request(request).result.unwrap();
+        assert_eq!(result["_meta"]["count"], 35);
+        assert_eq!(
+            result["_meta"]["categories"],
+            json!({
+                "arithmetic": 13,
+                "bitwise": 4,
+                "conversion": 5,
+                "expressions": 1,
+                "finance": 1,
+                "linear-algebra": 3,
+                "number-theory": 2,
+                "sequences": 1,
+                "statistics": 4
+            })
+        );
+        let gcd = result["tools"].as_array().unwrap().iter().find(|t| t["name"] == "gcd").unwrap();
+        assert_eq!(gcd["tags"], json!(["number-theory"]));
+    }
+
+    #[test]
+    fn test_tools_file_adds_declared_tools() {
+        let path = std::env::temp_dir().join(format!("mcp-tools-{}.json", uuid::Uuid::new_v4()));
+        std::fs::write(
+            &path,
+            r#"[{
+                "name": "hypot",
+                "description": "Length of the hypotenuse",
+                "inputSchema": {
+                    "type": "object",
+                    "properties": { "a": { "type": "number" }, "b": { "type": "number" } },
+                    "required": ["a", "b"]
+                },
+                "expression": "sqrt(a^2 + b^2)",
+                "tags": ["geometry"]
+            }]"#,
+        )
+        .unwrap();
+        let config = Config {
+            tools_file: Some(path.clone()),
+            ..Config::default()
+        };
+        let server = McpServer::from_config(&config);
+        std::fs::remove_file(&path).unwrap();
+        let server = server.unwrap();
+
+        let request = JsonRpcRequest {
+            jsonrpc: "2.0".to_string(),
+            id: Some(json!(1)),
+            method: "tools/list".to_string(),
+            params: None,
+        };
+        let result = server.handle_request(request).result.unwrap();
+        let hypot = result["tools"].as_array().unwrap().iter().find(|t| t["name"] == "hypot").unwrap();
+        assert_eq!(hypot["description"], "Length of the hypotenuse");
+        assert_eq!(result["_meta"]["categories"]["geometry"], 1);
+        let response = call_tool(&server, "hypot", json!({ "a": 3, "b": 4 }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], 5.0);
+
+        let missing = Config {
+            tools_file: Some(path),
+            ..Config::default()
+        };
+        assert!(McpServer::from_config(&missing).err().unwrap().starts_with("cannot read tools file"));
+    }
+
+    #[test]
+    fn test_divide_and_modulo_tools() {
+        let server = McpServer::new();
+        assert_eq!(result_number(&call_tool(&server, "divide", json!({ "a": 7, "b": 2 }))), 3.5);
+        assert_eq!(result_number(&call_tool(&server, "modulo", json!({ "a": -7, "b": 3 }))), -1.0);
+    }
+
+    #[test]
+    fn test_divide_by_zero_policies() {
+        let divide_by_zero = |policy| {
+            let config = Config {
+                divzero_policy: policy,
+                ..Config::default()
+            };
+            call_tool(&McpServer::with_config(&config), "divide", json!({ "a": 1, "b": 0 }))
+        };
+
+        let error = divide_by_zero(DivZeroPolicy::Error).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("Division by zero"));
+
+        let result = divide_by_zero(DivZeroPolicy::Null).result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], Value::Null);
+        assert_eq!(result["content"][0]["text"], "1 ÷ 0 = null");
+
+        let result = divide_by_zero(DivZeroPolicy::Infinity).result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], "Infinity");
+        assert_eq!(result["_meta"]["exact"], false);
+    }
+
+    #[test]
+    fn test_compound_interest_tool() {
+        let server = McpServer::new();
+        let arguments = json!({ "principal": 1000, "rate": 0.05, "times_per_year": 12, "years": 10 });
+        let amount = result_number(&call_tool(&server, "compound_interest", arguments));
+        assert!((amount - 1647.009497).abs() < 1e-6, "{}", amount);
+
+        let arguments = json!({ "principal": 1000, "rate": -0.05, "times_per_year": 12, "years": 10 });
+        let error = call_tool(&server, "compound_interest", arguments).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("rate must be non-negative"));
+
+        let arguments = json!({ "principal": 1000, "rate": 0.05, "times_per_year": 0, "years": 10 });
+        assert_eq!(call_tool(&server, "compound_interest", arguments).error.unwrap().code, -32602);
+    }
+
+    #[test]
+    fn test_array_arguments_are_broadcast() {
+        let server = McpServer::new();
+
+        let result = call_tool(&server, "add", json!({ "a": [1, 2, 3], "b": 10 })).result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], json!([11.0, 12.0, 13.0]));
+        assert_eq!(result["content"][0]["text"], "1 + 10 = 11\n2 + 10 = 12\n3 + 10 = 13");
+        assert_eq!(result["_meta"]["exact"], true);
+
+        let result = call_tool(&server, "multiply", json!({ "a": [1, 2], "b": [3, "4"] })).result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], json!([3.0, 8.0]));
+
+        let error = call_tool(&server, "add", json!({ "a": [1, 2, 3], "b": [1, 2] })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("Cannot broadcast"));
+
+        let error = call_tool(&server, "sqrt", json!({ "number": [4, -1] })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.starts_with("element 1:"));
+    }
+
+    #[test]
+    fn test_broadcast_limits() {
+        let config = Config {
+            range_max_count: 100,
+            broadcast_max_elements: 3,
+            ..Config::default()
         };
+        let server = McpServer::with_config(&config);
 
-        let result = params.a + params.b;
-        println!("Performed addition: {} + {} = {}", params.a, params.b, result);
+        let result = call_tool(&server, "add", json!({ "a": [1, 2, 3], "b": 1 })).result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], json!([2.0, 3.0, 4.0]));
+        let error = call_tool(&server, "add", json!({ "a": [1, 2, 3, 4], "b": 1 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.message, "Cannot broadcast 4 values of a: at most 3 elements are allowed");
 
-        JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(json!({
-                "content": [{
-                    "type": "text",
-                    "text": format!("{} + {} = {}", params.a, params.b, result)
-                }]
-            })),
-            error: None,
+        let result = call_tool(&server, "range", json!({ "start": [50, 60], "stop": 100 })).result.unwrap();
+        assert_eq!(result["structuredContent"]["result"][1].as_array().unwrap().len(), 40);
+        // Each element is within MCP_RANGE_MAX_COUNT, but together they aren't.
+        let error = call_tool(&server, "range", json!({ "start": [0, 0], "stop": 100 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.message, "range broadcast would produce more than 100 numbers in total");
+    }
+
+    #[test]
+    fn test_seeded_random_is_reproducible() {
+        let config = Config {
+            result_cache_size: 16,
+            ..Config::default()
+        };
+        let server = McpServer::with_config(&config);
+        let arguments = json!({ "min": 10, "max": 20, "seed": 42 });
+        let first = call_tool(&server, "random", arguments.clone()).result.unwrap();
+        let second = call_tool(&server, "random", arguments).result.unwrap();
+        assert_eq!(first["structuredContent"], second["structuredContent"]);
+        assert_eq!(second["_meta"]["cache"], "bypass");
+
+        let value = first["structuredContent"]["result"].as_f64().unwrap();
+        assert!((10.0..20.0).contains(&value));
+
+        let error = call_tool(&server, "random", json!({ "min": 2, "max": 1 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        let error = call_tool(&server, "random", json!({ "min": -1e308, "max": 1e308 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.message, "max - min is too large to sample from");
+    }
+
+    #[tokio::test]
+    async fn test_record_path_writes_request_response_pairs() {
+        let path = std::env::temp_dir().join(format!("mcp-record-{}.jsonl", uuid::Uuid::new_v4()));
+        let config = Config {
+            record_path: Some(path.clone()),
+            ..Config::default()
+        };
+        let app = app(Arc::new(McpServer::with_config(&config)), &config);
+        let bodies = [
+            r#"{"jsonrpc":"2.0","id":1,"method":"tools/list"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"tools/call","params":{"name":"add","arguments":{"a":2,"b":3}}}"#,
+        ];
+        for body in bodies {
+            let request = Request::post("/mcp")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap();
+            app.clone().oneshot(request).await.unwrap();
+        }
+
+        let text = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        let entries: Vec<Value> = text.lines().map(|line| serde_json::from_str(line).unwrap()).collect();
+        assert_eq!(entries.len(), 2);
+        for (entry, body) in entries.iter().zip(bodies) {
+            assert!(entry["timestamp"].as_u64().is_some());
+            assert_eq!(entry["request"], serde_json::from_str::<Value>(body).unwrap());
+            assert_eq!(entry["response"]["id"], entry["request"]["id"]);
         }
+        assert_eq!(entries[1]["response"]["result"]["structuredContent"]["result"], 5.0);
     }
 
-    fn handle_multiplication(&self, id: Option<Value>, arguments: Value) -> JsonRpcResponse {
-        let params: MultiplicationParams = match serde_json::from_value(arguments) {
-            Ok(p) => p,
-            Err(e) => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32602,
-                        message: format!("Invalid multiplication parameters: {}", e),
-                        data: None,
-                    }),
-                }
-            }
+    #[test]
+    fn test_dry_run_validates_without_computing() {
+        let server = McpServer::new();
+        let dry_run = |arguments: Value| {
+            server.handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "tools/call".to_string(),
+                params: Some(json!({ "name": "add", "arguments": arguments, "dryRun": true })),
+            })
         };
 
-        let result = params.a * params.b;
-        println!("Performed multiplication: {} × {} = {}", params.a, params.b, result);
+        let result = dry_run(json!({ "a": 2, "b": 3 })).result.unwrap();
+        assert_eq!(result, json!({ "content": [], "dryRun": true }));
+        assert!(server.audit.entries().is_empty());
 
-        JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(json!({
-                "content": [{
-                    "type": "text",
-                    "text": format!("{} × {} = {}", params.a, params.b, result)
-                }]
-            })),
-            error: None,
+        let error = dry_run(json!({ "a": "two", "b": 3 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+    }
+
+    async fn post_mcp_accepting(accept: &str) -> (StatusCode, String, String) {
+        let response = app(Arc::new(McpServer::new()), &Config::default())
+            .oneshot(
+                Request::post("/mcp")
+                    .header("content-type", "application/json")
+                    .header("accept", accept)
+                    .body(Body::from(
+                        r#"{"jsonrpc":"2.0","id":7,"method":"tools/call","params":{"name":"add","arguments":{"a":2,"b":3}}}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let status = response.status();
+        let content_type = response.headers()[header::CONTENT_TYPE].to_str().unwrap().to_string();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, content_type, String::from_utf8(bytes.to_vec()).unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_mcp_streams_response_when_event_stream_accepted() {
+        let (status, content_type, body) = post_mcp_accepting("text/event-stream").await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(content_type.starts_with("text/event-stream"), "{}", content_type);
+        let data = body.lines().find_map(|line| line.strip_prefix("data: ")).unwrap();
+        assert!(body.contains("event: message\n"), "{}", body);
+        let response: Value = serde_json::from_str(data).unwrap();
+        assert_eq!(response["id"], 7);
+        assert_eq!(response["result"]["structuredContent"]["result"], 5.0);
+    }
+
+    #[tokio::test]
+    async fn test_mcp_returns_json_without_event_stream_accept() {
+        let (status, content_type, body) = post_mcp_accepting("application/json").await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(content_type, "application/json");
+        let response: Value = serde_json::from_str(&body).unwrap();
+        assert_eq!(response["result"]["structuredContent"]["result"], 5.0);
+    }
+
+    #[test]
+    fn test_convert_base() {
+        let server = McpServer::new();
+        let response = call_tool(&server, "convert_base", json!({ "value": 255, "from_base": 10, "to_base": 16 }));
+        let result = response.result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], "ff");
+        assert_eq!(result["content"][0]["text"], "255 (base 10) in base 16 = ff");
+
+        let response = call_tool(&server, "convert_base", json!({ "value": "1010", "from_base": 2, "to_base": 10 }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], "10");
+        let response = call_tool(&server, "convert_base", json!({ "value": "-FF", "from_base": 16, "to_base": 2 }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], "-11111111");
+
+        for arguments in [
+            json!({ "value": 10, "from_base": 10, "to_base": 37 }),
+            json!({ "value": 2.5, "from_base": 10, "to_base": 2 }),
+            json!({ "value": "12", "from_base": 2, "to_base": 10 }),
+        ] {
+            let response = call_tool(&server, "convert_base", arguments);
+            assert_eq!(response.error.unwrap().code, -32602);
         }
     }
 
-    fn handle_square(&self, id: Option<Value>, arguments: Value) -> JsonRpcResponse {
-        let params: SquareParams = match serde_json::from_value(arguments) {
-            Ok(p) => p,
-            Err(e) => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32602,
-                        message: format!("Invalid square parameters: {}", e),
-                        data: None,
-                    }),
-                }
-            }
+    #[test]
+    fn test_include_timing_adds_duration() {
+        let config = Config {
+            include_timing: true,
+            ..Config::default()
         };
+        let server = McpServer::with_config(&config);
+        let result = call_tool(&server, "add", json!({ "a": 2, "b": 3 })).result.unwrap();
+        assert!(result["_meta"]["durationMicros"].is_u64(), "{}", result["_meta"]);
+        assert_eq!(result["_meta"]["exact"], true);
 
-        let result = params.number * params.number;
-        println!("Performed square: {}² = {}", params.number, result);
+        let result = call_tool(&McpServer::new(), "add", json!({ "a": 2, "b": 3 })).result.unwrap();
+        assert!(result["_meta"].get("durationMicros").is_none());
+    }
 
-        JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(json!({
-                "content": [{
-                    "type": "text",
-                    "text": format!("{}² = {}", params.number, result)
-                }]
-            })),
-            error: None,
+    #[test]
+    fn test_logging_set_level() {
+        let (log_level, _subscriber) = logging::local_level(tracing::level_filters::LevelFilter::INFO);
+        let log_level = Arc::new(log_level);
+        let server = McpServer::new().with_log_level(Arc::clone(&log_level));
+        let set_level = |level: &str| {
+            server.handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "logging/setLevel".to_string(),
+                params: Some(json!({ "level": level })),
+            })
+        };
+
+        assert_eq!(set_level("debug").result, Some(json!({})));
+        assert_eq!(log_level.current(), tracing::level_filters::LevelFilter::DEBUG);
+
+        let error = set_level("verbose").error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert_eq!(log_level.current(), tracing::level_filters::LevelFilter::DEBUG);
+    }
+
+    #[test]
+    fn test_admin_reset_clears_audit_log_and_metrics() {
+        let reset = |server: &McpServer| {
+            server.handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(json!(1)),
+                method: "admin/reset".to_string(),
+                params: None,
+            })
+        };
+        let config = Config {
+            auth_token: Some("secret-token".to_string()),
+            ..Config::default()
+        };
+        let server = McpServer::with_config(&config);
+        call_tool(&server, "add", json!({ "a": 2, "b": 3 }));
+        call_tool(&server, "sqrt", json!({ "number": 16 }));
+        assert_eq!(server.audit.entries().len(), 2);
+
+        assert_eq!(reset(&server).result, Some(json!({ "cleared": 2 })));
+        assert!(server.audit.entries().is_empty());
+        let metrics = server.metrics.render();
+        assert!(metrics.contains("mcp_requests_total 0\n"), "{}", metrics);
+        assert!(!metrics.contains("mcp_tool_calls_total{"), "{}", metrics);
+
+        let error = reset(&McpServer::new()).error.unwrap();
+        assert_eq!(error.code, -32601);
+    }
+
+    #[test]
+    fn test_bitwise_tools() {
+        let server = McpServer::new();
+        let response = call_tool(&server, "bitand", json!({ "a": 6, "b": 3 }));
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "6 & 3 = 2");
+        let response = call_tool(&server, "bitor", json!({ "a": 6, "b": 3 }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], 7);
+        let response = call_tool(&server, "bitxor", json!({ "a": -1, "b": 5 }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], -6);
+        let response = call_tool(&server, "bitand", json!({ "a": 6.5, "b": 3 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+        // Operands beyond ±2^53 must not be rounded through f64.
+        let response = call_tool(&server, "bitand", json!({ "a": 9223372036854775807i64, "b": "9223372036854775806" }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], 9223372036854775806i64);
+        let response = call_tool(&server, "bitxor", json!({ "a": 9223372036854775808u64, "b": 1 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+
+        let response = call_tool(&server, "shift", json!({ "value": 1, "amount": 10 }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], 1024);
+        let response = call_tool(&server, "shift", json!({ "value": -16, "amount": -2 }));
+        assert_eq!(response.result.unwrap()["content"][0]["text"], "-16 >> 2 = -4");
+        for arguments in [json!({ "value": 1, "amount": 64 }), json!({ "value": 3, "amount": 62 })] {
+            let response = call_tool(&server, "shift", arguments);
+            assert_eq!(response.error.unwrap().code, -32602);
         }
     }
 
-    fn handle_sqrt(&self, id: Option<Value>, arguments: Value) -> JsonRpcResponse {
-        let params: SqrtParams = match serde_json::from_value(arguments) {
-            Ok(p) => p,
-            Err(e) => {
-                return JsonRpcResponse {
-                    jsonrpc: "2.0".to_string(),
-                    id,
-                    result: None,
-                    error: Some(JsonRpcError {
-                        code: -32602,
-                        message: format!("Invalid sqrt parameters: {}", e),
-                        data: None,
-                    }),
-                }
+    // Stand-in for a long computation: reports 0%, 50% and 100% with pauses
+    // in between, as a real tool would between chunks of work.
+    struct ProgressTool;
+
+    impl ToolHandler for ProgressTool {
+        fn call(&self, ctx: &ToolContext, _arguments: Value) -> Result<tool::ToolOutput, JsonRpcError> {
+            for percent in [0.0, 50.0, 100.0] {
+                ctx.progress.report(percent, Some(100.0));
+                std::thread::sleep(Duration::from_millis(10));
             }
+            Ok(tool::ToolOutput::Number(1.0))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_progress_notifications_stream_over_sse() {
+        let slow = Tool {
+            name: "slow".to_string(),
+            description: "Report progress while working".to_string(),
+            input_schema: json!({ "type": "object" }),
+            annotations: None,
+            tags: Vec::new(),
         };
+        let slow_tool: Arc<dyn ToolHandler> = Arc::new(ProgressTool);
+        let server = McpServer::with_tools(&Config::default(), vec![(slow, slow_tool)]);
+        let response = app(Arc::new(server), &Config::default())
+            .oneshot(
+                Request::post("/mcp")
+                    .header("content-type", "application/json")
+                    .header("accept", "text/event-stream")
+                    .body(Body::from(
+                        r#"{"jsonrpc":"2.0","id":9,"method":"tools/call",
+                            "params":{"name":"slow","arguments":{},"_meta":{"progressToken":"job-1"}}}"#,
+                    ))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let messages: Vec<Value> = String::from_utf8(bytes.to_vec())
+            .unwrap()
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
 
-        if params.number < 0.0 {
-            return JsonRpcResponse {
+        assert_eq!(messages.len(), 4);
+        for (message, percent) in messages.iter().zip([0.0, 50.0, 100.0]) {
+            assert_eq!(message["method"], "notifications/progress");
+            assert_eq!(message["params"]["progressToken"], "job-1");
+            assert_eq!(message["params"]["progress"], percent);
+            assert_eq!(message["params"]["total"], 100.0);
+        }
+        assert_eq!(messages[3]["id"], 9);
+        assert_eq!(messages[3]["result"]["structuredContent"]["result"], 1.0);
+    }
+
+    #[test]
+    fn test_structured_ids_are_invalid_requests() {
+        let server = McpServer::new();
+        for id in [json!({ "x": 1 }), json!([1]), json!(true)] {
+            let response = server.handle_request(JsonRpcRequest {
                 jsonrpc: "2.0".to_string(),
-                id,
-                result: None,
-                error: Some(JsonRpcError {
-                    code: -32602,
-                    message: format!("Cannot calculate square root of negative number: {}", params.number),
-                    data: None,
-                }),
-            };
+                id: Some(id),
+                method: "ping".to_string(),
+                params: None,
+            });
+            assert_eq!(response.error.unwrap().code, -32600);
+            assert_eq!(response.id, None);
         }
 
-        let result = params.number.sqrt();
-        println!("Performed square root: √{} = {}", params.number, result);
+        for id in [json!("abc"), json!(7), json!(1.5)] {
+            let response = server.handle_request(JsonRpcRequest {
+                jsonrpc: "2.0".to_string(),
+                id: Some(id.clone()),
+                method: "ping".to_string(),
+                params: None,
+            });
+            assert!(response.error.is_none());
+            assert_eq!(response.id, Some(id));
+        }
+    }
 
-        JsonRpcResponse {
-            jsonrpc: "2.0".to_string(),
-            id,
-            result: Some(json!({
-                "content": [{
-                    "type": "text",
-                    "text": format!("√{} = {}", params.number, result)
-                }]
-            })),
-            error: None,
+    #[tokio::test]
+    async fn test_json_charset_option() {
+        for (json_charset, expected) in [(false, "application/json"), (true, "application/json; charset=utf-8")] {
+            let config = Config {
+                json_charset,
+                ..Config::default()
+            };
+            let response = app(Arc::new(McpServer::new()), &config)
+                .oneshot(
+                    Request::post("/mcp")
+                        .header("content-type", "application/json")
+                        .body(Body::from(r#"{"jsonrpc":"2.0","id":1,"method":"ping"}"#))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.headers()[header::CONTENT_TYPE], expected);
         }
     }
-}
 
-// SSE Handler
-async fn sse_handler(
-    Query(_params): Query<HashMap<String, String>>,
-    _headers: HeaderMap,
-) -> Response {
-    println!("SSE connection established");
-    
-    let server = McpServer::new();
-    
-    // Create a stream that handles incoming messages
-    let stream = stream::unfold(server, |server| async move {
-        // Simulate initialize request
-        let init_request = JsonRpcRequest {
+    #[test]
+    fn test_angle_conversions() {
+        let server = McpServer::new();
+        let response = call_tool(&server, "deg_to_rad", json!({ "number": 180 }));
+        let radians = response.result.unwrap()["structuredContent"]["result"].as_f64().unwrap();
+        assert!((radians - std::f64::consts::PI).abs() < 1e-12);
+
+        let response = call_tool(&server, "rad_to_deg", json!({ "number": std::f64::consts::PI }));
+        let result = response.result.unwrap();
+        assert!((result["structuredContent"]["result"].as_f64().unwrap() - 180.0).abs() < 1e-12);
+        assert_eq!(result["_meta"]["exact"], false);
+
+        let deg_to_rad = server.tools.iter().find(|tool| tool.name == "deg_to_rad").unwrap();
+        assert_eq!(deg_to_rad.annotations.as_ref().unwrap()["unit"], "radians");
+    }
+
+    #[tokio::test]
+    async fn test_range_streams_chunks() {
+        let server = Arc::new(McpServer::new());
+        let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+        let request = JsonRpcRequest {
             jsonrpc: "2.0".to_string(),
             id: Some(json!(1)),
-            method: "initialize".to_string(),
+            method: "tools/call".to_string(),
             params: Some(json!({
-                "protocolVersion": "2024-11-05",
-                "capabilities": {},
-                "clientInfo": {
-                    "name": "cursor-client",
-                    "version": "1.0.0"
-                }
+                "name": "range",
+                "arguments": { "start": 0, "stop": 2500 },
+                "_meta": { "stream": true }
             })),
         };
-        
-        let response = server.handle_request(init_request);
-        let event_data = serde_json::to_string(&response).unwrap_or_else(|_| "{}".to_string());
-        
-        Some((
-            Ok::<_, Infallible>(axum::response::sse::Event::default()
-                .data(event_data)
-                .event("message")),
-            server,
-        ))
-    })
-    .take(1); // Just send one response for demo
+        let response = server.dispatch_with_notifications(None, request, Some(tx)).await;
 
-    Sse::new(stream)
-        .keep_alive(axum::response::sse::KeepAlive::new().interval(Duration::from_secs(30)))
-        .into_response()
-}
+        let mut numbers = Vec::new();
+        while let Ok(notification) = rx.try_recv() {
+            if notification["method"] != "notifications/message" {
+                continue;
+            }
+            assert_eq!(notification["params"]["logger"], "range");
+            let data = &notification["params"]["data"];
+            assert_eq!(data["chunk"], numbers.len() / 1000);
+            numbers.extend(data["content"].as_array().unwrap().iter().map(|n| n.as_f64().unwrap()));
+        }
+        assert_eq!(numbers, (0..2500).map(f64::from).collect::<Vec<_>>());
 
-// JSON-RPC endpoint for MCP
-async fn jsonrpc_handler(Json(request): Json<JsonRpcRequest>) -> Json<JsonRpcResponse> {
-    println!("Received request: {:?}", request);
-    let server = McpServer::new();
-    let response = server.handle_request(request);
-    println!("Sending response: {:?}", response);
-    Json(response)
-}
+        let result = response.result.unwrap();
+        assert_eq!(result["structuredContent"]["count"], 2500);
+        assert_eq!(result["structuredContent"]["chunks"], 3);
+        assert!(result["structuredContent"].get("result").is_none());
 
-// Health check endpoint
-async fn health() -> Json<Value> {
-    Json(json!({
-        "status": "healthy",
-        "server": "mcp-calculator-server",
-        "version": "1.0.0"
-    }))
-}
+        let response = call_tool(&server, "range", json!({ "start": 5, "stop": 0, "step": -2 }));
+        let result = response.result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], json!([5.0, 3.0, 1.0]));
+        assert_eq!(result["content"][0]["text"], "range(5, 0, -2) = [5, 3, 1]");
+    }
 
-// Main application
-#[tokio::main]
-async fn main() {
-    let app = Router::new()
-        .route("/sse", get(sse_handler))
-        .route("/mcp", post(jsonrpc_handler))
-        .route("/health", get(health))
-        .layer(
-            CorsLayer::new()
-                .allow_origin(tower_http::cors::Any)
-                .allow_methods(tower_http::cors::Any)
-                .allow_headers(tower_http::cors::Any),
-        );
+    #[test]
+    fn test_range() {
+        let server = McpServer::new();
+        let response = call_tool(&server, "range", json!({ "start": 1, "stop": 5 }));
+        let result = response.result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], json!([1.0, 2.0, 3.0, 4.0]));
+        assert_eq!(result["_meta"]["exact"], true);
 
-    let listener = tokio::net::TcpListener::bind("127.0.0.1:3000")
-        .await
-        .unwrap();
-    
-    println!("Calculator MCP Server running on http://127.0.0.1:3000");
-    println!("MCP JSON-RPC endpoint: http://127.0.0.1:3000/mcp");
-    println!("SSE endpoint: http://127.0.0.1:3000/sse");
-    println!("Health check: http://127.0.0.1:3000/health");
-    println!("Available tools: add, multiply, square, sqrt");
+        let response = call_tool(&server, "range", json!({ "start": 0, "stop": 1, "step": 0.25 }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], json!([0.0, 0.25, 0.5, 0.75]));
+        let response = call_tool(&server, "range", json!({ "start": 3, "stop": 1 }));
+        assert_eq!(response.result.unwrap()["structuredContent"]["result"], json!([]));
 
-    axum::serve(listener, app).await.unwrap();
-}
+        let response = call_tool(&server, "range", json!({ "start": 0, "stop": 5, "step": 0 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
 
-// Example usage and testing
-#[cfg(test)]
-mod tests {
-    use super::*;
+    #[test]
+    fn test_range_max_count() {
+        let config = Config {
+            range_max_count: 100,
+            ..Config::default()
+        };
+        let server = McpServer::with_config(&config);
+        let response = call_tool(&server, "range", json!({ "start": 0, "stop": 100 }));
+        assert!(response.error.is_none());
+
+        let error = call_tool(&server, "range", json!({ "start": 0, "stop": 101 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.message, "range(0, 101, 1) would produce more than 100 numbers");
+        let response = call_tool(&server, "range", json!({ "start": 0, "stop": 1e300, "step": 1e-300 }));
+        assert_eq!(response.error.unwrap().code, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_mcp_request_is_exported_as_span() {
+        use opentelemetry::trace::TracerProvider as _;
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let exporter = opentelemetry_sdk::trace::InMemorySpanExporter::default();
+        let provider = opentelemetry_sdk::trace::SdkTracerProvider::builder()
+            .with_simple_exporter(exporter.clone())
+            .build();
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        // The test runtime is single-threaded, so the whole request runs
+        // under this thread's default subscriber.
+        let _guard = tracing::subscriber::set_default(subscriber);
+
+        let config = Config::default();
+        let app = app(Arc::new(McpServer::with_config(&config)), &config);
+        let request = Request::post("/mcp")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"jsonrpc":"2.0","id":1,"method":"tools/call","params":{"name":"add","arguments":{"a":2,"b":3}}}"#,
+            ))
+            .unwrap();
+        app.oneshot(request).await.unwrap();
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let span = spans.iter().find(|span| span.name == "mcp.request").unwrap();
+        let attributes: HashMap<_, _> =
+            span.attributes.iter().map(|kv| (kv.key.as_str().to_string(), kv.value.to_string())).collect();
+        assert_eq!(attributes["rpc.method"], "tools/call");
+        assert_eq!(attributes["mcp.tool"], "add");
+    }
 
     #[test]
-    fn test_addition_tool() {
+    fn test_arithmetic_rejects_unknown_arguments() {
         let server = McpServer::new();
-        
-        let request = JsonRpcRequest {
-            jsonrpc: "2.0".to_string(),
-            id: Some(json!(1)),
-            method: "tools/call".to_string(),
-            params: Some(json!({
-                "name": "add",
-                "arguments": {
-                    "a": 5.0,
-                    "b": 3.0
-                }
-            })),
+
+        let error = call_tool(&server, "add", json!({ "a": 1, "b": 2, "bb": 3 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("'bb'"), "{}", error.message);
+
+        // The misspelling is reported, not just the missing argument.
+        let error = call_tool(&server, "add", json!({ "aa": 1, "b": 2 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("'aa'"), "{}", error.message);
+        let violations = error.data.unwrap();
+        assert_eq!(violations[1]["message"], "\"a\" is a required property");
+
+        let error = call_tool(&server, "sqrt", json!({ "number": 4, "extra": true })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert!(error.message.contains("'extra'"), "{}", error.message);
+    }
+
+    #[test]
+    fn test_tool_aliases_resolve_to_canonical_tool() {
+        let server = McpServer::with_config(&Config {
+            tool_aliases: vec![("plus".to_string(), "add".to_string())],
+            ..Config::default()
+        });
+
+        let response = call_tool(&server, "plus", json!({ "a": 2, "b": 3 }));
+        assert_eq!(result_number(&response), 5.0);
+        assert_eq!(response.result.unwrap()["structuredContent"]["operation"], "add");
+
+        let list = |params: Value| {
+            let result = server
+                .handle_request(JsonRpcRequest {
+                    jsonrpc: "2.0".to_string(),
+                    id: Some(json!(1)),
+                    method: "tools/list".to_string(),
+                    params: Some(params),
+                })
+                .result
+                .unwrap();
+            result["tools"].as_array().unwrap().iter().map(|tool| tool["name"].clone()).collect::<Vec<_>>()
         };
+        assert!(!list(json!({})).contains(&json!("plus")));
+        assert!(list(json!({ "includeAliases": true })).contains(&json!("plus")));
 
-        let response = server.handle_request(request);
-        assert!(response.error.is_none());
-        assert!(response.result.is_some());
+        let config = Config {
+            tool_aliases: vec![("plus".to_string(), "missing".to_string())],
+            ..Config::default()
+        };
+        assert!(McpServer::try_with_tools(&config, Vec::new()).is_err());
     }
 
     #[test]
-    fn test_multiplication_tool() {
+    fn test_calculate_dispatches_on_operation() {
         let server = McpServer::new();
-        
+        let calculate =
+            |operation: &str| call_tool(&server, "calculate", json!({ "operation": operation, "a": 6, "b": 3 }));
+
+        for (operation, expected) in [("add", 9.0), ("subtract", 3.0), ("multiply", 18.0), ("divide", 2.0)] {
+            let response = calculate(operation);
+            assert_eq!(result_number(&response), expected, "{}", operation);
+            assert_eq!(response.result.unwrap()["structuredContent"]["operation"], operation);
+        }
+        let text = calculate("subtract").result.unwrap()["content"][0]["text"].clone();
+        assert_eq!(text, "6 - 3 = 3");
+
+        let error = calculate("power").error.unwrap();
+        assert_eq!(error.code, -32602);
+        // The handler rejects it too when called past the schema check.
+        let ctx = ToolContext::new(None, None, SessionVars::default());
+        let error = server.handle_calculate(json!({ "operation": "power", "a": 6, "b": 3 }), &ctx).unwrap_err();
+        assert!(error.to_string().contains("unknown variant `power`"), "{}", error);
+    }
+
+    #[tokio::test]
+    async fn test_sse_stream_delivers_session_notifications() {
+        let slow = Tool {
+            name: "slow".to_string(),
+            description: "Report progress while working".to_string(),
+            input_schema: json!({ "type": "object" }),
+            annotations: None,
+            tags: Vec::new(),
+        };
+        let server = Arc::new(McpServer::with_tools(&Config::default(), vec![(slow, Arc::new(ProgressTool) as _)]));
+        let response = app(Arc::clone(&server), &Config::default())
+            .oneshot(Request::get("/sse").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let session_id = response.headers()[SESSION_HEADER].to_str().unwrap().to_string();
+        assert!(server.sessions.get(&session_id).await.is_some());
+
+        // A plain request in the session sends its progress to the stream.
         let request = JsonRpcRequest {
             jsonrpc: "2.0".to_string(),
-            id: Some(json!(1)),
+            id: Some(json!(3)),
             method: "tools/call".to_string(),
-            params: Some(json!({
-                "name": "multiply",
-                "arguments": {
-                    "a": 4.0,
-                    "b": 3.0
-                }
-            })),
+            params: Some(json!({ "name": "slow", "arguments": {}, "_meta": { "progressToken": "job-1" } })),
         };
+        let response_to_call = server.dispatch(Some(session_id.clone()), request).await;
+        assert!(response_to_call.error.is_none());
+        let Json(sessions) = debug_sessions(State(Arc::clone(&server))).await;
+        assert_eq!(sessions["sessions"][0]["queuedMessages"], 3);
 
-        let response = server.handle_request(request);
-        assert!(response.error.is_none());
-        assert!(response.result.is_some());
+        let mut body = response.into_body().into_data_stream();
+        let mut text = String::new();
+        while text.matches("event: message").count() < 3 {
+            let chunk = tokio::time::timeout(Duration::from_secs(5), body.next()).await.unwrap().unwrap().unwrap();
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+        }
+        let progress: Vec<Value> = text
+            .lines()
+            .filter_map(|line| line.strip_prefix("data: "))
+            .map(|data| serde_json::from_str(data).unwrap())
+            .collect();
+        for (message, percent) in progress.iter().zip([0.0, 50.0, 100.0]) {
+            assert_eq!(message["method"], "notifications/progress");
+            assert_eq!(message["params"]["progress"], percent);
+        }
+        let Json(sessions) = debug_sessions(State(Arc::clone(&server))).await;
+        assert_eq!(sessions["sessions"][0]["queuedMessages"], 0);
+
+        // Disconnecting ends the session and unregisters the stream.
+        drop(body);
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while server.sessions.get(&session_id).await.is_some() {
+                tokio::task::yield_now().await;
+            }
+        })
+        .await
+        .unwrap();
+        assert!(server.sse_sender(&session_id).is_none());
     }
 
     #[test]
-    fn test_tools_list() {
+    fn test_matmul_tool() {
         let server = McpServer::new();
-        
-        let request = JsonRpcRequest {
-            jsonrpc: "2.0".to_string(),
-            id: Some(json!(1)),
-            method: "tools/list".to_string(),
-            params: None,
+
+        let a = json!([[1, 2, 3], [4, 5, 6]]);
+        let b = json!([[7, 8], [9, 10], [11, 12]]);
+        let result = call_tool(&server, "matmul", json!({ "a": a, "b": b })).result.unwrap();
+        assert_eq!(result["structuredContent"]["result"], json!([[58.0, 64.0], [139.0, 154.0]]));
+        assert_eq!(result["_meta"]["exact"], true);
+
+        let error = call_tool(&server, "matmul", json!({ "a": a, "b": a })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.message, "Cannot multiply a 2x3 matrix by a 2x3 matrix: a has 3 columns but b has 2 rows");
+
+        let error = call_tool(&server, "matmul", json!({ "a": [[1, 2], [3]], "b": b })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.message, "a must be rectangular: row 1 has 1 columns, row 0 has 2");
+    }
+
+    #[test]
+    fn test_dot_and_norm_tools() {
+        let server = McpServer::new();
+
+        let response = call_tool(&server, "dot", json!({ "a": [1, 2, 3], "b": [4, 5, 6] }));
+        assert_eq!(result_number(&response), 32.0);
+        assert_eq!(response.result.unwrap()["_meta"]["exact"], true);
+        let error = call_tool(&server, "dot", json!({ "a": [1, 2, 3], "b": [4, 5] })).error.unwrap();
+        assert_eq!(error.code, -32602);
+        assert_eq!(error.message, "Cannot take the dot product of vectors of length 3 and 2");
+
+        assert_eq!(result_number(&call_tool(&server, "norm", json!({ "values": [3, 4] }))), 5.0);
+        assert_eq!(result_number(&call_tool(&server, "norm", json!({ "values": [3, -4], "p": 1 }))), 7.0);
+        assert_eq!(result_number(&call_tool(&server, "norm", json!({ "values": [3, -4], "p": "inf" }))), 4.0);
+        let error = call_tool(&server, "norm", json!({ "values": [3, 4], "p": 3 })).error.unwrap();
+        assert_eq!(error.code, -32602);
+    }
+
+    #[tokio::test]
+    async fn test_tool_calls_are_timed_in_metrics() {
+        let server = Arc::new(McpServer::new());
+        for a in 0..3 {
+            call_tool(&server, "add", json!({ "a": a, "b": 1 }));
+        }
+
+        let response = app(Arc::clone(&server), &Config::default())
+            .oneshot(Request::get("/metrics").body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let text = String::from_utf8(bytes.to_vec()).unwrap();
+        assert!(text.contains("mcp_tool_duration_seconds_bucket{tool=\"add\",le=\"+Inf\"} 3\n"), "{}", text);
+        assert!(text.contains("mcp_tool_duration_seconds_bucket{tool=\"add\",le=\"5\"} 3\n"), "{}", text);
+        assert!(text.contains("mcp_tool_duration_seconds_count{tool=\"add\"} 3\n"), "{}", text);
+    }
+
+    #[tokio::test]
+    async fn test_batches_are_rejected() {
+        let app = app(Arc::new(McpServer::new()), &Config::default());
+        let send = |batch: &'static str| {
+            let request = Request::post("/mcp")
+                .header("content-type", "application/json")
+                .body(Body::from(batch))
+                .unwrap();
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                let body: Value = serde_json::from_slice(&bytes).unwrap();
+                assert_eq!(body["error"]["code"], -32600);
+                assert_eq!(body["id"], Value::Null);
+                body["error"]["message"].as_str().unwrap().to_string()
+            }
         };
 
-        let response = server.handle_request(request);
-        assert!(response.error.is_none());
-        assert!(response.result.is_some());
+        let message = send(
+            r#"[{"jsonrpc":"2.0","id":1,"method":"ping"},{"jsonrpc":"2.0","id":"a","method":"ping"},
+                {"jsonrpc":"2.0","id":null,"method":"ping"},{"jsonrpc":"2.0","id":null,"method":"ping"},
+                {"jsonrpc":"2.0","id":"a","method":"ping"},{"jsonrpc":"2.0","id":1,"method":"tools/list"}]"#,
+        )
+        .await;
+        assert_eq!(
+            message,
+            "Invalid Request: duplicate ids in batch: \"a\", 1; batch requests are not supported either, \
+             send one request per call"
+        );
+
+        let batch = r#"[{"jsonrpc":"2.0","id":1,"method":"ping"},{"jsonrpc":"2.0","id":2,"method":"ping"}]"#;
+        let message = send(batch).await;
+        assert_eq!(message, "Invalid Request: batch requests are not supported; send one request per call");
+    }
+
+    #[test]
+    fn test_duplicate_ids() {
+        let batch = [json!({ "id": 1 }), json!({ "id": 2 }), json!({}), json!({}), json!({ "id": 1 })];
+        assert_eq!(duplicate_ids(&batch), vec![json!(1)]);
+        assert!(duplicate_ids(&[json!({ "id": null }), json!({ "id": null })]).is_empty());
     }
 }
 
@@ -563,13 +6670,33 @@ mod tests {
 Cargo.toml dependencies needed:
 
 [dependencies]
-axum = "0.7"
+async-trait = "0.1"
+axum = { version = "0.7", features = ["ws"] }
 tokio = { version = "1.0", features = ["full"] }
 serde = { version = "1.0", features = ["derive"] }
 serde_json = "1.0"
 futures = "0.3"
 tokio-stream = "0.1"
-tower-http = { version = "0.5", features = ["cors"] }
+tower-http = { version = "0.5", features = ["cors", "timeout"] }
+uuid = { version = "1", features = ["v4"] }
+jsonschema = { version = "0.58", default-features = false }
+serde_urlencoded = "0.7"
+hmac = "0.12"
+sha2 = "0.10"
+tracing = "0.1"
+tracing-subscriber = { version = "0.3", default-features = false, features = ["fmt", "registry", "std", "ansi", "json"] }
+tracing-appender = "0.2"
+clap = { version = "4", features = ["derive"] }
+rand = "0.8"
+opentelemetry = "0.30"
+opentelemetry_sdk = "0.30"
+opentelemetry-otlp = { version = "0.30", default-features = false, features = ["trace", "http-proto", "reqwest-blocking-client"] }
+tracing-opentelemetry = "0.31"
+tower = { version = "0.5", features = ["limit"] }
+reqwest = { version = "0.12", default-features = false, features = ["json"] }
+
+[dev-dependencies]
+tokio-tungstenite = "0.24"
 
 To run:
 1. Update Cargo.toml with the dependencies above
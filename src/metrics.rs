@@ -0,0 +1,277 @@
+use std::{
+    collections::BTreeMap,
+    fmt::Write,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Mutex,
+    },
+    time::Duration,
+};
+
+// Upper bounds, in seconds, of the latency histogram buckets.
+const LATENCY_BUCKETS: &[f64] = &[0.001, 0.005, 0.025, 0.1, 0.5, 1.0, 5.0];
+
+// Request counters exposed at `/metrics` in Prometheus text format.
+#[derive(Debug, Default)]
+pub struct Metrics {
+    requests: AtomicU64,
+    errors: AtomicU64,
+    method_calls: Mutex<BTreeMap<String, u64>>,
+    tool_calls: Mutex<BTreeMap<String, u64>>,
+    queue_depth: AtomicU64,
+    queue_wait: Histogram,
+    tool_durations: Mutex<BTreeMap<String, Histogram>>,
+}
+
+impl Metrics {
+    pub fn record_request(&self, method: &str) {
+        self.requests.fetch_add(1, Ordering::Relaxed);
+        *self.method_calls.lock().unwrap().entry(method.to_string()).or_default() += 1;
+    }
+
+    pub fn record_tool_call(&self, tool: &str) {
+        *self.tool_calls.lock().unwrap().entry(tool.to_string()).or_default() += 1;
+    }
+
+    // Time spent running a tool, or answering it from the result cache.
+    pub fn observe_tool_duration(&self, tool: &str, duration: Duration) {
+        let mut durations = self.tool_durations.lock().unwrap();
+        durations.entry(tool.to_string()).or_default().observe(duration);
+    }
+
+    pub fn record_error(&self) {
+        self.errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    // Zeroes the counters and the histogram. The queue depth is a live
+    // gauge, so it's left alone.
+    pub fn reset(&self) {
+        self.requests.store(0, Ordering::Relaxed);
+        self.errors.store(0, Ordering::Relaxed);
+        self.method_calls.lock().unwrap().clear();
+        self.tool_calls.lock().unwrap().clear();
+        self.queue_wait.reset();
+        self.tool_durations.lock().unwrap().clear();
+    }
+
+    // Marks a tool call as waiting for a blocking pool slot until the
+    // returned guard is dropped.
+    pub fn enter_queue(&self) -> QueueGuard<'_> {
+        self.queue_depth.fetch_add(1, Ordering::Relaxed);
+        QueueGuard(self)
+    }
+
+    pub fn queue_depth(&self) -> u64 {
+        self.queue_depth.load(Ordering::Relaxed)
+    }
+
+    pub fn observe_queue_wait(&self, wait: Duration) {
+        self.queue_wait.observe(wait);
+    }
+
+    pub fn render(&self) -> String {
+        let mut out = String::new();
+        write_counter(
+            &mut out,
+            "mcp_requests_total",
+            "Total JSON-RPC requests handled.",
+            self.requests.load(Ordering::Relaxed),
+        );
+        write_labeled_counter(
+            &mut out,
+            "mcp_method_calls_total",
+            "JSON-RPC requests by method.",
+            "method",
+            &self.method_calls.lock().unwrap(),
+        );
+        write_labeled_counter(
+            &mut out,
+            "mcp_tool_calls_total",
+            "Tool invocations by tool name.",
+            "tool",
+            &self.tool_calls.lock().unwrap(),
+        );
+        write_counter(
+            &mut out,
+            "mcp_errors_total",
+            "JSON-RPC requests answered with an error.",
+            self.errors.load(Ordering::Relaxed),
+        );
+        let _ = writeln!(out, "# HELP mcp_request_queue_depth Tool calls waiting for a blocking pool slot.");
+        let _ = writeln!(out, "# TYPE mcp_request_queue_depth gauge");
+        let _ = writeln!(out, "mcp_request_queue_depth {}", self.queue_depth());
+        self.queue_wait.render(
+            &mut out,
+            "mcp_request_queue_wait_seconds",
+            "Time tool calls waited for a blocking pool slot.",
+        );
+        let name = "mcp_tool_duration_seconds";
+        write_header(&mut out, name, "Time taken by tool calls, by tool name.", "histogram");
+        for (tool, histogram) in self.tool_durations.lock().unwrap().iter() {
+            histogram.render_series(&mut out, name, &format!("tool=\"{}\",", escape_label(tool)));
+        }
+        out
+    }
+}
+
+pub struct QueueGuard<'a>(&'a Metrics);
+
+impl Drop for QueueGuard<'_> {
+    fn drop(&mut self) {
+        self.0.queue_depth.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+// Prometheus histogram over durations with fixed buckets.
+#[derive(Debug)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    buckets: Vec<AtomicU64>,
+    sum_micros: AtomicU64,
+    count: AtomicU64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self::new(LATENCY_BUCKETS)
+    }
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            buckets: bounds.iter().map(|_| AtomicU64::new(0)).collect(),
+            sum_micros: AtomicU64::new(0),
+            count: AtomicU64::new(0),
+        }
+    }
+
+    pub fn observe(&self, duration: Duration) {
+        let seconds = duration.as_secs_f64();
+        if let Some(i) = self.bounds.iter().position(|bound| seconds <= *bound) {
+            self.buckets[i].fetch_add(1, Ordering::Relaxed);
+        }
+        self.sum_micros.fetch_add(duration.as_micros() as u64, Ordering::Relaxed);
+        self.count.fetch_add(1, Ordering::Relaxed);
+    }
+
+    fn reset(&self) {
+        for bucket in &self.buckets {
+            bucket.store(0, Ordering::Relaxed);
+        }
+        self.sum_micros.store(0, Ordering::Relaxed);
+        self.count.store(0, Ordering::Relaxed);
+    }
+
+    fn render(&self, out: &mut String, name: &str, help: &str) {
+        write_header(out, name, help, "histogram");
+        self.render_series(out, name, "");
+    }
+
+    // The sample lines of one series. `labels` is empty or ends with a comma,
+    // ready to be followed by `le`.
+    fn render_series(&self, out: &mut String, name: &str, labels: &str) {
+        // Prometheus buckets are cumulative.
+        let mut cumulative = 0;
+        for (bound, bucket) in self.bounds.iter().zip(&self.buckets) {
+            cumulative += bucket.load(Ordering::Relaxed);
+            let _ = writeln!(out, "{}_bucket{{{}le=\"{}\"}} {}", name, labels, bound, cumulative);
+        }
+        let count = self.count.load(Ordering::Relaxed);
+        let _ = writeln!(out, "{}_bucket{{{}le=\"+Inf\"}} {}", name, labels, count);
+        let labels = labels.trim_end_matches(',');
+        let labels = if labels.is_empty() { String::new() } else { format!("{{{}}}", labels) };
+        let sum = self.sum_micros.load(Ordering::Relaxed) as f64 / 1_000_000.0;
+        let _ = writeln!(out, "{}_sum{} {}", name, labels, sum);
+        let _ = writeln!(out, "{}_count{} {}", name, labels, count);
+    }
+}
+
+fn write_header(out: &mut String, name: &str, help: &str, kind: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+fn write_counter(out: &mut String, name: &str, help: &str, value: u64) {
+    write_header(out, name, help, "counter");
+    let _ = writeln!(out, "{} {}", name, value);
+}
+
+fn write_labeled_counter(out: &mut String, name: &str, help: &str, label: &str, values: &BTreeMap<String, u64>) {
+    write_header(out, name, help, "counter");
+    for (key, value) in values {
+        let _ = writeln!(out, "{}{{{}=\"{}\"}} {}", name, label, escape_label(key), value);
+    }
+}
+
+fn escape_label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let metrics = Metrics::default();
+        metrics.record_request("tools/call");
+        metrics.record_tool_call("add");
+        metrics.record_error();
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE mcp_requests_total counter\nmcp_requests_total 1\n"));
+        assert!(text.contains("mcp_method_calls_total{method=\"tools/call\"} 1\n"));
+        assert!(text.contains("mcp_tool_calls_total{tool=\"add\"} 1\n"));
+        assert!(text.contains("mcp_errors_total 1\n"));
+    }
+
+    #[test]
+    fn test_histogram_buckets_are_cumulative() {
+        let histogram = Histogram::default();
+        histogram.observe(Duration::from_micros(500));
+        histogram.observe(Duration::from_millis(20));
+        histogram.observe(Duration::from_secs(10));
+
+        let mut out = String::new();
+        histogram.render(&mut out, "wait_seconds", "Wait.");
+        assert!(out.contains("wait_seconds_bucket{le=\"0.001\"} 1\n"));
+        assert!(out.contains("wait_seconds_bucket{le=\"0.025\"} 2\n"));
+        assert!(out.contains("wait_seconds_bucket{le=\"5\"} 2\n"));
+        assert!(out.contains("wait_seconds_bucket{le=\"+Inf\"} 3\n"));
+        assert!(out.contains("wait_seconds_count 3\n"));
+    }
+
+    #[test]
+    fn test_tool_durations_are_labeled_histograms() {
+        let metrics = Metrics::default();
+        metrics.observe_tool_duration("add", Duration::from_micros(200));
+        metrics.observe_tool_duration("add", Duration::from_millis(50));
+        metrics.observe_tool_duration("sqrt", Duration::from_millis(2));
+
+        let text = metrics.render();
+        assert!(text.contains("# TYPE mcp_tool_duration_seconds histogram\n"));
+        assert!(text.contains("mcp_tool_duration_seconds_bucket{tool=\"add\",le=\"0.001\"} 1\n"));
+        assert!(text.contains("mcp_tool_duration_seconds_bucket{tool=\"add\",le=\"0.1\"} 2\n"));
+        assert!(text.contains("mcp_tool_duration_seconds_bucket{tool=\"sqrt\",le=\"0.005\"} 1\n"));
+        assert!(text.contains("mcp_tool_duration_seconds_count{tool=\"add\"} 2\n"));
+        assert!(text.contains("mcp_tool_duration_seconds_sum{tool=\"add\"} 0.0502\n"));
+    }
+
+    #[test]
+    fn test_queue_guard_tracks_depth() {
+        let metrics = Metrics::default();
+        let first = metrics.enter_queue();
+        let second = metrics.enter_queue();
+        assert_eq!(metrics.queue_depth(), 2);
+        drop(first);
+        drop(second);
+        assert_eq!(metrics.queue_depth(), 0);
+    }
+
+    #[test]
+    fn test_escape_label() {
+        assert_eq!(escape_label("a\"b\\c"), "a\\\"b\\\\c");
+    }
+}
@@ -0,0 +1,232 @@
+use serde::{de, Deserializer};
+use serde_json::{json, Value};
+use std::{
+    fmt,
+    str::FromStr,
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+pub const DEFAULT_MAX_NUMBER_STRING_LEN: usize = 64;
+
+// Serde's `deserialize_with` hooks can't take arguments, so the limit is
+// process-wide and set once from `Config` at startup.
+static MAX_NUMBER_STRING_LEN: AtomicUsize = AtomicUsize::new(DEFAULT_MAX_NUMBER_STRING_LEN);
+
+pub fn set_max_number_string_len(len: usize) {
+    MAX_NUMBER_STRING_LEN.store(len, Ordering::Relaxed);
+}
+
+// Accepts a JSON number or a numeric string such as "5" or "-2.5e3", since
+// LLM clients often quote numbers. Overlong strings are rejected before
+// parsing so a huge digit string can't tie up a worker.
+pub fn deserialize_number<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    struct NumberVisitor;
+
+    impl de::Visitor<'_> for NumberVisitor {
+        type Value = f64;
+
+        fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            f.write_str("a number or a numeric string")
+        }
+
+        fn visit_f64<E: de::Error>(self, v: f64) -> Result<f64, E> {
+            Ok(v)
+        }
+
+        fn visit_i64<E: de::Error>(self, v: i64) -> Result<f64, E> {
+            Ok(v as f64)
+        }
+
+        fn visit_u64<E: de::Error>(self, v: u64) -> Result<f64, E> {
+            Ok(v as f64)
+        }
+
+        fn visit_str<E: de::Error>(self, v: &str) -> Result<f64, E> {
+            parse_number_str(v).map_err(E::custom)
+        }
+    }
+
+    deserializer.deserialize_any(NumberVisitor)
+}
+
+pub fn parse_number_str(s: &str) -> Result<f64, String> {
+    let s = s.trim();
+    let max_len = MAX_NUMBER_STRING_LEN.load(Ordering::Relaxed);
+    if s.len() > max_len {
+        return Err(format!("number string too long (max {} characters)", max_len));
+    }
+    // f64::from_str also accepts "inf" and "NaN", which aren't usable inputs.
+    match s.parse::<f64>() {
+        Ok(n) if n.is_finite() => Ok(n),
+        _ => Err(format!("invalid number string: {:?}", s)),
+    }
+}
+
+// What `divide` and `modulo` answer when the divisor is zero.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum DivZeroPolicy {
+    // A -32602 error, as for any other invalid input.
+    #[default]
+    Error,
+    // A successful result whose value is `null`.
+    Null,
+    // The IEEE 754 result ("Infinity", "-Infinity" or "NaN") as a string,
+    // since JSON numbers can't represent it.
+    Infinity,
+}
+
+impl FromStr for DivZeroPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "error" => Ok(DivZeroPolicy::Error),
+            "null" => Ok(DivZeroPolicy::Null),
+            "infinity" => Ok(DivZeroPolicy::Infinity),
+            other => Err(format!("unknown divide-by-zero policy {:?}", other)),
+        }
+    }
+}
+
+// JavaScript's spelling of a non-finite number, which most JSON consumers
+// parse back.
+pub fn non_finite_str(x: f64) -> &'static str {
+    if x.is_nan() {
+        "NaN"
+    } else if x > 0.0 {
+        "Infinity"
+    } else {
+        "-Infinity"
+    }
+}
+
+// Renders `x` rounded to `digits` significant digits, without trailing
+// zeros, so 0.1 + 0.2 reads as 0.3 rather than 0.30000000000000004.
+// `None` keeps Rust's shortest round-trip formatting.
+pub fn format_significant(x: f64, digits: Option<usize>) -> String {
+    match digits {
+        Some(digits) if digits > 0 && x.is_finite() && x != 0.0 => {
+            // Scientific notation rounds to significant digits; parsing it
+            // back lets Display pick the plain form.
+            let rounded: f64 = format!("{:.*e}", digits - 1, x).parse().unwrap_or(x);
+            rounded.to_string()
+        }
+        _ => x.to_string(),
+    }
+}
+
+// Renders `x` rounded to `places` decimal places, without trailing zeros.
+pub fn format_decimals(x: f64, places: usize) -> String {
+    let rounded: f64 = format!("{:.*}", places, x).parse().unwrap_or(x);
+    // Rounding can produce -0, e.g. -0.001 to two places.
+    if rounded == 0.0 {
+        return "0".to_string();
+    }
+    rounded.to_string()
+}
+
+// Copy of `value` with numeric strings turned into numbers wherever `schema`
+// expects a number, so schema validation agrees with `deserialize_number`.
+// Length limits are left to the deserializer, which reports them clearly.
+pub fn coerce_numeric_strings(schema: &Value, value: &Value) -> Value {
+    match (schema.get("type").and_then(Value::as_str), value) {
+        (Some("number" | "integer"), Value::String(s)) => match s.trim().parse::<f64>() {
+            Ok(n) if n.is_finite() => json!(n),
+            _ => value.clone(),
+        },
+        (Some("object"), Value::Object(fields)) => {
+            let properties = schema.get("properties");
+            Value::Object(
+                fields
+                    .iter()
+                    .map(|(key, field)| {
+                        let field = match properties.and_then(|p| p.get(key)) {
+                            Some(field_schema) => coerce_numeric_strings(field_schema, field),
+                            None => field.clone(),
+                        };
+                        (key.clone(), field)
+                    })
+                    .collect(),
+            )
+        }
+        (Some("array"), Value::Array(items)) => match schema.get("items") {
+            Some(item_schema) => Value::Array(
+                items
+                    .iter()
+                    .map(|item| coerce_numeric_strings(item_schema, item))
+                    .collect(),
+            ),
+            None => value.clone(),
+        },
+        _ => value.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_number_str() {
+        assert_eq!(parse_number_str("5"), Ok(5.0));
+        assert_eq!(parse_number_str(" -2.5e3 "), Ok(-2500.0));
+        assert!(parse_number_str("abc").is_err());
+        assert!(parse_number_str("NaN").is_err());
+        assert!(parse_number_str("inf").is_err());
+    }
+
+    #[test]
+    fn test_overlong_number_string_is_rejected() {
+        let digits = "9".repeat(DEFAULT_MAX_NUMBER_STRING_LEN + 1);
+        let error = parse_number_str(&digits).unwrap_err();
+        assert!(error.starts_with("number string too long"));
+    }
+
+    #[test]
+    fn test_div_zero_policy() {
+        assert_eq!("NULL".parse(), Ok(DivZeroPolicy::Null));
+        assert!("zero".parse::<DivZeroPolicy>().is_err());
+        assert_eq!(non_finite_str(-1.0 / 0.0), "-Infinity");
+        assert_eq!(non_finite_str(0.0 % 0.0), "NaN");
+    }
+
+    #[test]
+    fn test_format_significant() {
+        assert_eq!(format_significant(0.1 + 0.2, Some(10)), "0.3");
+        assert_eq!(format_significant(0.1 + 0.2, None), "0.30000000000000004");
+        assert_eq!(format_significant(2f64.sqrt(), Some(3)), "1.41");
+        assert_eq!(format_significant(123456.0, Some(2)), "120000");
+        assert_eq!(format_significant(-0.000123456, Some(3)), "-0.000123");
+    }
+
+    #[test]
+    fn test_format_decimals() {
+        assert_eq!(format_decimals(2f64.sqrt(), 2), "1.41");
+        assert_eq!(format_decimals(8.0, 2), "8");
+        assert_eq!(format_decimals(-0.001, 2), "0");
+        assert_eq!(format_decimals(1234.5678, 0), "1235");
+    }
+
+    #[test]
+    fn test_coerce_numeric_strings() {
+        let schema = json!({
+            "type": "object",
+            "properties": {
+                "a": { "type": "number" },
+                "values": { "type": "array", "items": { "type": "number" } },
+                "expression": { "type": "string" }
+            }
+        });
+        let coerced = coerce_numeric_strings(
+            &schema,
+            &json!({ "a": " 5 ", "values": ["1", 2, "x"], "expression": "3", "extra": "4" }),
+        );
+        assert_eq!(
+            coerced,
+            json!({ "a": 5.0, "values": [1.0, 2, "x"], "expression": "3", "extra": "4" })
+        );
+    }
+}
@@ -0,0 +1,116 @@
+use crate::metrics::Metrics;
+use std::{sync::Arc, time::Instant};
+use tokio::{sync::Semaphore, task::JoinError};
+
+// Bounded wrapper around `spawn_blocking` so CPU-bound tool calls can't
+// grow tokio's blocking thread pool without limit under load. Calls beyond
+// the pool size wait for a permit instead of spawning another thread.
+#[derive(Debug, Clone)]
+pub struct BlockingPool {
+    permits: Arc<Semaphore>,
+    size: usize,
+    metrics: Arc<Metrics>,
+}
+
+impl BlockingPool {
+    pub fn new(size: usize, metrics: Arc<Metrics>) -> Self {
+        let size = size.max(1);
+        Self {
+            permits: Arc::new(Semaphore::new(size)),
+            size,
+            metrics,
+        }
+    }
+
+    pub fn size(&self) -> usize {
+        self.size
+    }
+
+    pub async fn run<F, R>(&self, f: F) -> Result<R, JoinError>
+    where
+        F: FnOnce() -> R + Send + 'static,
+        R: Send + 'static,
+    {
+        let queued_at = Instant::now();
+        let permit = {
+            let _queued = self.metrics.enter_queue();
+            Arc::clone(&self.permits)
+                .acquire_owned()
+                .await
+                .expect("blocking pool semaphore is never closed")
+        };
+        self.metrics.observe_queue_wait(queued_at.elapsed());
+
+        // The permit moves into the closure so it is held until the work
+        // actually finishes, even if the awaiting request is dropped.
+        tokio::task::spawn_blocking(move || {
+            let _permit = permit;
+            f()
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::future::join_all;
+    use std::{
+        sync::atomic::{AtomicUsize, Ordering},
+        thread,
+        time::Duration,
+    };
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_saturated_pool_queues_extra_calls() {
+        let pool = BlockingPool::new(2, Arc::default());
+        let running = Arc::new(AtomicUsize::new(0));
+        let peak = Arc::new(AtomicUsize::new(0));
+
+        let calls = (0..6).map(|_| {
+            let pool = pool.clone();
+            let running = Arc::clone(&running);
+            let peak = Arc::clone(&peak);
+            tokio::spawn(async move {
+                pool.run(move || {
+                    let now = running.fetch_add(1, Ordering::SeqCst) + 1;
+                    peak.fetch_max(now, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(50));
+                    running.fetch_sub(1, Ordering::SeqCst);
+                })
+                .await
+            })
+        });
+
+        for result in join_all(calls).await {
+            result.unwrap().unwrap();
+        }
+        assert_eq!(peak.load(Ordering::SeqCst), pool.size());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn test_queue_depth_rises_under_load() {
+        let metrics = Arc::new(Metrics::default());
+        let pool = BlockingPool::new(1, Arc::clone(&metrics));
+
+        let calls: Vec<_> = (0..4)
+            .map(|_| {
+                let pool = pool.clone();
+                tokio::spawn(async move { pool.run(|| thread::sleep(Duration::from_millis(50))).await })
+            })
+            .collect();
+
+        let mut peak_depth = 0;
+        while !calls.iter().all(|call| call.is_finished()) {
+            peak_depth = peak_depth.max(metrics.queue_depth());
+            tokio::time::sleep(Duration::from_millis(5)).await;
+        }
+        for call in calls {
+            call.await.unwrap().unwrap();
+        }
+
+        assert!(peak_depth > 0);
+        assert_eq!(metrics.queue_depth(), 0);
+        assert!(metrics.render().contains("mcp_request_queue_wait_seconds_count 4\n"));
+    }
+}
@@ -0,0 +1,130 @@
+use serde_json::{json, Value};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+// Appends every `/mcp` exchange to `MCP_RECORD_PATH` as one JSON line of
+// `{timestamp, request, response}`, for `--replay` to send again later.
+// Notifications are recorded with a null response so a replayed session
+// goes through the same steps.
+pub struct Recorder {
+    file: Mutex<File>,
+}
+
+impl Recorder {
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { file: Mutex::new(file) })
+    }
+
+    pub fn record(&self, request: &Value, response: Option<&Value>) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        let mut line = json!({
+            "timestamp": timestamp,
+            "request": request,
+            "response": response
+        })
+        .to_string();
+        line.push('\n');
+        // One write per line keeps concurrent exchanges from interleaving.
+        let mut file = self.file.lock().unwrap();
+        if let Err(e) = file.write_all(line.as_bytes()) {
+            tracing::warn!("Could not write to recording file: {}", e);
+        }
+    }
+}
+
+// Sends each recorded request to `url` in order, carrying the session id the
+// server hands out, and prints a line for every response that differs from
+// the recorded one. Returns the number of differing responses.
+pub async fn replay(path: &Path, url: &str) -> Result<usize, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read recording {}: {}", path.display(), e))?;
+    let client = reqwest::Client::new();
+    let mut session_id: Option<String> = None;
+    let mut mismatches = 0;
+
+    for (index, line) in text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty()) {
+        let entry: Value =
+            serde_json::from_str(line).map_err(|e| format!("{}:{}: invalid entry: {}", path.display(), index + 1, e))?;
+        let mut request = client.post(url).json(&entry["request"]);
+        if let Some(session_id) = &session_id {
+            request = request.header("mcp-session-id", session_id);
+        }
+        let response = request.send().await.map_err(|e| format!("cannot reach {}: {}", url, e))?;
+        if let Some(id) = response.headers().get("mcp-session-id").and_then(|v| v.to_str().ok()) {
+            session_id = Some(id.to_string());
+        }
+        if entry["response"].is_null() {
+            continue;
+        }
+
+        let actual: Value = response
+            .json()
+            .await
+            .map_err(|e| format!("line {}: response is not JSON: {}", index + 1, e))?;
+        let mut differences = Vec::new();
+        diff("", &entry["response"], &actual, &mut differences);
+        if !differences.is_empty() {
+            mismatches += 1;
+            println!("line {} ({}):", index + 1, entry["request"]["method"]);
+            for difference in differences {
+                println!("  {}", difference);
+            }
+        }
+    }
+    Ok(mismatches)
+}
+
+// Collects one "path: expected ..., got ..." line per leaf that differs.
+fn diff(path: &str, expected: &Value, actual: &Value, differences: &mut Vec<String>) {
+    match (expected, actual) {
+        (Value::Object(expected), Value::Object(actual)) => {
+            for (key, value) in expected {
+                diff(&format!("{}/{}", path, key), value, actual.get(key).unwrap_or(&Value::Null), differences);
+            }
+            for (key, value) in actual.iter().filter(|(key, _)| !expected.contains_key(*key)) {
+                diff(&format!("{}/{}", path, key), &Value::Null, value, differences);
+            }
+        }
+        (Value::Array(expected), Value::Array(actual)) if expected.len() == actual.len() => {
+            for (index, (expected, actual)) in expected.iter().zip(actual).enumerate() {
+                diff(&format!("{}/{}", path, index), expected, actual, differences);
+            }
+        }
+        _ if expected != actual => {
+            let path = if path.is_empty() { "/" } else { path };
+            differences.push(format!("{}: expected {}, got {}", path, expected, actual));
+        }
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_diff_reports_changed_paths() {
+        let mut differences = Vec::new();
+        diff(
+            "",
+            &json!({ "id": 1, "result": { "content": [{ "text": "2 + 3 = 5" }] } }),
+            &json!({ "id": 1, "result": { "content": [{ "text": "2 + 3 = 6" }], "extra": true } }),
+            &mut differences,
+        );
+        assert_eq!(
+            differences,
+            vec![
+                "/result/content/0/text: expected \"2 + 3 = 5\", got \"2 + 3 = 6\"",
+                "/result/extra: expected null, got true",
+            ]
+        );
+    }
+}
@@ -0,0 +1,168 @@
+use serde_json::{json, Value};
+use std::{
+    fs::{self, File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
+use tracing_appender::non_blocking::{NonBlocking, WorkerGuard};
+
+pub const DEFAULT_MAX_BYTES: u64 = 10 * 1024 * 1024;
+
+// Appends to `path` until the next write would take it past `max_bytes`,
+// then moves it to `<path>.1` (replacing the previous one) and starts over.
+// tracing-appender only rotates by time, so this is the file it writes to.
+struct RotatingFile {
+    path: PathBuf,
+    max_bytes: u64,
+    file: File,
+    written: u64,
+}
+
+impl RotatingFile {
+    fn open(path: &Path, max_bytes: u64) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        let written = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_path_buf(),
+            max_bytes,
+            file,
+            written,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        let mut rotated = self.path.clone().into_os_string();
+        rotated.push(".1");
+        fs::rename(&self.path, rotated)?;
+        self.file = OpenOptions::new().create(true).append(true).open(&self.path)?;
+        self.written = 0;
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.written > 0 && self.written + buf.len() as u64 > self.max_bytes {
+            self.rotate()?;
+        }
+        let written = self.file.write(buf)?;
+        self.written += written as u64;
+        Ok(written)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
+
+// One JSON line per JSON-RPC request in `MCP_LOG_FILE`, written by a
+// background thread so a slow disk doesn't hold up requests. Lines still
+// queued are flushed when the log is dropped.
+pub struct RequestLog {
+    writer: NonBlocking,
+    redact_arguments: bool,
+    _guard: WorkerGuard,
+}
+
+impl RequestLog {
+    pub fn open(path: &Path, max_bytes: u64, redact_arguments: bool) -> io::Result<Self> {
+        let (writer, guard) = tracing_appender::non_blocking(RotatingFile::open(path, max_bytes)?);
+        Ok(Self {
+            writer,
+            redact_arguments,
+            _guard: guard,
+        })
+    }
+
+    pub fn record(&self, method: &str, tool: Option<&str>, arguments: Option<&Value>, is_error: bool) {
+        let timestamp = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|elapsed| elapsed.as_millis() as u64)
+            .unwrap_or_default();
+        let mut line = json!({
+            "timestamp": timestamp,
+            "method": method,
+            "outcome": if is_error { "error" } else { "ok" }
+        });
+        if let Some(tool) = tool {
+            line["tool"] = json!(tool);
+        }
+        if let Some(arguments) = arguments {
+            line["arguments"] = if self.redact_arguments {
+                redact(arguments)
+            } else {
+                arguments.clone()
+            };
+        }
+
+        let mut line = line.to_string();
+        line.push('\n');
+        // NonBlocking only queues the line; it fails only once the worker
+        // has gone, which leaves nothing useful to do with the error.
+        let _ = self.writer.clone().write_all(line.as_bytes());
+    }
+}
+
+// Keeps argument names, which say what was asked, and hides their values.
+fn redact(arguments: &Value) -> Value {
+    match arguments {
+        Value::Object(fields) => fields.keys().map(|key| (key.clone(), json!("[redacted]"))).collect(),
+        _ => json!("[redacted]"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("mcp-{}-{}.jsonl", name, uuid::Uuid::new_v4()))
+    }
+
+    fn read_lines(path: &Path) -> Vec<Value> {
+        fs::read_to_string(path)
+            .unwrap()
+            .lines()
+            .map(|line| serde_json::from_str(line).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_writes_one_json_line_per_request() {
+        let path = temp_path("requests");
+        let log = RequestLog::open(&path, DEFAULT_MAX_BYTES, false).unwrap();
+        log.record("initialize", None, None, false);
+        log.record("tools/call", Some("sqrt"), Some(&json!({ "number": -1 })), true);
+        drop(log);
+
+        let lines = read_lines(&path);
+        fs::remove_file(&path).unwrap();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[0]["method"], "initialize");
+        assert_eq!(lines[0]["outcome"], "ok");
+        assert!(lines[0].get("tool").is_none());
+        assert_eq!(lines[1]["tool"], "sqrt");
+        assert_eq!(lines[1]["arguments"], json!({ "number": -1 }));
+        assert_eq!(lines[1]["outcome"], "error");
+    }
+
+    #[test]
+    fn test_redacts_arguments_and_rotates_by_size() {
+        let path = temp_path("rotating");
+        let log = RequestLog::open(&path, 300, true).unwrap();
+        for _ in 0..3 {
+            log.record("tools/call", Some("add"), Some(&json!({ "a": 1, "b": 2 })), false);
+        }
+        drop(log);
+
+        let mut rotated = path.clone().into_os_string();
+        rotated.push(".1");
+        let (current, older) = (read_lines(&path), read_lines(Path::new(&rotated)));
+        fs::remove_file(&path).unwrap();
+        fs::remove_file(&rotated).unwrap();
+        assert_eq!(current.len() + older.len(), 3);
+        assert!(!current.is_empty() && !older.is_empty());
+        assert_eq!(current[0]["arguments"], json!({ "a": "[redacted]", "b": "[redacted]" }));
+    }
+}
@@ -0,0 +1,78 @@
+use std::{
+    collections::HashMap,
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+pub const RESULT_URI_PREFIX: &str = "calc://results/";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StoredResource {
+    pub mime_type: String,
+    pub text: String,
+}
+
+// Temporary resources holding tool results too large to inline, readable via
+// `resources/read` until they expire.
+#[derive(Debug)]
+pub struct ResourceStore {
+    ttl: Duration,
+    entries: Mutex<HashMap<String, (StoredResource, Instant)>>,
+}
+
+impl ResourceStore {
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    // Stores the resource and returns its URI.
+    pub fn insert(&self, resource: StoredResource) -> String {
+        let uri = format!("{}{}", RESULT_URI_PREFIX, uuid::Uuid::new_v4());
+        let mut entries = self.entries.lock().unwrap();
+        Self::purge_expired(&mut entries);
+        entries.insert(uri.clone(), (resource, Instant::now() + self.ttl));
+        uri
+    }
+
+    pub fn get(&self, uri: &str) -> Option<StoredResource> {
+        let mut entries = self.entries.lock().unwrap();
+        Self::purge_expired(&mut entries);
+        entries.get(uri).map(|(resource, _)| resource.clone())
+    }
+
+    fn purge_expired(entries: &mut HashMap<String, (StoredResource, Instant)>) {
+        let now = Instant::now();
+        entries.retain(|_, (_, expires_at)| *expires_at > now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn resource() -> StoredResource {
+        StoredResource {
+            mime_type: "text/plain".to_string(),
+            text: "42".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_insert_and_get() {
+        let store = ResourceStore::new(Duration::from_secs(60));
+        let uri = store.insert(resource());
+        assert!(uri.starts_with(RESULT_URI_PREFIX));
+        assert_eq!(store.get(&uri), Some(resource()));
+        assert_eq!(store.get("calc://results/missing"), None);
+    }
+
+    #[test]
+    fn test_expired_resources_are_gone() {
+        let store = ResourceStore::new(Duration::ZERO);
+        let uri = store.insert(resource());
+        assert_eq!(store.get(&uri), None);
+    }
+}
@@ -0,0 +1,262 @@
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::{
+    collections::{HashMap, HashSet},
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+// Header carrying the session id on the streamable HTTP transport.
+pub const SESSION_HEADER: &str = "mcp-session-id";
+
+pub const DEFAULT_IDLE_TTL: Duration = Duration::from_secs(30 * 60);
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Session {
+    pub id: String,
+    pub client_info: Option<Value>,
+    // Set once the client sends `notifications/initialized`.
+    pub initialized: bool,
+    // Variables assigned by the `evaluate` tool.
+    pub vars: HashMap<String, Value>,
+    // Result content the client declared it can render (`capabilities.contentTypes`
+    // in `initialize`), e.g. `["text"]`; `None` means everything.
+    pub content_types: Option<Vec<String>>,
+    // Decimal places for result text, from `_meta.displayPrecision` in `initialize`.
+    pub display_precision: Option<usize>,
+    // Protocol version agreed in `initialize`.
+    pub protocol_version: Option<String>,
+    // Milliseconds since the Unix epoch.
+    pub created_at: u64,
+    pub last_active_at: u64,
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|elapsed| elapsed.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+impl Session {
+    pub fn new(id: String) -> Self {
+        let now = now_millis();
+        Self {
+            id,
+            client_info: None,
+            initialized: false,
+            vars: HashMap::new(),
+            content_types: None,
+            display_precision: None,
+            protocol_version: None,
+            created_at: now,
+            last_active_at: now,
+        }
+    }
+
+    pub fn accepts_structured_content(&self) -> bool {
+        self.content_types
+            .as_ref()
+            .is_none_or(|types| types.iter().any(|t| t == "structured"))
+    }
+}
+
+// Where session state lives. The in-memory default is lost on restart; a
+// shared backend (e.g. Redis) lets several instances serve one session.
+#[async_trait]
+pub trait SessionStore: Send + Sync {
+    async fn get(&self, id: &str) -> Option<Session>;
+    async fn set(&self, session: Session);
+    async fn remove(&self, id: &str) -> Option<Session>;
+    async fn list(&self) -> Vec<Session>;
+}
+
+#[derive(Debug, Default)]
+pub struct InMemorySessionStore {
+    sessions: Mutex<HashMap<String, Session>>,
+}
+
+#[async_trait]
+impl SessionStore for InMemorySessionStore {
+    async fn get(&self, id: &str) -> Option<Session> {
+        self.sessions.lock().unwrap().get(id).cloned()
+    }
+
+    async fn set(&self, session: Session) {
+        self.sessions.lock().unwrap().insert(session.id.clone(), session);
+    }
+
+    async fn remove(&self, id: &str) -> Option<Session> {
+        self.sessions.lock().unwrap().remove(id)
+    }
+
+    async fn list(&self) -> Vec<Session> {
+        self.sessions.lock().unwrap().values().cloned().collect()
+    }
+}
+
+// Tracks per-session state for connected clients. Sessions started over
+// plain `/mcp` have no connection to end them, so any session idle for
+// longer than `idle_ttl` is dropped, except those of open SSE and WebSocket
+// connections, which are removed when the connection closes.
+pub struct SessionManager {
+    store: Arc<dyn SessionStore>,
+    last_client: Mutex<Option<Value>>,
+    idle_ttl: Duration,
+    connected: Mutex<HashSet<String>>,
+    // When idle sessions were last looked for, in Unix milliseconds.
+    last_sweep: Mutex<u64>,
+}
+
+impl Default for SessionManager {
+    fn default() -> Self {
+        Self::new(Arc::new(InMemorySessionStore::default()), DEFAULT_IDLE_TTL)
+    }
+}
+
+impl SessionManager {
+    pub fn new(store: Arc<dyn SessionStore>, idle_ttl: Duration) -> Self {
+        Self {
+            store,
+            last_client: Mutex::new(None),
+            idle_ttl,
+            connected: Mutex::new(HashSet::new()),
+            last_sweep: Mutex::new(now_millis()),
+        }
+    }
+
+    // Starts a session, first dropping idle ones. Creating sessions is what
+    // grows the store, so sweeping here keeps it bounded without a
+    // background task.
+    pub async fn create(&self) -> String {
+        self.sweep(now_millis()).await;
+        let id = uuid::Uuid::new_v4().to_string();
+        self.store.set(Session::new(id.clone())).await;
+        id
+    }
+
+    // Starts a session for a connection that will `remove` it on close; it
+    // is never dropped for being idle.
+    pub async fn connect(&self) -> String {
+        let id = self.create().await;
+        self.connected.lock().unwrap().insert(id.clone());
+        id
+    }
+
+    // Removes sessions idle for longer than `idle_ttl` at `now`. Listing the
+    // store is linear, so this runs at most once a minute (or once per TTL,
+    // if that is shorter).
+    async fn sweep(&self, now: u64) {
+        let interval = self.idle_ttl.min(Duration::from_secs(60)).as_millis() as u64;
+        {
+            let mut last_sweep = self.last_sweep.lock().unwrap();
+            if now.saturating_sub(*last_sweep) < interval {
+                return;
+            }
+            *last_sweep = now;
+        }
+        let idle_ttl = self.idle_ttl.as_millis() as u64;
+        for session in self.store.list().await {
+            let idle = now.saturating_sub(session.last_active_at) > idle_ttl;
+            if idle && !self.connected.lock().unwrap().contains(&session.id) {
+                self.store.remove(&session.id).await;
+            }
+        }
+    }
+
+    pub async fn get(&self, id: &str) -> Option<Session> {
+        self.store.get(id).await
+    }
+
+    // Writes back a session after a request has updated it, marking it as
+    // active now.
+    pub async fn save(&self, mut session: Session) {
+        session.last_active_at = now_millis();
+        self.store.set(session).await;
+    }
+
+    pub async fn remove(&self, id: &str) -> Option<Session> {
+        self.connected.lock().unwrap().remove(id);
+        self.store.remove(id).await
+    }
+
+    // Every stored session, oldest first.
+    pub async fn list(&self) -> Vec<Session> {
+        let mut sessions = self.store.list().await;
+        sessions.sort_by(|a, b| a.created_at.cmp(&b.created_at).then_with(|| a.id.cmp(&b.id)));
+        sessions
+    }
+
+    // Readiness probe: opens and drops a throwaway session to confirm the
+    // session bookkeeping the SSE transport relies on is working.
+    pub async fn self_check(&self) -> Result<(), String> {
+        let id = self.create().await;
+        if self.get(&id).await.is_none() {
+            return Err("created session was not found".to_string());
+        }
+        if self.remove(&id).await.is_none() || self.get(&id).await.is_some() {
+            return Err("session could not be removed".to_string());
+        }
+        Ok(())
+    }
+
+    // Remembers the `clientInfo` most recently sent with `initialize`, from
+    // any session or none.
+    pub fn record_client(&self, client_info: Value) {
+        *self.last_client.lock().unwrap() = Some(client_info);
+    }
+
+    pub fn last_client(&self) -> Option<Value> {
+        self.last_client.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn test_sessions_round_trip_through_store() {
+        let store = Arc::new(InMemorySessionStore::default());
+        let sessions = SessionManager::new(Arc::clone(&store) as Arc<dyn SessionStore>, DEFAULT_IDLE_TTL);
+        let first = sessions.create().await;
+        let second = sessions.create().await;
+        assert_ne!(first, second);
+
+        let mut session = sessions.get(&first).await.unwrap();
+        session.client_info = Some(json!({ "name": "cursor" }));
+        sessions.save(session).await;
+
+        assert_eq!(store.get(&first).await.unwrap().client_info, Some(json!({ "name": "cursor" })));
+        assert_eq!(store.get(&second).await.unwrap().client_info, None);
+        assert_eq!(sessions.list().await.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_self_check_leaves_no_session_behind() {
+        let store = Arc::new(InMemorySessionStore::default());
+        let sessions = SessionManager::new(Arc::clone(&store) as Arc<dyn SessionStore>, DEFAULT_IDLE_TTL);
+        assert_eq!(sessions.self_check().await, Ok(()));
+        assert!(store.sessions.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_idle_sessions_are_dropped() {
+        let sessions = SessionManager::new(Arc::new(InMemorySessionStore::default()), Duration::from_secs(60));
+        let idle = sessions.create().await;
+        let active = sessions.create().await;
+        let connected = sessions.connect().await;
+
+        let later = now_millis() + 61_000;
+        let mut session = sessions.get(&active).await.unwrap();
+        session.last_active_at = later;
+        sessions.store.set(session).await;
+        sessions.sweep(later).await;
+
+        assert!(sessions.get(&idle).await.is_none());
+        assert!(sessions.get(&active).await.is_some());
+        assert!(sessions.get(&connected).await.is_some());
+    }
+}
@@ -0,0 +1,62 @@
+use opentelemetry_otlp::{SpanExporter, WithExportConfig};
+use opentelemetry_sdk::{trace::SdkTracerProvider, Resource};
+use tracing::{field, Span};
+
+pub const SERVICE_NAME: &str = "mcp-sse-rust";
+
+// Batches spans to the OTLP/HTTP collector at `endpoint`, the value of
+// `OTEL_EXPORTER_OTLP_ENDPOINT`; traces go to its `/v1/traces` path as the
+// OpenTelemetry spec describes for that variable.
+pub fn tracer_provider(endpoint: &str) -> Result<SdkTracerProvider, String> {
+    let exporter = SpanExporter::builder()
+        .with_http()
+        .with_endpoint(format!("{}/v1/traces", endpoint.trim_end_matches('/')))
+        .build()
+        .map_err(|e| format!("cannot export traces to {}: {}", endpoint, e))?;
+    Ok(SdkTracerProvider::builder()
+        .with_batch_exporter(exporter)
+        .with_resource(Resource::builder().with_service_name(SERVICE_NAME).build())
+        .build())
+}
+
+// The span a `/mcp` request is handled in. With an exporter configured it
+// becomes a trace span carrying the JSON-RPC method and, for `tools/call`,
+// the tool; without one it only scopes log lines.
+pub fn request_span(method: &str, tool: Option<&str>) -> Span {
+    let span = tracing::info_span!("mcp.request", rpc.method = %method, mcp.tool = field::Empty);
+    if let Some(tool) = tool {
+        span.record("mcp.tool", tool);
+    }
+    span
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentelemetry::{trace::TracerProvider as _, Value};
+    use opentelemetry_sdk::trace::InMemorySpanExporter;
+    use tracing_subscriber::layer::SubscriberExt;
+
+    #[test]
+    fn test_request_span_attributes_are_exported() {
+        let exporter = InMemorySpanExporter::default();
+        let provider = SdkTracerProvider::builder().with_simple_exporter(exporter.clone()).build();
+        let subscriber =
+            tracing_subscriber::registry().with(tracing_opentelemetry::layer().with_tracer(provider.tracer("test")));
+        tracing::subscriber::with_default(subscriber, || {
+            drop(request_span("tools/call", Some("add")));
+            drop(request_span("ping", None));
+        });
+
+        let spans = exporter.get_finished_spans().unwrap();
+        let attribute = |index: usize, key: &str| {
+            spans[index].attributes.iter().find(|kv| kv.key.as_str() == key).map(|kv| kv.value.clone())
+        };
+        assert_eq!(spans.len(), 2);
+        assert_eq!(spans[0].name, "mcp.request");
+        assert_eq!(attribute(0, "rpc.method"), Some(Value::from("tools/call")));
+        assert_eq!(attribute(0, "mcp.tool"), Some(Value::from("add")));
+        assert_eq!(attribute(1, "rpc.method"), Some(Value::from("ping")));
+        assert_eq!(attribute(1, "mcp.tool"), None);
+    }
+}
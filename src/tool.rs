@@ -0,0 +1,50 @@
+use crate::{context::ToolContext, JsonRpcError};
+use serde_json::{json, Value};
+
+// A tool implementation plugged in alongside the built-in calculator tools.
+pub trait ToolHandler: Send + Sync {
+    fn call(&self, ctx: &ToolContext, arguments: Value) -> Result<ToolOutput, JsonRpcError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ToolOutput {
+    // A single numeric answer, rendered as text plus `structuredContent.result`.
+    Number(f64),
+}
+
+impl ToolOutput {
+    // JSON has no NaN or infinity (serde_json would silently turn them into
+    // null), so a non-finite number is reported instead of sent.
+    pub fn into_result(self) -> Result<Value, String> {
+        match self {
+            ToolOutput::Number(n) if !n.is_finite() => Err(format!("non-finite number {}", n)),
+            ToolOutput::Number(n) => Ok(json!({
+                "content": [{
+                    "type": "text",
+                    "text": n.to_string()
+                }],
+                "structuredContent": {
+                    "result": n
+                }
+            })),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_number_output() {
+        let result = ToolOutput::Number(1.5).into_result().unwrap();
+        assert_eq!(result["content"][0]["text"], "1.5");
+        assert_eq!(result["structuredContent"]["result"], 1.5);
+    }
+
+    #[test]
+    fn test_non_finite_output_is_rejected() {
+        assert!(ToolOutput::Number(f64::NAN).into_result().is_err());
+        assert!(ToolOutput::Number(f64::INFINITY).into_result().is_err());
+    }
+}
@@ -0,0 +1,110 @@
+use crate::{
+    context::ToolContext,
+    error::McpError,
+    expr::{self, EvalError, Expr},
+    numeric,
+    tool::{ToolHandler, ToolOutput},
+    JsonRpcError, Tool,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::{fs, path::Path, sync::Arc};
+
+// One entry of the JSON array in `MCP_TOOLS_FILE`.
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase", deny_unknown_fields)]
+struct ToolDefinition {
+    name: String,
+    #[serde(default)]
+    description: String,
+    #[serde(default = "default_input_schema")]
+    input_schema: Value,
+    // Arithmetic in `evaluate` syntax over the call's arguments.
+    expression: String,
+    #[serde(default)]
+    tags: Vec<String>,
+}
+
+type DeclaredTools = Vec<(Tool, Arc<dyn ToolHandler>)>;
+
+fn default_input_schema() -> Value {
+    json!({ "type": "object" })
+}
+
+// A declared tool: evaluates its expression with each argument bound as a
+// variable of the same name.
+pub struct ExpressionTool {
+    expression: Expr,
+    max_steps: usize,
+}
+
+impl ToolHandler for ExpressionTool {
+    fn call(&self, _ctx: &ToolContext, arguments: Value) -> Result<ToolOutput, JsonRpcError> {
+        let vars = |name: &str| match arguments.get(name)? {
+            Value::Number(n) => n.as_f64(),
+            Value::String(s) => numeric::parse_number_str(s).ok(),
+            _ => None,
+        };
+        let result = self.expression.eval(&vars, self.max_steps).map_err(|e| match e {
+            EvalError::BudgetExceeded => McpError::InternalError("evaluation budget exceeded".to_string()),
+            EvalError::Invalid(message) => McpError::InvalidParams(message),
+        })?;
+        Ok(ToolOutput::Number(result))
+    }
+}
+
+// Reads the tool definitions in `path`, failing with a message naming the
+// file (and the tool, for a bad expression) so a typo stops startup.
+pub fn load(path: &Path, max_steps: usize, max_depth: usize) -> Result<DeclaredTools, String> {
+    let text = fs::read_to_string(path).map_err(|e| format!("cannot read tools file {}: {}", path.display(), e))?;
+    parse(&text, max_steps, max_depth).map_err(|e| format!("invalid tools file {}: {}", path.display(), e))
+}
+
+fn parse(text: &str, max_steps: usize, max_depth: usize) -> Result<DeclaredTools, String> {
+    let definitions: Vec<ToolDefinition> = serde_json::from_str(text).map_err(|e| e.to_string())?;
+    definitions
+        .into_iter()
+        .map(|definition| {
+            let expression = expr::parse(&definition.expression, max_depth)
+                .map_err(|e| format!("tool {:?}: invalid expression: {}", definition.name, e))?;
+            let tool = Tool {
+                name: definition.name,
+                description: definition.description,
+                input_schema: definition.input_schema,
+                annotations: None,
+                tags: definition.tags,
+            };
+            let handler: Arc<dyn ToolHandler> = Arc::new(ExpressionTool { expression, max_steps });
+            Ok((tool, handler))
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::context::SessionVars;
+
+    #[test]
+    fn test_declared_tool_evaluates_its_expression() {
+        let definitions = r#"[{"name": "hypot", "expression": "sqrt(a^2 + b^2)"}]"#;
+        let tools = parse(definitions, 1000, expr::DEFAULT_MAX_DEPTH).unwrap();
+        let (tool, handler) = &tools[0];
+        assert_eq!(tool.name, "hypot");
+
+        let ctx = ToolContext::new(None, None, SessionVars::default());
+        let output = handler.call(&ctx, json!({ "a": 3, "b": "4" })).unwrap();
+        assert_eq!(output, ToolOutput::Number(5.0));
+        assert_eq!(handler.call(&ctx, json!({ "a": 3 })).unwrap_err().code, -32602);
+    }
+
+    #[test]
+    fn test_malformed_definitions_are_reported() {
+        let error = parse(r#"[{"name": "broken"}]"#, 1000, expr::DEFAULT_MAX_DEPTH).err().unwrap();
+        assert!(error.contains("missing field `expression`"), "{}", error);
+
+        let definitions = r#"[{"name": "broken", "expression": "1 +"}]"#;
+        let error = parse(definitions, 1000, expr::DEFAULT_MAX_DEPTH).err().unwrap();
+        assert!(error.starts_with("tool \"broken\": invalid expression"), "{}", error);
+    }
+}